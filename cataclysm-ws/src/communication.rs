@@ -1,15 +1,59 @@
-use tokio::net::{TcpStream};
-use crate::{Error, FrameParseError, Message, Frame};
+use tokio::{net::{TcpStream, tcp::OwnedWriteHalf}, sync::{Notify, Mutex}};
+use std::sync::atomic::{AtomicBool, Ordering};
+use crate::{Error, Frame, FrameParseError, frame::RawFrame};
+use bytes::Buf;
 
 const READ_CHUNK_SIZE: usize = 4_096;
+const WRITE_CHUNK_SIZE: usize = 4_096;
+/// Default maximum size of a message, assembled from its fragments
+pub(crate) const MAX_MESSAGE_SIZE: usize = 64 * 1_024 * 1_024;
 
-// Helper function to write a message through the websockets write end
-pub async fn write_message<A: AsRef<TcpStream>>(stream: A, message: Message) -> Result<(), Error> {
-    let content: Vec<u8> = message.into();
+/// State of the closing handshake, shared by both halves of a split connection
+#[derive(Default)]
+pub(crate) struct CloseHandshake {
+    /// Set once a close frame has been sent to the peer
+    sent: AtomicBool,
+    /// Wakes up the writer when the peer's close frame arrives
+    received: Notify,
+    /// Indicates if the frames need a masking key (client side)
+    pub(crate) mask_frames: bool
+}
+
+impl CloseHandshake {
+    pub(crate) fn new(mask_frames: bool) -> CloseHandshake {
+        CloseHandshake {
+            mask_frames,
+            ..Default::default()
+        }
+    }
+
+    /// Marks the close frame as sent, returning true if it already was
+    pub(crate) fn mark_sent(&self) -> bool {
+        self.sent.swap(true, Ordering::SeqCst)
+    }
+
+    pub(crate) fn is_sent(&self) -> bool {
+        self.sent.load(Ordering::SeqCst)
+    }
+
+    /// Signals that the peer's close frame arrived
+    pub(crate) fn mark_received(&self) {
+        // A permit is stored if nobody is waiting yet, so the writer doesn't miss it
+        self.received.notify_one();
+    }
+
+    pub(crate) async fn wait_received(&self) {
+        self.received.notified().await
+    }
+}
+
+// Helper function to write a frame through the websockets write end
+pub async fn write_frame<A: AsRef<TcpStream>>(stream: A, frame: Frame) -> Result<(), Error> {
+    let content: Vec<u8> = frame.into();
     let ref_stream: &TcpStream = stream.as_ref();
     loop {
         // Wait for the socket to be writable
-        ref_stream.writable().await.map_err(Error::Io)?;
+        ref_stream.writable().await.map_err(Error::from_io)?;
 
         // Try to write data, this may still fail with `WouldBlock`
         // if the readiness event is a false positive.
@@ -20,61 +64,194 @@ pub async fn write_message<A: AsRef<TcpStream>>(stream: A, message: Message) ->
             Err(ref e) if e.kind() == tokio::io::ErrorKind::WouldBlock => {
                 continue;
             }
-            Err(e) => break Err(Error::Io(e))
+            Err(e) => break Err(Error::from_io(e))
         }
     }
 }
 
-/// Reads a frame from the incoming connection
-pub async fn read_frame<A: AsRef<TcpStream>>(stream: A) -> Result<Frame, Error> {
-    Frame::parse(read_bytes(stream).await?).map_err(Error::FrameParse)
+/// Writes already serialized frames through a write half, holding its lock until they are completely written
+pub(crate) async fn write_locked(write_stream: &Mutex<OwnedWriteHalf>, content: &[u8]) -> Result<(), Error> {
+    let mut chunks_iter = content.chunks(WRITE_CHUNK_SIZE);
+    #[cfg(feature = "full_log")]
+    log::trace!("writting {} chunks of maximum {} bytes each", chunks_iter.len(), WRITE_CHUNK_SIZE);
+    // We check the first chunk
+    let mut current_chunk = match chunks_iter.next() {
+        Some(v) => v,
+        None => return Ok(()) // Zero length response
+    };
+    // The lock is held for the whole frame, so concurrent writes don't interleave
+    let write_stream = write_stream.lock().await;
+    let stream: &TcpStream = write_stream.as_ref();
+    loop {
+        // Wait for the socket to be writable
+        if let Err(e) = stream.writable().await {
+            break Err(Error::from_io(e));
+        }
+
+        // Try to write data, this may still fail with `WouldBlock`
+        // if the readiness event is a false positive.
+        match stream.try_write(current_chunk) {
+            Ok(n) => {
+                if n != current_chunk.remaining() {
+                    // There are some bytes still to be written in this chunk
+                    #[cfg(feature = "full_log")]
+                    log::debug!("incomplete chunk, trying to serve remaining bytes ({}/{})", current_chunk.len(), WRITE_CHUNK_SIZE);
+                    current_chunk.advance(n);
+                    continue;
+                } else {
+                    current_chunk = match chunks_iter.next() {
+                        Some(v) => v,
+                        None => break Ok(())
+                    }
+                }
+            }
+            Err(ref e) if e.kind() == tokio::io::ErrorKind::WouldBlock => {
+                continue;
+            }
+            Err(e) => break Err(Error::from_io(e))
+        }
+    }
 }
 
-async fn read_bytes<A: AsRef<TcpStream>>(stream: A) -> Result<Vec<u8>, Error> {
-    let mut stream_bytes = Vec::with_capacity(READ_CHUNK_SIZE);
-    let ref_stream: &TcpStream = stream.as_ref();
+/// Message being assembled from fragments, kept across reads so control frames can be returned in between
+#[derive(Default)]
+struct Fragments {
+    /// Operation code of the first fragment, `None` if no fragmented message has started
+    op_code: Option<u8>,
+    masking_key: Option<[u8; 4]>,
+    payload: Vec<u8>,
+    /// Length of the payload already known to be valid utf-8, for text messages
+    valid_utf8: usize
+}
+
+impl Fragments {
+    /// Adds a data frame, returning the full message once the final fragment arrives
+    fn push(&mut self, raw: RawFrame) -> Result<Option<Frame>, FrameParseError> {
+        let is_final = raw.fin_rsv & 0x80 != 0;
+        match (self.op_code, raw.op_code) {
+            (None, Frame::OP_CODE_CONTINUATION) | (Some(_), Frame::OP_CODE_TEXT | Frame::OP_CODE_BINARY) => Err(FrameParseError::UnexpectedContinuation),
+            (None, Frame::OP_CODE_TEXT | Frame::OP_CODE_BINARY) if !is_final => {
+                self.op_code = Some(raw.op_code);
+                self.masking_key = raw.masking_key;
+                self.payload = raw.payload;
+                self.check_utf8(false).map(|_| None)
+            },
+            (Some(op_code), Frame::OP_CODE_CONTINUATION) => {
+                self.payload.extend(raw.payload);
+                self.check_utf8(is_final)?;
+                if !is_final {
+                    return Ok(None)
+                }
+                // The message is complete, so the next data frame starts a new one
+                let Fragments {masking_key, payload, ..} = std::mem::take(self);
+                Frame::from_raw(RawFrame {
+                    fin_rsv: Frame::FIN_RSV,
+                    op_code,
+                    masking_key,
+                    payload
+                }).map(Some)
+            },
+            _ => Frame::from_raw(raw).map(Some)
+        }
+    }
+
+    /// Validates the text received since the last check, so invalid utf-8 is detected without waiting for the final fragment
+    fn check_utf8(&mut self, is_final: bool) -> Result<(), FrameParseError> {
+        if self.op_code != Some(Frame::OP_CODE_TEXT) {
+            return Ok(())
+        }
+        match std::str::from_utf8(&self.payload[self.valid_utf8..]) {
+            Ok(_) => self.valid_utf8 = self.payload.len(),
+            // A code point split between two fragments, the rest of it comes in the next one
+            Err(e) if e.error_len().is_none() && !is_final => self.valid_utf8 += e.valid_up_to(),
+            Err(_) => {
+                let error = String::from_utf8(std::mem::take(&mut self.payload)).unwrap_err();
+                return Err(FrameParseError::InvalidUtf8(error))
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Bytes read from the connection that don't make a whole frame yet, along with the message being assembled from fragments
+pub(crate) struct ReadBuffer {
+    bytes: Vec<u8>,
+    fragments: Fragments,
+    /// Maximum size of a message, checked against the declared lengths before buffering the frames
+    pub(crate) max_message_size: usize
+}
+
+impl Default for ReadBuffer {
+    fn default() -> ReadBuffer {
+        ReadBuffer::from(Vec::new())
+    }
+}
+
+impl From<Vec<u8>> for ReadBuffer {
+    fn from(bytes: Vec<u8>) -> ReadBuffer {
+        ReadBuffer {
+            bytes,
+            fragments: Fragments::default(),
+            max_message_size: MAX_MESSAGE_SIZE
+        }
+    }
+}
 
-    // We need to compute this to receive a full message, no matter the length
-    let mut expected_length = None;
+/// Reads a frame from the incoming connection
+///
+/// Fragmented messages are assembled into a single frame, validating the utf-8 of text messages as the fragments arrive. Messages over the maximum size of the read buffer are rejected with [FrameParseError::TooLarge] as soon as their length is known. Control frames found in between the fragments are returned right away, and the fragments received so far stay in the read buffer for the next call, so they are not parsed nor validated again. The buffer also keeps the bytes that arrived after the frame, so the next call doesn't lose them
+pub async fn read_frame<A: AsRef<TcpStream>>(stream: A, read_buffer: &mut ReadBuffer) -> Result<Frame, Error> {
+    let ref_stream: &TcpStream = stream.as_ref();
+    let ReadBuffer {bytes: buffer, fragments, max_message_size} = read_buffer;
 
     loop {
+        // We check first if full frames are already available
+        while let Some((header_length, payload_length)) = Frame::header_lengths(buffer) {
+            // The declared length is checked before waiting for the payload, so a peer can't make us buffer without limit. Data frames add up to the fragments received so far
+            let assembled = if buffer[0] & 0x08 == 0 { fragments.payload.len() } else { 0 };
+            let length = (assembled as u64).saturating_add(payload_length);
+            if length > *max_message_size as u64 {
+                return Err(Error::FrameParse(FrameParseError::TooLarge{length, limit: *max_message_size}));
+            }
+            // Fits in memory, as it is below the maximum size
+            let frame_length = header_length + payload_length as usize;
+            if buffer.len() < frame_length {
+                break;
+            }
+            let raw = Frame::parse_raw(&buffer[..frame_length]).map_err(Error::FrameParse)?;
+            buffer.drain(..frame_length);
+            // No extensions are negotiated, so the reserved bits must be unset
+            if raw.fin_rsv & 0x70 != 0 {
+                return Err(Error::FrameParse(FrameParseError::WrongFinRSV));
+            }
+            if raw.op_code & 0x08 != 0 {
+                // Control frames can't be fragmented, but they can be sent in between the fragments of a message
+                if raw.fin_rsv & 0x80 == 0 {
+                    return Err(Error::FrameParse(FrameParseError::WrongFinRSV));
+                }
+                return Frame::from_raw(raw).map_err(Error::FrameParse);
+            }
+            if let Some(frame) = fragments.push(raw).map_err(Error::FrameParse)? {
+                return Ok(frame);
+            }
+        }
+
         // Wait for the socket to be readable
-        ref_stream.readable().await.map_err(Error::Io)?;
+        ref_stream.readable().await.map_err(Error::from_io)?;
         let mut buf = [0; READ_CHUNK_SIZE];
         match ref_stream.try_read(&mut buf) {
             Ok(0) => {
                 return Err(Error::ConnectionReset);
             }, // will not produce anymore, in theory
             Ok(n) => {
-                stream_bytes.extend_from_slice(&buf[0..n]);
-                
-                if let Some(expected_length) = &expected_length {
-                    if stream_bytes.len() != *expected_length {
-                        continue
-                    } else {
-                        break;
-                    }
-                } else {
-                    match Frame::parse(&stream_bytes) {
-                        Ok(_) => break,
-                        Err(FrameParseError::Incomplete{expected, ..}) => {
-                            expected_length = Some(expected);
-                            continue
-                        },
-                        Err(e) => {
-                            return Err(Error::FrameParse(e))
-                        }
-                    }
-                }
+                buffer.extend_from_slice(&buf[0..n]);
             }
             Err(ref e) if e.kind() == std::io::ErrorKind::WouldBlock => {
                 continue
             }
             Err(e) => {
-                return Err(Error::Io(e));
+                return Err(Error::from_io(e));
             }
         }
     }
-
-    Ok(stream_bytes)
-}
\ No newline at end of file
+}
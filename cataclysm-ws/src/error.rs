@@ -1,7 +1,7 @@
 /// Error indicating a frame parsing error
 #[derive(Debug)]
 pub enum FrameParseError {
-    /// Indicates that the first 4 bits of the message are unsupported
+    /// Indicates that the FIN and RSV bits of the message are unsupported by the reader
     WrongFinRSV,
     /// Indicates that the message content is incomplete
     Incomplete {
@@ -15,7 +15,14 @@ pub enum FrameParseError {
     /// The text sent through the message is not valid a utf-8
     InvalidUtf8(std::string::FromUtf8Error),
     /// Indicates an unsupported operation code contained in the frame
-    UnsupportedOpCode
+    UnsupportedOpCode,
+    /// Indicates a continuation frame with no message to continue, or a new message before the last fragment of the previous one
+    UnexpectedContinuation,
+    /// The message, or the fragments received so far, exceed the maximum size accepted by the reader
+    TooLarge {
+        length: u64,
+        limit: usize
+    }
 }
 
 impl std::fmt::Display for FrameParseError {
@@ -26,7 +33,9 @@ impl std::fmt::Display for FrameParseError {
             FrameParseError::Malformed => format!("the message does not have the corret structure or enough bytes"),
             FrameParseError::NullContent => format!("can't parse because the message has length 0"),
             FrameParseError::InvalidUtf8(e) => format!("invalid utf8 bytes, {}", e),
-            FrameParseError::UnsupportedOpCode => format!("the op code received is not supported")
+            FrameParseError::UnsupportedOpCode => format!("the op code received is not supported"),
+            FrameParseError::UnexpectedContinuation => "the fragments of the message are out of order".to_string(),
+            FrameParseError::TooLarge{length, limit} => format!("message of {} bytes exceeds the maximum size of {} bytes", length, limit)
         };
         write!(formatter, "{}", content)
     }
@@ -41,8 +50,28 @@ pub enum Error {
     Io(std::io::Error),
     /// Could not parse properly a frame, the detail is contained inside
     FrameParse(FrameParseError),
-    /// Indicates that the connection was closed abruptly
-    ConnectionReset
+    /// Indicates that the connection was closed abruptly, either while reading or writing. The peer is gone, so the connection can be dropped
+    ConnectionReset,
+    /// No frame was received within the configured read timeout
+    Timeout,
+    /// The websockets handshake could not be completed
+    Handshake(String),
+    /// No client is registered with the requested id in the [WebSocketHub](crate::WebSocketHub)
+    UnknownClient
+}
+
+impl Error {
+    /// Wraps an io error, reporting a closed connection as [ConnectionReset](Error::ConnectionReset)
+    pub(crate) fn from_io(error: std::io::Error) -> Error {
+        match error.kind() {
+            std::io::ErrorKind::BrokenPipe |
+            std::io::ErrorKind::ConnectionReset |
+            std::io::ErrorKind::ConnectionAborted |
+            std::io::ErrorKind::NotConnected |
+            std::io::ErrorKind::UnexpectedEof => Error::ConnectionReset,
+            _ => Error::Io(error)
+        }
+    }
 }
 
 impl std::fmt::Display for Error {
@@ -50,7 +79,10 @@ impl std::fmt::Display for Error {
         let content = match self {
             Error::Io(inner_error) => format!("io error: {}", inner_error),
             Error::FrameParse(fpe) => format!("frame parse error: {}", fpe),
-            Error::ConnectionReset => format!("connection reset by peer")
+            Error::ConnectionReset => format!("connection reset by peer"),
+            Error::Timeout => "timeout reached while waiting for a frame".to_string(),
+            Error::Handshake(detail) => format!("handshake error: {}", detail),
+            Error::UnknownClient => "no client registered with the given id".to_string()
         };
         write!(formatter, "{}", content)
     }
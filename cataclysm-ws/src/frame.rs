@@ -2,12 +2,24 @@ use crate::{FrameParseError, Message};
 
 /// Frame structure from websockets connection
 pub struct Frame {
+    /// FIN and RSV bits, in the 4 most significant bits
+    fin_rsv: u8,
     inner_op_code: u8,
     masking_key: Option<u32>,
+    /// Status code carried by a close frame
+    close_code: Option<u16>,
     /// Inner message
     pub message: Message
 }
 
+/// Frame as read from the connection, before its payload is interpreted
+pub(crate) struct RawFrame {
+    pub(crate) fin_rsv: u8,
+    pub(crate) op_code: u8,
+    pub(crate) masking_key: Option<[u8; 4]>,
+    pub(crate) payload: Vec<u8>
+}
+
 impl Frame {
     /// FIN RSV bytes
     pub const FIN_RSV: u8 = 0x80;
@@ -27,24 +39,66 @@ impl Frame {
     /// Operation code for a pong message
     pub const OP_CODE_PONG: u8 = 0x0A;
 
+    /// Close status code for a message whose content doesn't match its type, like a text message with invalid utf-8
+    pub const CLOSE_INVALID_PAYLOAD: u16 = 1007;
+    /// Close status code for a message too big to be processed
+    pub const CLOSE_MESSAGE_TOO_BIG: u16 = 1009;
+
     /// Returns the OP CODE of the frame as a u8, where the last 4 bits contain the OP CODE
     pub fn op_code(&self) -> u8 {
         self.inner_op_code
     }
 
+    /// Indicates if the FIN bit is set, that is, if this is the last fragment of a message
+    pub fn is_final(&self) -> bool {
+        self.fin_rsv & 0x80 != 0
+    }
+
+    /// Indicates if the first reserved bit is set, used by extensions like permessage-deflate
+    pub fn rsv1(&self) -> bool {
+        self.fin_rsv & 0x40 != 0
+    }
+
+    /// Indicates if the second reserved bit is set
+    pub fn rsv2(&self) -> bool {
+        self.fin_rsv & 0x20 != 0
+    }
+
+    /// Indicates if the third reserved bit is set
+    pub fn rsv3(&self) -> bool {
+        self.fin_rsv & 0x10 != 0
+    }
+
+    /// Reads the header of the frame at the beginning of the bytes, if enough of it is available, giving back the length of the header (masking key included) and the declared length of the payload
+    ///
+    /// The payload length comes from the peer, so it is kept as a `u64` for the caller to check it before buffering anything.
+    pub(crate) fn header_lengths(candidate: &[u8]) -> Option<(usize, u64)> {
+        let min_length = candidate.get(1)? & (!0x80);
+        let (length, offset) = match min_length {
+            126 => (u16::from_be_bytes([*candidate.get(2)?, *candidate.get(3)?]) as u64, 4usize),
+            127 => (u64::from_be_bytes(candidate.get(2..10)?.try_into().ok()?), 10usize),
+            _ => (min_length as u64, 2usize)
+        };
+        let masking_key_length = if 0x80 == (candidate[1] & 0x80) { 4 } else { 0 };
+        Some((offset + masking_key_length, length))
+    }
+
     /// Attempts to parse a frame from a stream of bytes
+    ///
+    /// The FIN and RSV bits are preserved, and can be checked through [is_final](Frame::is_final) and the `rsv` functions.
     pub fn parse<A: AsRef<[u8]>>(content: A) -> Result<Frame, FrameParseError> {
-        let candidate = content.as_ref();
+        Frame::from_raw(Frame::parse_raw(content.as_ref())?)
+    }
 
+    /// Reads the header of a frame and unmasks its payload, without interpreting it
+    pub(crate) fn parse_raw(candidate: &[u8]) -> Result<RawFrame, FrameParseError> {
         if candidate.is_empty() {
             // Not enough bytes to even read a possible FIN_RSV + OP_CODE, and prevent panics
             return Err(FrameParseError::NullContent);
         }
 
-        // The or operation needs to put ones on the first 4 bits, if candidate[0] matches FIN_RSV
-        if ((candidate[0] ^ !Frame::FIN_RSV) >> 4) != 0x0f {
-            return Err(FrameParseError::WrongFinRSV);
-        }
+        // FIN and RSV bits are kept as they are, it is up to the reader to accept them or not
+        let fin_rsv = candidate[0] & 0xf0;
 
         // We extract the minimum length, removing the masking key
         let min_length = candidate[1] & (!0x80);
@@ -57,7 +111,9 @@ impl Frame {
             if candidate.len() < 10 {
                 return Err(FrameParseError::Malformed)
             }
-            (u64::from_be_bytes([candidate[2], candidate[3], candidate[4], candidate[5], candidate[6], candidate[7], candidate[8], candidate[9]]) as usize, 10usize)
+            // A length that doesn't fit in memory can't be complete anyway
+            let length = u64::from_be_bytes([candidate[2], candidate[3], candidate[4], candidate[5], candidate[6], candidate[7], candidate[8], candidate[9]]);
+            (usize::try_from(length).unwrap_or(usize::MAX), 10usize)
         } else {
             (min_length as usize, 2usize)
         };
@@ -66,7 +122,7 @@ impl Frame {
         let masking_key = if 0x80 == (candidate[1] & 0x80) {
             if candidate.len() < offset + 4  {
                 // Quite likely not a malformed message, just incomplete
-                return Err(FrameParseError::Incomplete{expected: length + offset + 4, obtained: candidate.len()})
+                return Err(FrameParseError::Incomplete{expected: length.saturating_add(offset + 4), obtained: candidate.len()})
             }
 
             offset += 4;
@@ -77,7 +133,8 @@ impl Frame {
         };
         // Now we read the operation code
         let inner_op_code = (candidate[0] << 4) >> 4;
-        let mut payload = candidate.get(offset..offset+length).ok_or_else(|| FrameParseError::Incomplete{expected: length + offset, obtained: candidate.len()})?.to_vec();
+        let end = offset.saturating_add(length);
+        let mut payload = candidate.get(offset..end).ok_or(FrameParseError::Incomplete{expected: end, obtained: candidate.len()})?.to_vec();
         if let Some(masking_key) = &masking_key {
             // We decode the content in case we have a masking key
             payload = payload.into_iter().enumerate().map(|(idx, v)| {
@@ -86,18 +143,35 @@ impl Frame {
                 v ^ masking_key[j]
             }).collect();
         }
-        let message = match inner_op_code {
+        Ok(RawFrame {
+            fin_rsv,
+            op_code: inner_op_code,
+            masking_key,
+            payload
+        })
+    }
+
+    /// Interprets the payload of a raw frame according to its operation code
+    pub(crate) fn from_raw(raw: RawFrame) -> Result<Frame, FrameParseError> {
+        let RawFrame {fin_rsv, op_code, masking_key, payload} = raw;
+        let mut close_code = None;
+        let message = match op_code {
             Frame::OP_CODE_TEXT => Message::Text(String::from_utf8(payload).map_err(|e| FrameParseError::InvalidUtf8(e))?),
             Frame::OP_CODE_BINARY => Message::Binary(payload),
             Frame::OP_CODE_PING => Message::Ping(payload),
             Frame::OP_CODE_PONG => Message::Pong(payload),
-            Frame::OP_CODE_CLOSE => Message::Close,
+            Frame::OP_CODE_CLOSE => {
+                close_code = payload.get(0..2).map(|code| u16::from_be_bytes([code[0], code[1]]));
+                Message::Close
+            },
             _ => return Err(FrameParseError::UnsupportedOpCode)
         };
 
         Ok(Frame {
-            inner_op_code,
+            fin_rsv,
+            inner_op_code: op_code,
             masking_key: masking_key.map(u32::from_be_bytes),
+            close_code,
             message
         })
     }
@@ -107,19 +181,35 @@ impl Frame {
         let payload = text.into();
         let message = Message::Text(payload);
         Frame {
+            fin_rsv: Frame::FIN_RSV,
             inner_op_code: Frame::OP_CODE_TEXT,
             masking_key: None,
+            close_code: None,
             message
         }
     }
 
     /// Creates a ping message with the given application data
+    ///
+    /// The data survives serialization and parsing, so it can be echoed back in a [pong](Frame::pong).
+    ///
+    /// ```rust
+    /// # use cataclysm_ws::{Frame, Message};
+    /// let bytes: Vec<u8> = Frame::ping("heartbeat").masked(0x12345678).into();
+    /// let parsed = Frame::parse(&bytes).unwrap();
+    /// assert!(matches!(parsed.get_message(), Message::Ping(payload) if payload == b"heartbeat"));
+    ///
+    /// let pong: Vec<u8> = Frame::pong(parsed.get_message().as_bytes()).into();
+    /// assert!(matches!(Frame::parse(&pong).unwrap().get_message(), Message::Pong(payload) if payload == b"heartbeat"));
+    /// ```
     pub fn ping<A: Into<Vec<u8>>>(payload: A) -> Frame {
         let payload = payload.into();
         let message = Message::Ping(payload);
         Frame {
+            fin_rsv: Frame::FIN_RSV,
             inner_op_code: Frame::OP_CODE_PING,
             masking_key: None,
+            close_code: None,
             message
         }
     }
@@ -129,8 +219,10 @@ impl Frame {
         let payload = payload.into();
         let message = Message::Pong(payload);
         Frame {
+            fin_rsv: Frame::FIN_RSV,
             inner_op_code: Frame::OP_CODE_PONG,
             masking_key: None,
+            close_code: None,
             message
         }
     }
@@ -140,8 +232,10 @@ impl Frame {
         let payload = binary.into();
         let message = Message::Binary(payload);
         Frame {
+            fin_rsv: Frame::FIN_RSV,
             inner_op_code: Frame::OP_CODE_BINARY,
             masking_key: None,
+            close_code: None,
             message
         }
     }
@@ -150,23 +244,94 @@ impl Frame {
     pub fn close() -> Frame {
         let masking_key = None; //Some(rand::random::<u32>());
         Frame {
+            fin_rsv: Frame::FIN_RSV,
             inner_op_code: Frame::OP_CODE_CLOSE,
             masking_key,
+            close_code: None,
             message: Message::Close
         }
     }
 
+    /// Creates a close frame carrying a status code
+    ///
+    /// ```rust
+    /// # use cataclysm_ws::Frame;
+    /// let bytes: Vec<u8> = Frame::close_with_code(Frame::CLOSE_INVALID_PAYLOAD).into();
+    /// assert_eq!(Frame::parse(&bytes).unwrap().close_code(), Some(1007));
+    /// ```
+    pub fn close_with_code(code: u16) -> Frame {
+        Frame {
+            close_code: Some(code),
+            ..Frame::close()
+        }
+    }
+
+    /// Returns the status code of a close frame, if it carries one
+    pub fn close_code(&self) -> Option<u16> {
+        self.close_code
+    }
+
+    /// Sets a masking key for the frame, as required by RFC 6455 for the frames that a client sends
+    pub fn masked(mut self, masking_key: u32) -> Frame {
+        self.masking_key = Some(masking_key);
+        self
+    }
+
     /// Takes the frame and returns the contained message, if any
     pub fn get_message(&self) -> &Message {
         &self.message
     }
 
+    /// Serializes a frame from its parts, copying the (masked) payload right after the header in a single allocation
+    pub(crate) fn encode(fin_rsv: u8, op_code: u8, masking_key: Option<u32>, payload: &[u8]) -> Vec<u8> {
+        let mask_bit = if masking_key.is_some() {0x80} else {0x00};
+        // At most 10 bytes of header, plus the masking key
+        let mut content = Vec::with_capacity(14 + payload.len());
+        content.push(fin_rsv | op_code);
+        let payload_length = payload.len();
+        if payload_length < 126 {
+            content.push(payload_length as u8 | mask_bit);
+        } else if payload_length <= u16::MAX.into() /*65535*/{
+            content.push(126u8 | mask_bit);
+            // And now we push the length as u16
+            content.extend((payload_length as u16).to_be_bytes());
+        } else {
+            content.push(127u8 | mask_bit);
+            // And now we push the length as u64
+            content.extend((payload_length as u64).to_be_bytes());
+        }
+
+        match masking_key {
+            Some(masking_key) => {
+                let masking_bytes = masking_key.to_be_bytes();
+                // We add the masking key
+                content.extend(masking_bytes);
+                // According to rfc 6455
+                content.extend(payload.iter().enumerate().map(|(idx, v)| v ^ masking_bytes[idx % 4]));
+            },
+            None => content.extend_from_slice(payload)
+        }
+        content
+    }
+
     /// Indicates if this frame is a closing frame
     pub fn is_close(&self) -> bool {
         self.inner_op_code == Frame::OP_CODE_CLOSE
     }
 }
 
+impl From<Message> for Frame {
+    fn from(message: Message) -> Frame {
+        Frame {
+            fin_rsv: Frame::FIN_RSV,
+            inner_op_code: message.op_code(),
+            masking_key: None,
+            close_code: None,
+            message
+        }
+    }
+}
+
 impl From<Frame> for Message {
     fn from(source: Frame) -> Message {
         source.message
@@ -175,35 +340,9 @@ impl From<Frame> for Message {
 
 impl From<Frame> for Vec<u8> {
     fn from(source: Frame) -> Vec<u8> {
-        let mut content = vec![Frame::FIN_RSV ^ source.inner_op_code];
-        let mut payload: Vec<u8> = source.message.into();
-        let payload_length = payload.len();
-        if payload_length < 126 {
-            content.push(payload_length as u8 | if source.masking_key.is_some() {0x80} else {0x00});
-        } else if payload_length <= u16::MAX.into() /*65535*/{
-            content.push(126u8 | if source.masking_key.is_some() {0x80} else {0x00});
-            // And now we push the length as u16
-            content.extend((payload_length as u16).to_be_bytes());
-        } else {
-            content.push(127u8 | if source.masking_key.is_some() {0x80} else {0x00});
-            // And now we push the length as u16
-            content.extend((payload_length as u64).to_be_bytes());
+        match source.close_code {
+            Some(code) if source.inner_op_code == Frame::OP_CODE_CLOSE => Frame::encode(source.fin_rsv, source.inner_op_code, source.masking_key, &code.to_be_bytes()),
+            _ => Frame::encode(source.fin_rsv, source.inner_op_code, source.masking_key, source.message.as_bytes())
         }
-
-        if let Some(masking_key) = &source.masking_key {
-            let masking_bytes = masking_key.to_be_bytes();
-            payload = payload.into_iter().enumerate().map(|(idx, v)| {
-                // According to rfc 6455
-                let j = idx % 4;
-                v ^ masking_bytes[j]
-            }).collect();
-            // We add the masking key
-            content.extend(masking_bytes);
-        }
-        if !payload.is_empty() {
-            content.extend(payload);
-        }
-        
-        content
     }
 }
\ No newline at end of file
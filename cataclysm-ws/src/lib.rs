@@ -6,6 +6,7 @@ pub use self::web_socket_stream::WebSocketStream;
 pub use self::web_socket_reader::{WebSocketReader, WebSocketCustomChild};
 pub use self::web_socket_writer::WebSocketWriter;
 pub use self::web_socket_thread::WebSocketThread;
+pub use self::web_socket_hub::{WebSocketHub, HubThread};
 pub use self::frame::Frame;
 pub use self::message::Message;
 pub use self::error::{Error, FrameParseError};
@@ -14,6 +15,7 @@ mod web_socket_stream;
 mod web_socket_reader;
 mod web_socket_writer;
 mod web_socket_thread;
+mod web_socket_hub;
 mod frame;
 mod message;
 mod error;
@@ -1,12 +1,15 @@
+use crate::Frame;
+
 /// Message structure contained in a frame
+#[derive(Clone, Debug)]
 pub enum Message {
     /// Text message
     Text(String),
     /// Binary message
     Binary(Vec<u8>),
-    /// Ping message
+    /// Ping message, with its application data
     Ping(Vec<u8>),
-    /// Pong message
+    /// Pong message, with the application data of the ping it answers
     Pong(Vec<u8>),
     /// Close message
     Close
@@ -23,16 +26,26 @@ impl Message {
         Message::Binary(bytes.into())
     }
 
-    /// Geneates an instances of the [Message::Binary](Message::Binary) variant
+    /// Geneates an instances of the [Message::Ping](Message::Ping) variant
     pub fn ping<A: Into<Vec<u8>>>(payload: A) -> Message {
         Message::Ping(payload.into())
     }
 
-    /// Geneates an instances of the [Message::Binary](Message::Binary) variant
+    /// Geneates an instances of the [Message::Pong](Message::Pong) variant
     pub fn pong<A: Into<Vec<u8>>>(payload: A) -> Message {
         Message::Pong(payload.into())
     }
 
+    /// Indicates if the variant equates de [Message::Text](Message::Text) variant
+    pub fn is_text(&self) -> bool {
+        matches!(&self, Message::Text(_))
+    }
+
+    /// Indicates if the variant equates de [Message::Binary](Message::Binary) variant
+    pub fn is_binary(&self) -> bool {
+        matches!(&self, Message::Binary(_))
+    }
+
     /// Indicates if the variant equates de [Message::Close](Message::Close) variant
     pub fn is_close(&self) -> bool {
         matches!(&self, Message::Close)
@@ -47,6 +60,52 @@ impl Message {
     pub fn is_pong(&self) -> bool {
         matches!(&self, Message::Pong(_))
     }
+
+    /// Returns the content of a [Message::Text](Message::Text), or `None` for any other variant
+    pub fn as_text(&self) -> Option<&str> {
+        match self {
+            Message::Text(content) => Some(content),
+            _ => None
+        }
+    }
+
+    /// Returns the payload of the message as bytes, which is empty for a [Message::Close](Message::Close)
+    ///
+    /// ```rust
+    /// # use cataclysm_ws::Message;
+    /// let message = Message::text("hello");
+    /// assert_eq!(message.as_bytes(), b"hello");
+    /// assert_eq!(message.len(), 5);
+    /// assert_eq!(message.as_text(), Some("hello"));
+    /// ```
+    pub fn as_bytes(&self) -> &[u8] {
+        match self {
+            Message::Text(content) => content.as_bytes(),
+            Message::Binary(content) | Message::Ping(content) | Message::Pong(content) => content,
+            Message::Close => &[]
+        }
+    }
+
+    /// Operation code of the frames that carry this message
+    pub(crate) fn op_code(&self) -> u8 {
+        match self {
+            Message::Text(_) => Frame::OP_CODE_TEXT,
+            Message::Binary(_) => Frame::OP_CODE_BINARY,
+            Message::Ping(_) => Frame::OP_CODE_PING,
+            Message::Pong(_) => Frame::OP_CODE_PONG,
+            Message::Close => Frame::OP_CODE_CLOSE
+        }
+    }
+
+    /// Returns the size of the payload of the message, in bytes
+    pub fn len(&self) -> usize {
+        self.as_bytes().len()
+    }
+
+    /// Indicates if the payload of the message is empty
+    pub fn is_empty(&self) -> bool {
+        self.as_bytes().is_empty()
+    }
 }
 
 impl From<Message> for Vec<u8> {
@@ -0,0 +1,167 @@
+use tokio::sync::Mutex;
+use std::{
+    collections::HashMap,
+    hash::Hash,
+    sync::Arc
+};
+use crate::{Error, Frame, Message, WebSocketWriter, WebSocketThread};
+
+/// Registry of connected websockets clients
+///
+/// The hub keeps the writing part of each connection under an id, and allows to send messages to one or all of them. Writers that fail to send a message are removed from the hub. Clones of the hub share the same registry.
+///
+/// ```rust,no_run
+/// # use cataclysm_ws::{WebSocketHub, WebSocketStream, WebSocketThread, Message};
+/// # use std::sync::Arc;
+/// struct Chat {
+///     hub: WebSocketHub<usize>
+/// }
+///
+/// impl WebSocketThread for Chat {
+///     type Output = ();
+///     async fn on_message(&mut self, message: Message) {
+///         self.hub.broadcast(message).await;
+///     }
+///     async fn on_close(&mut self, _clean: bool) {}
+/// }
+///
+/// # async fn handler(hub: WebSocketHub<usize>, id: usize, web_socket_stream: WebSocketStream) {
+/// let (writer, reader) = web_socket_stream.split();
+/// // Registration and removal happen on open and on close
+/// reader.spawn(hub.track(id, Arc::new(writer), Chat{hub: hub.clone()}));
+/// # }
+/// ```
+pub struct WebSocketHub<K> {
+    clients: Arc<Mutex<HashMap<K, Arc<WebSocketWriter>>>>
+}
+
+impl<K: Eq + Hash + Clone + Send + Sync + 'static> WebSocketHub<K> {
+    /// Creates an empty hub
+    pub fn new() -> WebSocketHub<K> {
+        WebSocketHub {
+            clients: Arc::new(Mutex::new(HashMap::new()))
+        }
+    }
+
+    /// Registers a writer under the given id, returning the previous one (if any)
+    pub async fn register(&self, id: K, writer: Arc<WebSocketWriter>) -> Option<Arc<WebSocketWriter>> {
+        self.clients.lock().await.insert(id, writer)
+    }
+
+    /// Removes the writer registered under the given id, returning it (if any)
+    pub async fn deregister(&self, id: &K) -> Option<Arc<WebSocketWriter>> {
+        self.clients.lock().await.remove(id)
+    }
+
+    /// Returns the amount of registered writers
+    pub async fn len(&self) -> usize {
+        self.clients.lock().await.len()
+    }
+
+    /// Indicates if there are no registered writers
+    pub async fn is_empty(&self) -> bool {
+        self.clients.lock().await.is_empty()
+    }
+
+    /// Sends a message to all the registered writers
+    ///
+    /// Writers that fail to send the message are removed from the hub. The message is serialized only once, and the same bytes are written to every (server side) connection.
+    pub async fn broadcast(&self, message: Message) {
+        // The lock is not held while sending, so a slow client doesn't block the registry
+        let clients: Vec<(K, Arc<WebSocketWriter>)> = self.clients.lock().await.iter()
+            .map(|(id, writer)| (id.clone(), writer.clone()))
+            .collect();
+        // Client side writers need their own masking key, the rest share the same bytes
+        let encoded = Frame::encode(Frame::FIN_RSV, message.op_code(), None, message.as_bytes());
+        let mut failed = Vec::new();
+        for (id, writer) in clients {
+            let result = if writer.mask_frames {
+                writer.send_ref(&message).await
+            } else {
+                writer.write(&encoded).await
+            };
+            if let Err(_e) = result {
+                #[cfg(feature = "full_log")]
+                log::debug!("removing writer from hub, {}", _e);
+                failed.push((id, writer));
+            }
+        }
+        self.remove_failed(failed).await;
+    }
+
+    /// Sends a message to the writer registered under the given id
+    ///
+    /// If the message can't be sent, the writer is removed from the hub.
+    pub async fn send_to(&self, id: &K, message: Message) -> Result<(), Error> {
+        let writer = self.clients.lock().await.get(id).cloned().ok_or(Error::UnknownClient)?;
+        let result = writer.send(message).await;
+        if result.is_err() {
+            self.remove_failed(vec![(id.clone(), writer)]).await;
+        }
+        result
+    }
+
+    /// Wraps a [WebSocketThread], so the writer gets registered on open and removed on close
+    pub fn track<H: WebSocketThread>(&self, id: K, writer: Arc<WebSocketWriter>, wst: H) -> HubThread<K, H> {
+        HubThread {
+            hub: self.clone(),
+            id,
+            writer,
+            wst
+        }
+    }
+
+    async fn remove_failed(&self, failed: Vec<(K, Arc<WebSocketWriter>)>) {
+        let mut clients = self.clients.lock().await;
+        for (id, writer) in failed {
+            // The id might have been registered again with a new writer in the meantime
+            if clients.get(&id).map(|current| Arc::ptr_eq(current, &writer)).unwrap_or(false) {
+                clients.remove(&id);
+            }
+        }
+    }
+}
+
+impl<K: Eq + Hash + Clone + Send + Sync + 'static> Default for WebSocketHub<K> {
+    fn default() -> Self {
+        WebSocketHub::new()
+    }
+}
+
+impl<K> Clone for WebSocketHub<K> {
+    fn clone(&self) -> Self {
+        WebSocketHub {
+            clients: self.clients.clone()
+        }
+    }
+}
+
+/// [WebSocketThread] wrapper created by [WebSocketHub::track]
+pub struct HubThread<K, H> {
+    hub: WebSocketHub<K>,
+    id: K,
+    writer: Arc<WebSocketWriter>,
+    wst: H
+}
+
+impl<K: Eq + Hash + Clone + Send + Sync + 'static, H: WebSocketThread> WebSocketThread for HubThread<K, H> {
+    type Output = H::Output;
+
+    async fn on_protocol(&mut self, protocol: Option<String>) {
+        self.wst.on_protocol(protocol).await
+    }
+
+    async fn on_open(&mut self) {
+        self.hub.register(self.id.clone(), self.writer.clone()).await;
+        self.wst.on_open().await
+    }
+
+    async fn on_message(&mut self, message: Message) {
+        self.wst.on_message(message).await
+    }
+
+    async fn on_close(&mut self, clean: bool) -> Self::Output {
+        self.hub.remove_failed(vec![(self.id.clone(), self.writer.clone())]).await;
+        self.wst.on_close(clean).await
+    }
+}
\ No newline at end of file
@@ -1,39 +1,108 @@
 use tokio::{
-    net::{TcpStream, tcp::OwnedReadHalf},
+    net::{TcpStream, tcp::{OwnedReadHalf, OwnedWriteHalf}},
     task::JoinHandle,
-    sync::{OwnedSemaphorePermit}
+    sync::{OwnedSemaphorePermit, Mutex}
 };
 use crate::{
     Frame,
     Error,
     FrameParseError,
     WebSocketThread,
-    communication::read_frame
+    communication::{read_frame, write_frame, write_locked, CloseHandshake, ReadBuffer}
 };
+use std::sync::{Arc, Weak};
 
 /// Runner thread for a websockets connection
 pub struct WebSocketReader {
     read_stream: OwnedReadHalf,
-    permit: Option<OwnedSemaphorePermit>
+    permit: Option<OwnedSemaphorePermit>,
+    read_timeout: Option<std::time::Duration>,
+    /// Bytes already read from the connection, that belong to the next frame(s), and the fragments received so far
+    read_buffer: Mutex<ReadBuffer>,
+    /// Closing handshake state, shared with the writer when both come from a split
+    pub(crate) close_handshake: Option<Arc<CloseHandshake>>,
+    /// Write half of the writer from the same split, so close frames sent from here wait for its ongoing writes. It is not kept alive once the writer is dropped
+    pub(crate) write_stream: Weak<Mutex<OwnedWriteHalf>>,
+    /// Subprotocol agreed during the handshake
+    pub(crate) protocol: Option<String>
 }
 
 impl WebSocketReader {
     /// Generates a new instance of the websocket reader, assuming the handshake has already been performed
     pub fn new_unchecked(read_stream: OwnedReadHalf) -> WebSocketReader {
+        WebSocketReader::with_buffer(read_stream, ReadBuffer::default())
+    }
+
+    /// Same as `new_unchecked`, but with some bytes that were already read from the connection
+    pub(crate) fn with_buffer(read_stream: OwnedReadHalf, read_buffer: ReadBuffer) -> WebSocketReader {
         WebSocketReader {
             read_stream,
-            permit: None
+            permit: None,
+            read_timeout: None,
+            read_buffer: Mutex::new(read_buffer),
+            close_handshake: None,
+            write_stream: Weak::new(),
+            protocol: None
         }
     }
 
+    /// Sets a maximum time to wait for each frame
+    ///
+    /// When no frame arrives within this time, [try_read_frame](WebSocketReader::try_read_frame) returns [Error::Timeout], which makes the [WebSocketCustomChild] close the connection. By default, the reader waits forever.
+    pub fn read_timeout(mut self, duration: std::time::Duration) -> Self {
+        self.read_timeout = Some(duration);
+        self
+    }
+
+    /// Sets the maximum size of a message, in bytes, fragmented messages included
+    ///
+    /// Frames are rejected with [FrameParseError::TooLarge] as soon as their declared length goes over the limit, before their payload gets buffered, which makes the [WebSocketCustomChild] close the connection with the `1009` status code. Defaults to 64 MiB.
+    pub fn max_message_size(mut self, max_message_size: usize) -> Self {
+        self.read_buffer.get_mut().max_message_size = max_message_size;
+        self
+    }
+
     /// Auxiliar function that cataclysm uses to keep track of connections
     pub fn set_permit(&mut self, permit: OwnedSemaphorePermit) {
         self.permit = Some(permit);
     }
 
-    /// Blocks until a message is received
+    /// Returns the subprotocol agreed during the handshake, if any
+    pub fn protocol(&self) -> Option<&str> {
+        self.protocol.as_deref()
+    }
+
+    /// Blocks until a message is received, or the read timeout is reached
     pub async fn try_read_frame(&self) -> Result<Frame, Error> {
-        read_frame(&self).await
+        let frame = match self.read_timeout {
+            Some(duration) => tokio::time::timeout(duration, read_frame(&self, &mut *self.read_buffer.lock().await)).await.map_err(|_| Error::Timeout)?,
+            None => read_frame(&self, &mut *self.read_buffer.lock().await).await
+        }?;
+        if frame.is_close() {
+            if let Some(close_handshake) = &self.close_handshake {
+                if close_handshake.is_sent() {
+                    // This is the answer to our own close frame
+                    close_handshake.mark_received();
+                }
+            }
+        }
+        Ok(frame)
+    }
+
+    /// Sends a close frame, either answering the peer or due to a protocol error, unless a close frame was already sent
+    async fn reply_close(&self, mut frame: Frame) -> Result<(), Error> {
+        if let Some(close_handshake) = &self.close_handshake {
+            if close_handshake.mark_sent() {
+                return Ok(());
+            }
+            if close_handshake.mask_frames {
+                frame = frame.masked(rand::random::<u32>());
+            }
+        }
+        match self.write_stream.upgrade() {
+            Some(write_stream) => write_locked(&write_stream, &Vec::from(frame)).await,
+            None => write_frame(self, frame).await
+        }
     }
 
     /// Spawns a tokio thread that dispatches the message to the proved handler
@@ -70,11 +139,15 @@ impl WebSocketCustomChild {
     /// Spawns a tokio thread that dispatches the message to the proved handler
     pub fn spawn<H: WebSocketThread + 'static>(self, mut wst: H) -> JoinHandle<<H as WebSocketThread>::Output> {
         tokio::spawn(async move {
+            wst.on_protocol(self.wsr.protocol.clone()).await;
             wst.on_open().await;
             loop {
                 match self.wsr.try_read_frame().await {
                     Ok(frame) => {
                         if frame.message.is_close() && self.automatic_close {
+                            if let Err(e) = self.wsr.reply_close(Frame::close()).await {
+                                log::debug!("could not answer the close frame, {}", e);
+                            }
                             break wst.on_close(true).await
                         }
 
@@ -87,6 +160,19 @@ impl WebSocketCustomChild {
                                 // It is likely that a next chunk is missing
                                 continue
                             },
+                            Error::FrameParse(FrameParseError::InvalidUtf8(_)) => {
+                                // Text messages with invalid utf-8 close the connection with the corresponding status code
+                                if let Err(e) = self.wsr.reply_close(Frame::close_with_code(Frame::CLOSE_INVALID_PAYLOAD)).await {
+                                    log::debug!("could not send the close frame, {}", e);
+                                }
+                                break wst.on_close(false).await
+                            },
+                            Error::FrameParse(FrameParseError::TooLarge{..}) => {
+                                if let Err(e) = self.wsr.reply_close(Frame::close_with_code(Frame::CLOSE_MESSAGE_TOO_BIG)).await {
+                                    log::debug!("could not send the close frame, {}", e);
+                                }
+                                break wst.on_close(false).await
+                            },
                             _ => {
                                 log::debug!("closing connection");
                                 break wst.on_close(false).await
@@ -1,14 +1,21 @@
 use tokio::{
     net::{TcpStream},
-    sync::{OwnedSemaphorePermit}
+    sync::{OwnedSemaphorePermit, Mutex}
 };
 use crate::{Error, Message, Frame, WebSocketWriter, WebSocketReader};
-use crate::communication::{write_message, read_frame};
+use crate::communication::{write_frame, read_frame, CloseHandshake, ReadBuffer};
+use std::sync::Arc;
 
 /// Wrapper structure of a tcp stream with some websockets utilities
 pub struct WebSocketStream {
     inner: TcpStream,
-    permit: Option<OwnedSemaphorePermit>
+    permit: Option<OwnedSemaphorePermit>,
+    /// Bytes already read from the connection, that belong to the next frame(s), and the fragments received so far
+    read_buffer: Mutex<ReadBuffer>,
+    /// Indicates if outgoing frames need to be masked, which is the case for the client side
+    mask_frames: bool,
+    /// Subprotocol agreed during the handshake
+    protocol: Option<String>
 }
 
 impl WebSocketStream {
@@ -16,33 +23,166 @@ impl WebSocketStream {
     pub fn from_tcp_stream_unchecked(stream: TcpStream) -> WebSocketStream {
         WebSocketStream {
             inner: stream,
-            permit: None
+            permit: None,
+            read_buffer: Mutex::new(ReadBuffer::default()),
+            mask_frames: false,
+            protocol: None
         }
     }
 
+    /// Connects to a websockets server, performing the client side of the handshake
+    ///
+    /// Only the `ws` scheme is supported. Frames sent through the resulting stream (or through its writer, after a split) are masked, as required for clients.
+    ///
+    /// ```rust,no_run
+    /// # use cataclysm_ws::{WebSocketStream, Message};
+    /// # async fn client() -> Result<(), cataclysm_ws::Error> {
+    /// let stream = WebSocketStream::connect("ws://127.0.0.1:8000/socket").await?;
+    /// stream.send_message(Message::text("hello")).await?;
+    /// let frame = stream.try_read_frame().await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    #[cfg(feature = "client")]
+    pub async fn connect<A: AsRef<str>>(url: A) -> Result<WebSocketStream, Error> {
+        use base64::{Engine, engine::general_purpose};
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        let url = url::Url::parse(url.as_ref()).map_err(|e| Error::Handshake(format!("invalid url, {}", e)))?;
+        if url.scheme() != "ws" {
+            return Err(Error::Handshake(format!("unsupported scheme {}", url.scheme())));
+        }
+        let host = url.host_str().ok_or_else(|| Error::Handshake("url has no host".to_string()))?;
+        let port = url.port().unwrap_or(80);
+        let mut path = url.path().to_string();
+        if let Some(query) = url.query() {
+            path += &format!("?{}", query);
+        }
+
+        let key = general_purpose::STANDARD.encode(rand::random::<[u8; 16]>());
+        let handshake = format!(
+            "GET {} HTTP/1.1\r\nHost: {}\r\nUpgrade: websocket\r\nConnection: Upgrade\r\nSec-WebSocket-Key: {}\r\nSec-WebSocket-Version: 13\r\n\r\n",
+            path, if url.port().is_some() {format!("{}:{}", host, port)} else {host.to_string()}, key
+        );
+
+        let mut stream = TcpStream::connect((host, port)).await.map_err(Error::Io)?;
+        stream.write_all(handshake.as_bytes()).await.map_err(Error::Io)?;
+
+        // We read until the end of the response header
+        let mut buffer = Vec::new();
+        let header_end = loop {
+            if let Some(idx) = buffer.windows(4).position(|w| w == b"\r\n\r\n") {
+                break idx + 4;
+            }
+            let mut chunk = [0; 1_024];
+            match stream.read(&mut chunk).await.map_err(Error::Io)? {
+                0 => return Err(Error::Handshake("connection closed during the handshake".to_string())),
+                n => buffer.extend_from_slice(&chunk[..n])
+            }
+        };
+        let leftover = buffer.split_off(header_end);
+        let header = String::from_utf8(buffer).map_err(|e| Error::Handshake(format!("{}", e)))?;
+        let mut lines = header.split("\r\n");
+
+        let status = lines.next().and_then(|line| line.split(' ').nth(1)).unwrap_or("");
+        if status != "101" {
+            return Err(Error::Handshake(format!("server answered with status {}", status)));
+        }
+
+        let nonce = format!("{}258EAFA5-E914-47DA-95CA-C5AB0DC85B11", key);
+        let expected_accept = general_purpose::STANDARD.encode(ring::digest::digest(&ring::digest::SHA1_FOR_LEGACY_USE_ONLY, nonce.as_bytes()));
+        let accept = lines.filter_map(|line| line.split_once(':'))
+            .find(|(k, _)| k.trim().eq_ignore_ascii_case("sec-websocket-accept"))
+            .map(|(_, v)| v.trim());
+        if accept != Some(expected_accept.as_str()) {
+            return Err(Error::Handshake("missing or wrong Sec-WebSocket-Accept header".to_string()));
+        }
+
+        let protocol = header.split("\r\n").filter_map(|line| line.split_once(':'))
+            .find(|(k, _)| k.trim().eq_ignore_ascii_case("sec-websocket-protocol"))
+            .map(|(_, v)| v.trim().to_string());
+
+        Ok(WebSocketStream {
+            inner: stream,
+            permit: None,
+            read_buffer: Mutex::new(leftover.into()),
+            mask_frames: true,
+            protocol
+        })
+    }
+
+    /// Sets the maximum size of a message, in bytes, fragmented messages included
+    ///
+    /// Frames whose declared length goes over the limit are rejected with [FrameParseError::TooLarge](crate::FrameParseError::TooLarge) before their payload gets buffered. The limit is kept by the reader after a split. Defaults to 64 MiB.
+    pub fn max_message_size(mut self, max_message_size: usize) -> Self {
+        self.read_buffer.get_mut().max_message_size = max_message_size;
+        self
+    }
+
     /// Auxiliar function that cataclysm uses to keep track of connections
     pub fn set_permit(&mut self, permit: OwnedSemaphorePermit) {
         self.permit = Some(permit);
     }
 
+    /// Auxiliar function that cataclysm uses to record the subprotocol agreed during the handshake
+    pub fn set_protocol<A: Into<String>>(&mut self, protocol: A) {
+        self.protocol = Some(protocol.into());
+    }
+
+    /// Returns the subprotocol agreed during the handshake, if any
+    pub fn protocol(&self) -> Option<&str> {
+        self.protocol.as_deref()
+    }
+
     /// Sends a message through the websockets connection
     pub async fn send_message(&self, message: Message) -> Result<(), Error> {
-        write_message(&self, message).await
+        let mut frame = Frame::from(message);
+        if self.mask_frames {
+            frame = frame.masked(rand::random::<u32>());
+        }
+        write_frame(&self, frame).await
     }
 
     /// Blocks until a message is received
     pub async fn try_read_frame(&self) -> Result<Frame, Error> {
-        read_frame(&self).await
+        read_frame(&self, &mut *self.read_buffer.lock().await).await
     }
 
-    /// Splits the stream into the reading and writting part
+    /// Splits the stream into the writting and reading part
+    ///
+    /// Both halves are owned, so one task can read while another one writes, with no locking involved.
+    ///
+    /// ```rust,no_run
+    /// # use cataclysm_ws::{WebSocketStream, Message};
+    /// # async fn handler(web_socket_stream: WebSocketStream) {
+    /// let (writer, reader) = web_socket_stream.split();
+    /// tokio::spawn(async move {
+    ///     writer.text("hello").await.unwrap();
+    /// });
+    /// while let Ok(frame) = reader.try_read_frame().await {
+    ///     if let Message::Text(text) = frame.message {
+    ///         println!("{}", text);
+    ///     }
+    /// }
+    /// # }
+    /// ```
+    ///
+    /// Any bytes already read by the stream, but not consumed as a frame, are handed to the reader, along with the fragments of an incomplete message. Both halves cooperate on the closing handshake, see [WebSocketWriter::close].
     pub fn split(self) -> (WebSocketWriter, WebSocketReader) {
         let (rx, tx) = self.inner.into_split();
-        let mut web_socket_reader = WebSocketReader::new_unchecked(rx);
+        let mut web_socket_reader = WebSocketReader::with_buffer(rx, self.read_buffer.into_inner());
         if let Some(permit) = self.permit {
             web_socket_reader.set_permit(permit);
         }
-        (WebSocketWriter::new_unchecked(tx), web_socket_reader)
+        web_socket_reader.protocol = self.protocol;
+        let mut web_socket_writer = WebSocketWriter::new_unchecked(tx);
+        web_socket_writer.mask_frames = self.mask_frames;
+        web_socket_reader.write_stream = Arc::downgrade(&web_socket_writer.write_stream);
+        // Both halves take part in the closing handshake
+        let close_handshake = Arc::new(CloseHandshake::new(self.mask_frames));
+        web_socket_reader.close_handshake = Some(close_handshake.clone());
+        web_socket_writer.close_handshake = Some(close_handshake);
+        (web_socket_writer, web_socket_reader)
     }
 }
 
@@ -4,6 +4,13 @@ use crate::Message;
 /// Trait necessary to start a ws read-processing thread
 pub trait WebSocketThread: Send + 'static {
     type Output: Send;
+    /// On negotiated subprotocol
+    ///
+    /// This function gets called right before [on_open](WebSocketThread::on_open), with the subprotocol agreed during the handshake (if any). Useful when one endpoint supports more than one subprotocol.
+    fn on_protocol(&mut self, _protocol: Option<String>) -> impl Future<Output = ()> + Send {
+        async {}
+    }
+
     /// On opened connection
     ///
     /// This function gets called when the websockets connection is properly stablished.
@@ -12,8 +19,30 @@ pub trait WebSocketThread: Send + 'static {
     }
     /// On message callback
     ///
-    /// This function gets called back when a [Message](crate::Message) is received.
-    fn on_message(&mut self, message: Message) -> impl Future<Output = ()> + Send;
+    /// This function gets called back when a [Message](crate::Message) is received. By default, text and binary messages are dispatched to [on_text](WebSocketThread::on_text) and [on_binary](WebSocketThread::on_binary), and the rest are ignored.
+    fn on_message(&mut self, message: Message) -> impl Future<Output = ()> + Send {
+        async move {
+            match message {
+                Message::Text(text) => self.on_text(text).await,
+                Message::Binary(bytes) => self.on_binary(bytes).await,
+                _ => ()
+            }
+        }
+    }
+
+    /// On text message
+    ///
+    /// This function gets called by the default [on_message](WebSocketThread::on_message) implementation when a text message is received.
+    fn on_text(&mut self, _text: String) -> impl Future<Output = ()> + Send {
+        async {}
+    }
+
+    /// On binary message
+    ///
+    /// This function gets called by the default [on_message](WebSocketThread::on_message) implementation when a binary message is received.
+    fn on_binary(&mut self, _bytes: Vec<u8>) -> impl Future<Output = ()> + Send {
+        async {}
+    }
     
     /// On closed connection
     ///
@@ -1,12 +1,22 @@
-use tokio::net::{TcpStream, tcp::OwnedWriteHalf};
-use crate::{Error, Frame};
-use bytes::Buf;
+use tokio::{
+    io::AsyncWriteExt,
+    net::tcp::OwnedWriteHalf,
+    sync::Mutex
+};
+use std::{sync::Arc, time::Duration};
+use crate::{Error, Frame, Message, communication::{CloseHandshake, write_locked}};
 
-const CHUNK_SIZE: usize = 4_096;
+const DEFAULT_CLOSE_TIMEOUT: Duration = Duration::from_secs(5);
 
 /// Sending part of web sockets connection
 pub struct WebSocketWriter {
-    write_stream: OwnedWriteHalf
+    /// Shared with the reader when both come from a split, so its close frames don't interleave with ours
+    pub(crate) write_stream: Arc<Mutex<OwnedWriteHalf>>,
+    /// Indicates if the frames need a masking key (client side)
+    pub(crate) mask_frames: bool,
+    /// Closing handshake state, shared with the reader when both come from a split
+    pub(crate) close_handshake: Option<Arc<CloseHandshake>>,
+    close_timeout: Duration
 }
 
 impl WebSocketWriter {
@@ -20,72 +30,99 @@ impl WebSocketWriter {
 
     pub fn new_unchecked(write_stream: OwnedWriteHalf) -> Self {
         WebSocketWriter {
-            write_stream
+            write_stream: Arc::new(Mutex::new(write_stream)),
+            mask_frames: false,
+            close_handshake: None,
+            close_timeout: DEFAULT_CLOSE_TIMEOUT
         }
     }
 
-    async fn write<A: Into<Vec<u8>>>(&self, content: A) -> Result<(), Error> {
-        let content: Vec<u8> = content.into();
-        let mut chunks_iter = content.chunks(CHUNK_SIZE);
-        #[cfg(feature = "full_log")]
-        log::trace!("writting {} chunks of maximum {} bytes each", chunks_iter.len(), CHUNK_SIZE);
-        // We check the first chunk
-        let mut current_chunk = match chunks_iter.next() {
-            Some(v) => v,
-            None => return Ok(()) // Zero length response
-        };
-        loop {
-            // Wait for the socket to be writable
-            let stream: &TcpStream = self.write_stream.as_ref();
-            stream.writable().await.unwrap();
-    
-            // Try to write data, this may still fail with `WouldBlock`
-            // if the readiness event is a false positive.
-            match stream.try_write(&current_chunk) {
-                Ok(n) => {
-                    if n != current_chunk.remaining() {
-                        // There are some bytes still to be written in this chunk
-                        #[cfg(feature = "full_log")]
-                        log::debug!("incomplete chunk, trying to serve remaining bytes ({}/{})", current_chunk.len(), CHUNK_SIZE);
-                        current_chunk.advance(n);
-                        continue;
-                    } else {
-                        current_chunk = match chunks_iter.next() {
-                            Some(v) => v,
-                            None => break Ok(())
-                        }
-                    }
-                }
-                Err(ref e) if e.kind() == tokio::io::ErrorKind::WouldBlock => {
-                    continue;
-                }
-                Err(e) => break Err(Error::Io(e))
-            }
+    /// Sets the maximum time that [close](WebSocketWriter::close) waits for the peer's close frame, 5 seconds by default
+    pub fn close_timeout(mut self, duration: Duration) -> Self {
+        self.close_timeout = duration;
+        self
+    }
+
+    async fn write_frame(&self, mut frame: Frame) -> Result<(), Error> {
+        if self.mask_frames {
+            frame = frame.masked(rand::random::<u32>());
         }
+        self.write(&Vec::from(frame)).await
+    }
+
+    /// Writes already serialized frames through the connection
+    pub(crate) async fn write(&self, content: &[u8]) -> Result<(), Error> {
+        write_locked(&self.write_stream, content).await
+    }
+
+    /// Sends any message through the websockets connection
+    ///
+    /// If the peer is gone, [Error::ConnectionReset](crate::Error::ConnectionReset) is returned, so the writer can be dropped. Other errors might be transient. The same applies to the rest of the sending functions.
+    ///
+    /// ```rust,no_run
+    /// # use cataclysm_ws::{WebSocketWriter, Message, Error};
+    /// async fn broadcast(writers: &mut Vec<WebSocketWriter>, message: Message) {
+    ///     let mut closed = Vec::new();
+    ///     for (idx, writer) in writers.iter().enumerate() {
+    ///         if let Err(Error::ConnectionReset) = writer.send_ref(&message).await {
+    ///             closed.push(idx);
+    ///         }
+    ///     }
+    ///     for idx in closed.into_iter().rev() {
+    ///         writers.remove(idx);
+    ///     }
+    /// }
+    /// ```
+    pub async fn send(&self, message: Message) -> Result<(), Error> {
+        self.write_frame(Frame::from(message)).await
+    }
+
+    /// Sends a message without taking ownership of it
+    ///
+    /// The frame is serialized straight from the borrowed payload, so forwarding the same message to several connections doesn't require cloning it for each one.
+    pub async fn send_ref(&self, message: &Message) -> Result<(), Error> {
+        let masking_key = self.mask_frames.then(rand::random::<u32>);
+        self.write(&Frame::encode(Frame::FIN_RSV, message.op_code(), masking_key, message.as_bytes())).await
     }
 
     /// Sends a text message through the websockets connection
     pub async fn text<A: Into<String>>(&self, text: A) -> Result<(), Error> {
-        self.write(Frame::text(text)).await
+        self.write_frame(Frame::text(text)).await
     }
 
-    /// Sends a text message through the websockets connection
+    /// Sends a binary message through the websockets connection
     pub async fn bytes<A: Into<Vec<u8>>>(&self, bytes: A) -> Result<(), Error> {
-        self.write(Frame::binary(bytes)).await
+        self.write_frame(Frame::binary(bytes)).await
     }
 
     /// Sends a ping message through the websockets connection
     pub async fn ping<A: Into<Vec<u8>>>(&self, payload: A) -> Result<(), Error> {
-        self.write(Frame::ping(payload)).await
+        self.write_frame(Frame::ping(payload)).await
     }
 
     /// Sends a pong message through the websockets connection
     pub async fn pong<A: Into<Vec<u8>>>(&self, payload: A) -> Result<(), Error> {
-        self.write(Frame::pong(payload)).await
+        self.write_frame(Frame::pong(payload)).await
     }
 
-    /// Closes the write part of the socket
+    /// Performs the closing handshake, and closes the write part of the socket
+    ///
+    /// A close frame is sent, and when this writer comes from [WebSocketStream::split](crate::WebSocketStream::split), it waits until the reader gets the peer's close frame, or the [close_timeout](WebSocketWriter::close_timeout) elapses. Calling this function more than once has no effect.
     pub async fn close(&self) -> Result<(), Error> {
-        self.write(Frame::close()).await
+        match &self.close_handshake {
+            Some(close_handshake) => {
+                if close_handshake.mark_sent() {
+                    // The close frame was already sent, either by us or as a reply from the reader
+                    return Ok(());
+                }
+                self.write_frame(Frame::close()).await?;
+                if tokio::time::timeout(self.close_timeout, close_handshake.wait_received()).await.is_err() {
+                    #[cfg(feature = "full_log")]
+                    log::debug!("peer did not answer the close frame in time");
+                }
+            },
+            None => self.write_frame(Frame::close()).await?
+        }
+        self.write_stream.lock().await.shutdown().await.map_err(Error::Io)
     }
 }
\ No newline at end of file
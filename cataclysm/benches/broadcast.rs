@@ -0,0 +1,53 @@
+use criterion::*;
+use cataclysm::ws::{WebSocketStream, WebSocketWriter, WebSocketHub, Message};
+use tokio::io::AsyncReadExt;
+use std::sync::Arc;
+
+const CLIENTS: usize = 16;
+
+/// Connects the clients, which just drain whatever they receive, and returns the server side writers
+async fn connect() -> Vec<Arc<WebSocketWriter>> {
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:8100").await.unwrap();
+    let mut writers = Vec::new();
+    for _ in 0..CLIENTS {
+        let mut client = tokio::net::TcpStream::connect("127.0.0.1:8100").await.unwrap();
+        tokio::spawn(async move {
+            let mut buf = vec![0; 65_536];
+            while let Ok(n) = client.read(&mut buf).await {
+                if n == 0 {
+                    break;
+                }
+            }
+        });
+        // Dropping the reader does not close the connection, only the writer does
+        let (writer, _) = WebSocketStream::from_tcp_stream_unchecked(listener.accept().await.unwrap().0).split();
+        writers.push(Arc::new(writer));
+    }
+    writers
+}
+
+fn bench(c: &mut Criterion) {
+    let rt = tokio::runtime::Runtime::new().unwrap();
+    let writers = rt.block_on(connect());
+    let hub = WebSocketHub::new();
+    for (id, writer) in writers.iter().enumerate() {
+        rt.block_on(hub.register(id, writer.clone()));
+    }
+
+    for size in [64, 16_384] {
+        let message = Message::binary(vec![7; size]);
+        c.bench_function(&format!("{} clients, {} bytes, cloned send", CLIENTS, size), |b| {
+            b.to_async(&rt).iter(|| async {
+                for writer in &writers {
+                    writer.send(message.clone()).await.unwrap();
+                }
+            });
+        });
+        c.bench_function(&format!("{} clients, {} bytes, hub broadcast", CLIENTS, size), |b| {
+            b.to_async(&rt).iter(|| hub.broadcast(message.clone()));
+        });
+    }
+}
+
+criterion_group!(benches, bench);
+criterion_main!(benches);
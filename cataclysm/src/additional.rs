@@ -1,13 +1,24 @@
-use crate::{Shared};
+use crate::{Shared, shared::DataMap, metafunctions::ExtractionErrorFn, stats::Counters};
 use std::sync::Arc;
+use std::any::TypeId;
 use crate::session::SessionCreator;
 
 /// Wrapper for additional shared data in the server
 ///
-/// This structure is reserved for future use, particularly dealing with the pipeline (possibly session management)
+/// It reaches every layer and extractor, and holds the values declared in the server builder.
 pub struct Additional<T> {
     pub(crate) shared: Option<Shared<T>>,
+    /// Values retrieved by their type, through the [Data](crate::Data) extractor
+    pub(crate) data: DataMap,
     pub(crate) session_creator: Option<Arc<Box<dyn SessionCreator>>>,
+    /// Indicates if the file handlers should guess the mime type from the content when the extension is unknown
+    pub(crate) content_sniffing: bool,
+    /// Number of reverse proxies whose forwarding headers can be trusted
+    pub(crate) trusted_proxies: usize,
+    /// Response for failed extractions, when the branch does not set one
+    pub(crate) extraction_error: Option<Arc<ExtractionErrorFn>>,
+    /// Traffic counters of the server
+    pub(crate) stats: Arc<Counters>
 }
 
 impl<T> Additional<T> {
@@ -15,4 +26,24 @@ impl<T> Additional<T> {
     pub fn shared(&self) -> Option<Shared<T>> {
         self.shared.clone()
     }
+
+    /// Retrieves a value declared with the ServerBuilder's [data](crate::ServerBuilder::data), by its type
+    ///
+    /// Mostly useful for layers, as handlers can use the [Data](crate::Data) extractor instead.
+    ///
+    /// ```rust,no_run
+    /// # use cataclysm::{Additional, Pipeline, http::{Request, Response}};
+    /// # use futures::future::FutureExt;
+    /// # use std::sync::{Arc, atomic::{AtomicUsize, Ordering}};
+    /// // Layer that counts the requests, with a counter given to the server through `data`
+    /// let layer = |req: Request, pipeline: Box<Pipeline<()>>, ad: Arc<Additional<()>>| async move {
+    ///     if let Some(counter) = ad.get::<AtomicUsize>() {
+    ///         counter.fetch_add(1, Ordering::Relaxed);
+    ///     }
+    ///     pipeline.execute(req, ad).await
+    /// }.boxed();
+    /// ```
+    pub fn get<X: 'static>(&self) -> Option<&X> {
+        self.data.get(&TypeId::of::<X>()).and_then(|data| data.downcast_ref::<X>())
+    }
 }
\ No newline at end of file
@@ -1,14 +1,14 @@
 use std::collections::{HashMap, HashSet};
-use regex::Regex;
+use regex::{Regex, RegexSet};
 use futures::future::FutureExt;
 use crate::{
     additional::Additional,
-    CoreFn, LayerFn, Extractor, Callback, Pipeline,
-    http::{Method, Request, Response, MethodHandler}
+    CoreFn, LayerFn, Extractor, Callback, Pipeline, Readiness, Error,
+    http::{Method, Request, Response, MethodHandler, StatusCode, file_content_type}
 };
-use crate::metafunctions::callback::{PipelineKind, PipelineInfo};
+use crate::metafunctions::{ExtractionErrorFn, extraction_error_response, callback::{PipelineKind, PipelineInfo}};
 #[cfg(feature = "stream")]
-use crate::{HandlerFn, StreamCallback, Stream};
+use crate::{HandlerFn, StreamCallback, StreamThread, Stream};
 #[cfg(feature = "full_log")]
 use crate::metafunctions::callback::{PipelineTrack};
 use std::sync::Arc;
@@ -18,12 +18,36 @@ use std::fs::File;
 use std::io::prelude::*;
 use std::path::PathBuf;
 
+lazy_static::lazy_static! {
+    /// Matches variable tokens, like `{:name}`
+    static ref DEFAULT_RE: Regex = Regex::new(r"^\{:.*\}$").unwrap();
+    /// Matches pattern tokens, like `{regex:^\d+$}`
+    static ref REGEX_RE: Regex = Regex::new(r"^\{regex:.*\}$").unwrap();
+}
+
 enum BranchKind {
     Exact,
     Pattern,
     Default
 }
 
+/// Behaviour of the file handler when the requested path is a directory
+#[derive(Clone, Copy)]
+enum DirectoryIndex {
+    Disabled,
+    IndexFile,
+    Listing
+}
+
+/// Description of an endpoint registered in a branch, see [routes](Branch::routes)
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RouteInfo {
+    /// Path template of the endpoint, like `/users/{:id}`
+    pub template: String,
+    /// Methods with a handler in the endpoint, sorted alphabetically
+    pub methods: Vec<Method>
+}
+
 /// ## Main cataclysm structure for route handling
 ///
 /// Branches are cataclysm's main building block. It is a really simple pattern matching system, with the following priorities. They are named branches to avoid conflict with the [Path](crate::http::Path) extractor.
@@ -81,8 +105,14 @@ pub struct Branch<T> {
     default_callback: Option<Arc<CoreFn<T>>>,
     /// File callback, in case this endpoint wants to be used for static file serving
     files_callback: Option<Arc<CoreFn<T>>>,
+    /// Files location, in case the file callback also replies to directories
+    files_index: Option<PathBuf>,
     /// Layer functions on this branch
     layers: Vec<Arc<LayerFn<T>>>,
+    /// Maximum size of the request bodies for this node and its children, replacing the server's one
+    max_body_size: Option<usize>,
+    /// Response for failed extractions in this node and its children, replacing the server's one
+    extraction_error: Option<Arc<ExtractionErrorFn>>,
     /// Stream handler, when no other match was found
     #[cfg(feature = "stream")]
     stream_handler: Option<Arc<HandlerFn<T>>>
@@ -135,9 +165,12 @@ impl<T: Sync + Send> Branch<T> {
             default_method_callback: None,
             default_callback: None,
             files_callback: None,
+            files_index: None,
             #[cfg(feature = "stream")]
             stream_handler: None,
-            layers: vec![]
+            layers: vec![],
+            max_body_size: None,
+            extraction_error: None
         };
         let (base, rest_branch) = if let Some((base, rest)) = trimmed_trail.tokenize_once() {
             let rest_branch = Branch::new(rest);
@@ -204,25 +237,31 @@ impl<T: Sync + Send> Branch<T> {
         let source = self.source.clone();
         let top_branch = self.get_branch(source).unwrap();
         top_branch.default_method_callback = Some(Arc::new(Box::new(move |req: Request, additional: Arc<Additional<T>>|  {
-            match <A as Extractor<T>>::extract(&req, additional) {
+            match <A as Extractor<T>>::extract(&req, additional.clone()) {
                 Ok(args) => callback.invoke(args).boxed(),
-                Err(_e) => {
-                    #[cfg(feature = "full_log")]
-                    {
-                        log::error!("extractor error: {}", _e);
-                        let response = _e.as_response();
-                        (async {response}).boxed()
-                    }
-                    #[cfg(not(feature = "full_log"))]
-                    {
-                        (async {Response::bad_request()}).boxed()
-                    }
+                Err(e) => {
+                    let response = extraction_error_response(&req, &additional, e);
+                    (async {response}).boxed()
                 }
             }
         })));
         self
     }
 
+    /// Replies to any method with the given callback, custom ones included.
+    ///
+    /// Handlers registered for specific methods through [with](Branch::with) still take precedence, as this is a shortcut for [unmatched_method_to](Branch::unmatched_method_to).
+    ///
+    /// ```rust
+    /// # use cataclysm::{Branch, http::Response};
+    /// let branch: Branch<()> = Branch::new("/health").any(|| async {
+    ///     Response::ok().body("up")
+    /// });
+    /// ```
+    pub fn any<F: Callback<A> + Send + Sync + 'static, A: Extractor<T>>(self, callback: F) -> Self {
+        self.unmatched_method_to(callback)
+    }
+
     /// Adds a default callback, in case of no nested matching.
     ///
     /// ```rust
@@ -236,19 +275,11 @@ impl<T: Sync + Send> Branch<T> {
         let source = self.source.clone();
         let top_branch = self.get_branch(source).unwrap();
         top_branch.default_callback = Some(Arc::new(Box::new(move |req: Request, additional: Arc<Additional<T>>|  {
-            match <A as Extractor<T>>::extract(&req, additional) {
+            match <A as Extractor<T>>::extract(&req, additional.clone()) {
                 Ok(args) => callback.invoke(args).boxed(),
-                Err(_e) => {
-                    #[cfg(feature = "full_log")]
-                    {
-                        log::error!("extractor error: {}", _e);
-                        let response = _e.as_response();
-                        (async {response}).boxed()
-                    }
-                    #[cfg(not(feature = "full_log"))]
-                    {
-                        (async {Response::bad_request()}).boxed()
-                    }
+                Err(e) => {
+                    let response = extraction_error_response(&req, &additional, e);
+                    (async {response}).boxed()
                 }
             }
         })));
@@ -266,21 +297,89 @@ impl<T: Sync + Send> Branch<T> {
     ///     Response::ok().body("Is this an SPA?")
     /// }).files("./static");
     /// ```
-    pub fn files<A: Into<PathBuf>>(mut self, files_location: A) -> Self {
-        let fl = files_location.into();
+    ///
+    /// Html files are served with `Cache-Control: no-cache`, and the rest with a `max-age` of one hour. A [layer](Branch::layer) can replace the header, if another policy is needed.
+    ///
+    /// Precompressed versions of a file, like `app.js.br` or `app.js.gz` next to `app.js`, are served instead of the original when the client accepts their encoding, with the corresponding `Content-Encoding` and the type of the original file. Brotli is preferred over gzip.
+    pub fn files<A: Into<PathBuf>>(self, files_location: A) -> Self {
+        self.files_with_directory_index(files_location.into(), DirectoryIndex::Disabled)
+    }
+
+    /// Allows static file serving, replying to directories with their `index.html` file.
+    ///
+    /// Works exactly as [files](Branch::files), but when the requested path points to a directory inside the files location, the `index.html` file contained in it gets served (or a `404 Not Found`, if missing).
+    ///
+    /// ```rust
+    /// # use cataclysm::Branch;
+    /// // `/docs/` will serve `./static/docs/index.html`
+    /// let branch: Branch<()> = Branch::new("/").files_with_index("./static");
+    /// ```
+    pub fn files_with_index<A: Into<PathBuf>>(self, files_location: A) -> Self {
+        self.files_with_directory_index(files_location.into(), DirectoryIndex::IndexFile)
+    }
+
+    /// Allows static file serving, rendering a listing for directories with no `index.html` file.
+    ///
+    /// Works exactly as [files_with_index](Branch::files_with_index), but if the directory contains no `index.html` file, an html page listing the directory contents is generated. Use with care, as this exposes the structure of the files location.
+    ///
+    /// ```rust
+    /// # use cataclysm::Branch;
+    /// let branch: Branch<()> = Branch::new("/downloads").files_with_listing("./downloads");
+    /// ```
+    pub fn files_with_listing<A: Into<PathBuf>>(self, files_location: A) -> Self {
+        self.files_with_directory_index(files_location.into(), DirectoryIndex::Listing)
+    }
+
+    /// Sets the file callback, with the given directory behaviour
+    fn files_with_directory_index(mut self, fl: PathBuf, directory_index: DirectoryIndex) -> Self {
+        let files_index = match directory_index {
+            DirectoryIndex::Disabled => None,
+            _ => Some(fl.clone())
+        };
         // For some odd reason, the compiler didn't guess this closure properly. So we help it :)
-        let close: Box<dyn Fn(Request, Arc<Additional<T>>) -> Pin<Box<(dyn futures::Future<Output = Response> + Send + 'static)>> + Sync + Send> = Box::new(move |req: Request, _additional: Arc<Additional<T>>|  {
+        let close: Box<dyn Fn(Request, Arc<Additional<T>>) -> Pin<Box<(dyn futures::Future<Output = Response> + Send + 'static)>> + Sync + Send> = Box::new(move |req: Request, additional: Arc<Additional<T>>|  {
+            let fl_root = fl.clone();
             let mut fl_clone = fl.clone();
+            let content_sniffing = additional.content_sniffing;
             (async move {
                 let trimmed_trail = req.url().path().trim_start_matches("/");
                 let tokens = trimmed_trail.tokenize();
-                let path: PathBuf = tokens.iter().skip(req.depth).collect();
+                let path: PathBuf = tokens.iter().skip(req.depth).map(|token| percent_encoding::percent_decode_str(token).decode_utf8_lossy().to_string()).collect();
+                // Only plain components are allowed, so the files location can't be escaped
+                if !is_plain(&path) {
+                    #[cfg(feature = "full_log")]
+                    log::debug!("refusing to serve path {}", path.display());
+                    return Response::forbidden()
+                }
                 fl_clone.push(path);
+                // The resolved path needs to stay inside the files location, even through symbolic links
+                fl_clone = match resolve_inside(&fl_root, &fl_clone) {
+                    Ok(p) => p,
+                    Err(e) => return resolution_response(e)
+                };
+                if fl_clone.is_dir() {
+                    let index_file = fl_clone.join("index.html");
+                    match directory_index {
+                        DirectoryIndex::Disabled => return Response::not_found(),
+                        DirectoryIndex::IndexFile | DirectoryIndex::Listing if index_file.is_file() => fl_clone = match resolve_inside(&fl_root, &index_file) {
+                            Ok(p) => p,
+                            Err(e) => return resolution_response(e)
+                        },
+                        DirectoryIndex::IndexFile => return Response::not_found(),
+                        DirectoryIndex::Listing => return directory_listing(&fl_clone, req.url().path())
+                    }
+                }
                 let extension = match fl_clone.extension().map(|e| e.to_str()).flatten() {
                     Some(e) => e,
                     None => return Response::internal_server_error()
                 };
-                match File::open(&fl_clone) {
+                // A precompressed version of the file is preferred, if the client accepts its encoding
+                let (sidecar, has_sidecars) = precompressed_sidecar(&fl_root, &fl_clone, &req);
+                let (file_path, content_encoding) = match sidecar {
+                    Some((sidecar_path, encoding)) => (sidecar_path, Some(encoding)),
+                    None => (fl_clone.clone(), None)
+                };
+                match File::open(&file_path) {
                     Ok(mut f) =>  {
                         let mut content = Vec::new();
                         match f.read_to_end(&mut content) {
@@ -288,8 +387,17 @@ impl<T: Sync + Send> Branch<T> {
                             Err(_) => return Response::internal_server_error()
                         }
                         #[cfg(feature = "full_log")]
-                        log::trace!("serving file {}", fl_clone.display());
-                        Response::ok().body(content).header("Content-Type", crate::http::MIME_TYPES.get(extension).map(|v| *v).unwrap_or("application/octet-stream"))
+                        log::trace!("serving file {}", file_path.display());
+                        // The type comes from the original file, compressed bytes can't be sniffed
+                        let content_type = file_content_type(extension, &content, content_sniffing && content_encoding.is_none());
+                        let mut response = file_response(content, content_type);
+                        if let Some(encoding) = content_encoding {
+                            response = response.header("Content-Encoding", encoding);
+                        }
+                        if has_sidecars {
+                            response = response.header("Vary", "Accept-Encoding");
+                        }
+                        response
                     },
                     Err(_) => {
                         #[cfg(feature = "full_log")]
@@ -302,6 +410,7 @@ impl<T: Sync + Send> Branch<T> {
         let source = self.source.clone();
         let top_branch = self.get_branch(source).unwrap();
         top_branch.files_callback = Some(Arc::new(close));
+        top_branch.files_index = files_index;
         self
     }
 
@@ -319,8 +428,9 @@ impl<T: Sync + Send> Branch<T> {
     pub fn defaults_to_file<A: Into<PathBuf>>(mut self, file_location: A) -> Self {
         let fl = file_location.into();
         // For some odd reason, the compiler didn't guess this closure properly. So we help it :)
-        let close: Box<dyn Fn(Request, Arc<Additional<T>>) -> Pin<Box<(dyn futures::Future<Output = Response> + Send + 'static)>> + Sync + Send> = Box::new(move |_req: Request, _additional: Arc<Additional<T>>|  {
+        let close: Box<dyn Fn(Request, Arc<Additional<T>>) -> Pin<Box<(dyn futures::Future<Output = Response> + Send + 'static)>> + Sync + Send> = Box::new(move |_req: Request, additional: Arc<Additional<T>>|  {
             let fl_clone = fl.clone();
+            let content_sniffing = additional.content_sniffing;
             (async move {
                 let extension = match fl_clone.extension().map(|e| e.to_str()).flatten() {
                     Some(e) => e,
@@ -333,7 +443,8 @@ impl<T: Sync + Send> Branch<T> {
                             Ok(_) => (),
                             Err(_) => return Response::internal_server_error()
                         }
-                        Response::ok().body(content).header("Content-Type", crate::http::MIME_TYPES.get(extension).map(|s| *s).unwrap_or("application/octet-stream"))
+                        let content_type = file_content_type(extension, &content, content_sniffing);
+                        file_response(content, content_type)
                     },
                     Err(_) => Response::not_found()
                 }
@@ -357,6 +468,9 @@ impl<T: Sync + Send> Branch<T> {
             default_method_callback,
             default_callback,
             files_callback,
+            files_index,
+            max_body_size,
+            extraction_error,
             #[cfg(feature = "stream")]
             stream_handler,
             ..
@@ -387,9 +501,12 @@ impl<T: Sync + Send> Branch<T> {
         }
         self.pattern_branches.extend(additional_pattern_branches);
 
-        // Priority to the other branch
-        if self.variable_branch.is_none() {
-            self.variable_branch = variable_branch;
+        // Variable matches are merged, keeping the variable name from the lhs branch
+        if let Some((rhs_id, rhs_branch)) = variable_branch {
+            match &mut self.variable_branch {
+                Some((_, lhs_branch)) => lhs_branch.merge_mut(*rhs_branch),
+                None => self.variable_branch = Some((rhs_id, rhs_branch))
+            }
         }
 
         //** Now the callbacks in this node **//
@@ -412,6 +529,17 @@ impl<T: Sync + Send> Branch<T> {
         // Priority for the lhs branch
         if self.files_callback.is_none() {
             self.files_callback = files_callback;
+            self.files_index = files_index;
+        }
+
+        // Priority for the lhs branch
+        if self.max_body_size.is_none() {
+            self.max_body_size = max_body_size;
+        }
+
+        // Priority for the lhs branch
+        if self.extraction_error.is_none() {
+            self.extraction_error = extraction_error;
         }
 
         #[cfg(feature = "stream")]
@@ -438,13 +566,164 @@ impl<T: Sync + Send> Branch<T> {
     /// * Method callbacks from `rhs` are only merged if not already present in `lhs`.
     /// * Exact matches from `rhs` will be merged if already found in `lhs`, else they get inserted.
     /// * Pattern matches from `rhs` will be marged if matched literally to another regex, else they will be inserted at the end of the evaluation queue.
-    /// * Variable match from `rhs` is ignored if `lhs` already contains one.
+    /// * Variable match from `rhs` will be merged into the one from `lhs`, keeping the variable name from `lhs`, else it gets inserted.
     /// * Static file serving from `rhs` is ignored if `lhs` already contains one.
     pub fn merge(mut self, other: Branch<T>) -> Branch<T> {
         self.merge_mut(other);
         self
     }
 
+    /// Builds a tree from a list of routes, each one with its path and handler
+    ///
+    /// Useful when routes are data, like when they come from a configuration file or a plugin system. Shared prefixes end up in the same nodes of the tree.
+    ///
+    /// ```rust
+    /// # use cataclysm::{Branch, http::{Method, Response}};
+    /// let routes = vec![
+    ///     ("/users", Method::Get.to(|| async {Response::ok().body("list")})),
+    ///     ("/users", Method::Post.to(|| async {Response::created()})),
+    ///     ("/users/{:id}", Method::Get.to(|| async {Response::ok().body("user")})),
+    ///     ("/users/{:id}/posts", Method::Get.to(|| async {Response::ok().body("posts")}))
+    /// ];
+    /// let branch: Branch<()> = Branch::from_routes(routes);
+    /// ```
+    ///
+    /// Routes are [merged](Branch::merge) in order, so if two of them share path and method, the first one is kept.
+    pub fn from_routes<A: AsRef<str>, I: IntoIterator<Item = (A, MethodHandler<T>)>>(routes: I) -> Branch<T> {
+        routes.into_iter().fold(Branch::new("/"), |branch, (trail, method_handler)| {
+            branch.merge(Branch::new(trail).with(method_handler))
+        })
+    }
+
+    /// Creates a branch that replies to `GET` and `HEAD` with `200 OK` and a `{"status": "ok"}` json body
+    ///
+    /// Meant for liveness probes, it composes with the rest of the tree like any other branch.
+    ///
+    /// ```rust
+    /// # use cataclysm::{Branch, http::{Response, Method}};
+    /// let branch: Branch<()> = Branch::new("/api").with(Method::Get.to(|| async {Response::ok()}))
+    ///     .merge(Branch::health_check("/health"));
+    /// ```
+    pub fn health_check<A: AsRef<str>>(trail: A) -> Branch<T> {
+        Branch::new(trail).with(Method::Get.and(Method::Head).to(|| async {
+            serde_json::json!({"status": "ok"})
+        }))
+    }
+
+    /// Creates a branch that redirects `GET` and `HEAD` requests to another location, with a `302 Found`
+    ///
+    /// ```rust
+    /// # use cataclysm::{Branch, http::{Response, Method}};
+    /// let branch: Branch<()> = Branch::new("/")
+    ///     .nest(Branch::new("/home").with(Method::Get.to(|| async {Response::ok()})))
+    ///     .nest(Branch::redirect("/index.html", "/home"));
+    /// ```
+    ///
+    /// The location is sent as given, so it can be a path in the same server or a full url. See [permanent_redirect](Branch::permanent_redirect) for moves that clients may remember.
+    pub fn redirect<A: AsRef<str>, B: Into<String>>(trail: A, location: B) -> Branch<T> {
+        let location = location.into();
+        Branch::new(trail).with(Method::Get.and(Method::Head).to(move || {
+            let response = Response::redirect(location.clone());
+            async move { response }
+        }))
+    }
+
+    /// Creates a branch that redirects `GET` and `HEAD` requests to another location, with a `301 Moved Permanently`
+    ///
+    /// ```rust
+    /// # use cataclysm::Branch;
+    /// let branch: Branch<()> = Branch::new("/")
+    ///     .nest(Branch::permanent_redirect("/blog", "https://blog.example.com"));
+    /// ```
+    ///
+    /// Browsers and caches remember permanent redirections, so prefer [redirect](Branch::redirect) while the new location may still change.
+    pub fn permanent_redirect<A: AsRef<str>, B: Into<String>>(trail: A, location: B) -> Branch<T> {
+        let location = location.into();
+        Branch::new(trail).with(Method::Get.and(Method::Head).to(move || {
+            let response = Response::permanent_redirect(location.clone());
+            async move { response }
+        }))
+    }
+
+    /// Creates a branch that replies to `GET` and `HEAD` according to a readiness check
+    ///
+    /// The check can return a `bool`, or a `Result<(), E>`. When ready, the reply is a `200 OK` with a `{"status": "ok"}` json body. Otherwise, it is a `503 Service Unavailable` with a `{"status": "unavailable"}` body, which also contains a `detail` field with the error, if any.
+    ///
+    /// ```rust
+    /// # use cataclysm::Branch;
+    /// # use std::sync::{Arc, atomic::{AtomicBool, Ordering}};
+    /// let warmed_up = Arc::new(AtomicBool::new(false));
+    /// let flag = warmed_up.clone();
+    /// let branch: Branch<()> = Branch::new("/")
+    ///     .nest(Branch::health_check("/health"))
+    ///     .nest(Branch::readiness_check("/ready", move || {
+    ///         let flag = flag.clone();
+    ///         async move { flag.load(Ordering::Relaxed) }
+    ///     }));
+    /// ```
+    pub fn readiness_check<A, F, R, Z>(trail: A, check: F) -> Branch<T>
+    where
+        A: AsRef<str>,
+        F: Fn() -> R + Send + Sync + 'static,
+        R: Future<Output = Z> + Send + 'static,
+        Z: Readiness + Send
+    {
+        Branch::new(trail).with(Method::Get.and(Method::Head).to(move || {
+            let readiness = check();
+            async move {
+                match readiness.await.readiness() {
+                    Ok(()) => (StatusCode::OK, serde_json::json!({"status": "ok"})),
+                    Err(None) => (StatusCode::SERVICE_UNAVAILABLE, serde_json::json!({"status": "unavailable"})),
+                    Err(Some(detail)) => (StatusCode::SERVICE_UNAVAILABLE, serde_json::json!({"status": "unavailable", "detail": detail}))
+                }
+            }
+        }))
+    }
+
+    /// Lists the endpoints of the tree, with the methods they reply to
+    ///
+    /// Meant for tooling, like generating api documentation stubs or checking the route table in tests. The [Display](std::fmt::Display) implementation is kept for humans.
+    ///
+    /// ```rust
+    /// # use cataclysm::{Branch, RouteInfo, http::{Method, Response}};
+    /// let branch: Branch<()> = Branch::new("/users")
+    ///     .with(Method::Get.and(Method::Post).to(|| async {Response::ok()}))
+    ///     .nest(Branch::new("/{:id}").with(Method::Get.to(|| async {Response::ok()})));
+    /// assert_eq!(branch.routes(), vec![
+    ///     RouteInfo{template: "/users".to_string(), methods: vec![Method::Get, Method::Post]},
+    ///     RouteInfo{template: "/users/{:id}".to_string(), methods: vec![Method::Get]}
+    /// ]);
+    /// ```
+    ///
+    /// Only the nodes with method handlers are listed, sorted by template. Default callbacks, file serving and stream handlers are not included.
+    pub fn routes(&self) -> Vec<RouteInfo> {
+        let mut routes = Vec::new();
+        self.collect_routes("", &mut routes);
+        routes.sort_by(|a, b| a.template.cmp(&b.template));
+        routes
+    }
+
+    /// Walks the tree, adding an entry for each node with method handlers
+    fn collect_routes(&self, template: &str, routes: &mut Vec<RouteInfo>) {
+        if !self.method_callbacks.is_empty() {
+            let mut methods: Vec<Method> = self.method_callbacks.keys().cloned().collect();
+            methods.sort_by(|a, b| a.to_str().cmp(b.to_str()));
+            routes.push(RouteInfo {
+                template: if template.is_empty() { "/".to_string() } else { template.to_string() },
+                methods
+            });
+        }
+        for (base, branch) in &self.exact_branches {
+            branch.collect_routes(&format!("{}/{}", template, base), routes);
+        }
+        for (pattern, branch) in &self.pattern_branches {
+            branch.collect_routes(&format!("{}/{{regex:{}}}", template, pattern.as_str()), routes);
+        }
+        if let Some((var_id, branch)) = &self.variable_branch {
+            branch.collect_routes(&format!("{}/{{:{}}}", template, var_id), routes);
+        }
+    }
+
     /// Nests one branch in the top node of the first one
     ///
     /// The "top node" is defined as the one following the path given to the branch constructor.
@@ -464,6 +743,33 @@ impl<T: Sync + Send> Branch<T> {
         self
     }
 
+    /// Groups routes under a common prefix, nesting them in the top node of this branch
+    ///
+    /// The closure receives a branch created with the given prefix, in which routes, layers and defaults can be set in one place. The returned branch gets nested, so layers added inside the closure only wrap the routes of the scope.
+    ///
+    /// ```rust,no_run
+    /// use cataclysm::{Branch, Additional, Pipeline, http::{Request, Response, Method}};
+    /// use futures::future::FutureExt;
+    /// use std::sync::Arc;
+    ///
+    /// let branch: Branch<()> = Branch::new("/")
+    ///     .with(Method::Get.to(|| async {Response::ok().body("home")}))
+    ///     .scope("/api/v1", |scope| scope
+    ///         .nest(Branch::new("/users").with(Method::Get.to(|| async {Response::ok().body("users")})))
+    ///         .nest(Branch::new("/posts").with(Method::Get.to(|| async {Response::ok().body("posts")})))
+    ///         .defaults_to(|| async {Response::not_found()})
+    ///         .layer(|req: Request, pipeline: Box<Pipeline<()>>, ad: Arc<Additional<()>>| async {
+    ///             // Only the routes under `/api/v1` go through this layer
+    ///             pipeline.execute(req, ad).await
+    ///         }.boxed())
+    ///     );
+    /// ```
+    ///
+    /// This is equivalent to calling [nest](Branch::nest) with a branch built separately.
+    pub fn scope<A: AsRef<str>, F: FnOnce(Branch<T>) -> Branch<T>>(self, prefix: A, scope_fn: F) -> Self {
+        self.nest(scope_fn(Branch::new(prefix)))
+    }
+
     /// Adds a processing layer to the callbacks contained in this branch
     ///
     /// A layer is what is commonly known as middleware. The passed layer methods act as a wrap to the core handling functions of this branch. It is important to note that layer functions have a very specific structure: each one receives a [`Request`](crate::http::Request) and a boxed [`Pipeline`](crate::Pipeline). The function must return a pinned boxed future. A Timing Layer/Middleware function is provided as an example.
@@ -489,7 +795,7 @@ impl<T: Sync + Send> Branch<T> {
     /// );
     /// ```
     ///
-    /// Calling the function multiple times will wrap the preceeding layer (or core handlers), like an onion 🧅.
+    /// Calling the function multiple times will wrap the preceeding layer (or core handlers), like an onion 🧅. Layers from a parent branch wrap the ones from its nested branches, so the outermost branch's layers run first on the way in, and last on the way out.
     pub fn layer<F: 'static + Fn(Request, Box<Pipeline<T>>, Arc<Additional<T>>) -> Pin<Box<dyn Future<Output = Response> + Send>> + Send + Sync>(mut self, layer_fn: F) -> Self {
         let source = self.source.clone();
         let top_branch = self.get_branch(source).unwrap();
@@ -497,6 +803,46 @@ impl<T: Sync + Send> Branch<T> {
         self
     }
 
+    /// Sets a maximum size, in bytes, for the request bodies of this branch, replacing the one of the server
+    ///
+    /// ```rust,no_run
+    /// # use cataclysm::{Server, Branch, http::{Response, Method}};
+    /// let branch: Branch<()> = Branch::new("/")
+    ///     .with(Method::Post.to(|| async {Response::ok()}))
+    ///     // Uploads can be big, up to 500MB
+    ///     .nest(Branch::new("/upload").with(Method::Post.to(|| async {Response::ok()})).max_body_size(500 * 1024 * 1024));
+    /// // Everything else is capped at 1MB
+    /// let server = Server::builder(branch).max_body_size(1024 * 1024).build().unwrap();
+    /// ```
+    ///
+    /// The limit also applies to the nested branches, unless they set their own. The route is looked up as soon as the request header arrives, so a request announcing a bigger `Content-Length` gets a `413 Payload Too Large` before its body is read. In particular, clients that send `Expect: 100-continue` get the final answer instead of the interim response, and never send the body.
+    pub fn max_body_size(mut self, n: usize) -> Self {
+        let source = self.source.clone();
+        let top_branch = self.get_branch(source).unwrap();
+        top_branch.max_body_size = Some(n);
+        self
+    }
+
+    /// Sets up the response given when an extractor fails in this branch, replacing the one of the server
+    ///
+    /// ```rust,no_run
+    /// # use cataclysm::{Branch, http::{Response, Method, Query}};
+    /// # use std::collections::HashMap;
+    /// let branch: Branch<()> = Branch::new("/search")
+    ///     .with(Method::Get.to(|Query(query): Query<HashMap<String, String>>| async move {
+    ///         Response::ok().body(format!("{} parameters", query.len()))
+    ///     }))
+    ///     .on_extraction_error(|error| Response::bad_request().body(format!("invalid search: {}", error)));
+    /// ```
+    ///
+    /// The function also applies to the nested branches, unless they set their own, and covers every callback of the branch (method handlers, defaults and stream handlers). See [on_extraction_error](crate::ServerBuilder::on_extraction_error) for the default behaviour.
+    pub fn on_extraction_error<F: 'static + Fn(Error) -> Response + Send + Sync>(mut self, extraction_error: F) -> Self {
+        let source = self.source.clone();
+        let top_branch = self.get_branch(source).unwrap();
+        top_branch.extraction_error = Some(Arc::new(extraction_error));
+        self
+    }
+
     /// Callback handler for direct stream manipulation
     /// 
     /// ```rust,no_run
@@ -517,58 +863,82 @@ impl<T: Sync + Send> Branch<T> {
     ///     server.run("127.0.0.1:8000").await.unwrap();
     /// }
     /// ```
+    ///
+    /// Besides the stream, the handler accepts extractors, which act over the request that reached the endpoint (an upgrade request, in the case of websockets). This way, path variables, query parameters or cookies can be checked before entering the read loop, and a `Request` argument can be given to the `guard` of a `WebSocketHandshake`. If an extractor fails, a bad request response is sent over the stream.
     #[cfg(feature = "stream")]
     pub fn stream_handler<F: StreamCallback<A> + Send + Sync + 'static, A: Extractor<T>>(mut self, handler: F) -> Self {
         // We get the top node from the current branch
         let source = self.source.clone();
         let top_branch = self.get_branch(source).unwrap();
         top_branch.stream_handler = Some(Arc::new(Box::new(move |req: Request, additional: Arc<Additional<T>>, stream: Stream|  {
-            match <A as Extractor<T>>::extract(&req, additional) {
+            match <A as Extractor<T>>::extract(&req, additional.clone()) {
                 Ok(args) => handler.invoke(stream, args).boxed(),
-                Err(_e) => {
-                    #[cfg(feature = "full_log")]
-                    {
-                        log::error!("extractor error: {}", _e);
-                        let response = _e.as_response();
-                        // We use the stream to send the request
-                        (async move {match stream.response(response).await {
-                            Ok(_) => (),
-                            Err(_e) => {
-                                #[cfg(feature = "full_log")]
-                                log::debug!("stream reply error: {}", _e);
-                            }
-                        };}).boxed()
-                    }
-                    #[cfg(not(feature = "full_log"))]
-                    {
-                        // We use the stream to send the request
-                        (async move {match stream.response(Response::bad_request()).await {
-                            Ok(_) => (),
-                            Err(_e) => {
-                                #[cfg(feature = "full_log")]
-                                log::debug!("stream reply error: {}", _e);
-                            }
-                        };}).boxed()
-                    }
+                Err(e) => {
+                    let response = extraction_error_response(&req, &additional, e);
+                    // We use the stream to send the request
+                    (async move {match stream.response(response).await {
+                        Ok(_) => (),
+                        Err(_e) => {
+                            #[cfg(feature = "full_log")]
+                            log::debug!("stream reply error: {}", _e);
+                        }
+                    };}).boxed()
                 }
             }
         })));
         self
     }
 
+    /// Handles the stream with a [StreamThread](crate::StreamThread), created for each connection
+    ///
+    /// ```rust,no_run
+    /// use cataclysm::{Branch, Stream, StreamThread, http::Path};
+    /// use bytes::BytesMut;
+    ///
+    /// struct Counter {
+    ///     room: String,
+    ///     received: usize
+    /// }
+    ///
+    /// impl StreamThread for Counter {
+    ///     async fn on_data(&mut self, stream: &Stream, buffer: &mut BytesMut) -> bool {
+    ///         self.received += buffer.len();
+    ///         buffer.clear();
+    ///         stream.write_bytes(format!("{}: {} bytes\n", self.room, self.received)).await.is_ok()
+    ///     }
+    /// }
+    ///
+    /// let branch: Branch<()> = Branch::new("/rooms/{:room}").stream_thread(|path: Path<(String,)>| {
+    ///     let (room,) = path.into_inner();
+    ///     Counter {room, received: 0}
+    /// });
+    /// ```
+    ///
+    /// The factory receives an extractor, which works as in [stream_handler](Branch::stream_handler). The connection is read until it gets closed, and every arrival of data is given to [on_data](crate::StreamThread::on_data).
+    #[cfg(feature = "stream")]
+    pub fn stream_thread<F: Fn(A) -> S + Send + Sync + 'static, A: 'static + Extractor<T>, S: StreamThread>(self, factory: F) -> Self {
+        self.stream_handler::<_, (A,)>(move |stream: Stream, args: A| crate::stream_thread::drive(stream, factory(args)))
+    }
+
     /// Turns the Branch into a PureBranch, basically getting rid of the "source" variable, and creating some callbacks.
     ///
     /// Internal use only. It helps because the tree structure won't change after this.
     pub(crate) fn purify(self) -> PureBranch<T> {
+        // The patterns were already compiled one by one, so the set can't fail
+        let pattern_set = RegexSet::new(self.pattern_branches.iter().map(|(pattern, _)| pattern.as_str())).unwrap();
         PureBranch {
             exact_branches: self.exact_branches.into_iter().map(|(base, bb)| (base, bb.purify())).collect(),
-            pattern_branches: self.pattern_branches.into_iter().map(|(base, bb)| (base, bb.purify())).collect(),
+            pattern_set,
+            pattern_branches: self.pattern_branches.into_iter().map(|(_, bb)| bb.purify()).collect(),
             variable_branch: self.variable_branch.map(|(var_id, bb)| (var_id, Box::new(bb.purify()))),
             method_callbacks: self.method_callbacks,
             default_method_callback: self.default_method_callback,
             default_callback: self.default_callback,
             files_callback: self.files_callback,
+            files_index: self.files_index,
             layers: self.layers,
+            max_body_size: self.max_body_size,
+            extraction_error: self.extraction_error,
             #[cfg(feature = "stream")]
             stream_handler: self.stream_handler
         }
@@ -610,11 +980,9 @@ impl<T: Sync + Send> Branch<T> {
 
     /// Clasifies each token from the path in one of the three possibilities.
     fn clasify<A: AsRef<str>>(candidate: A) -> BranchKind {
-        let default_re = Regex::new(r"^\{:.*\}$").unwrap();
-        let regex_re = Regex::new(r"^\{regex:.*\}$").unwrap();
-        if default_re.is_match(candidate.as_ref()) {
+        if DEFAULT_RE.is_match(candidate.as_ref()) {
             BranchKind::Default
-        } else if regex_re.is_match(candidate.as_ref()) {
+        } else if REGEX_RE.is_match(candidate.as_ref()) {
             BranchKind::Pattern
         } else {
             BranchKind::Exact
@@ -629,14 +997,23 @@ enum CallbackInformation<T> {
         tracker: PipelineTrack,
         callback: Arc<CoreFn<T>>,
         layers: Vec<Arc<LayerFn<T>>>,
-        variable_indicators: Vec<bool>
+        variable_indicators: Vec<bool>,
+        /// Tokens of the matched route template, from the innermost branch to the outermost one
+        template: Vec<String>,
+        /// Body size limit of the innermost branch that sets one
+        max_body_size: Option<usize>,
+        /// Extraction error handler of the innermost branch that sets one
+        extraction_error: Option<Arc<ExtractionErrorFn>>
     },
     #[cfg(feature = "stream")]
     StreamHandler {
         #[cfg(feature = "full_log")]
         tracker: PipelineTrack,
         callback: Arc<HandlerFn<T>>,
-        variable_indicators: Vec<bool>
+        variable_indicators: Vec<bool>,
+        template: Vec<String>,
+        max_body_size: Option<usize>,
+        extraction_error: Option<Arc<ExtractionErrorFn>>
     }
 }
 
@@ -654,20 +1031,39 @@ impl<T> CallbackInformation<T> {
         }
     }
 
-    fn update(&mut self, layers: Vec<Arc<LayerFn<T>>>, is_var: bool) {
+    fn update(&mut self, layers: Vec<Arc<LayerFn<T>>>, is_var: bool, token: String, branch_max_body_size: Option<usize>, branch_extraction_error: Option<Arc<ExtractionErrorFn>>) {
         match self {
-            CallbackInformation::ResponseHandler{layers: prev_layers, variable_indicators,..} => {
+            CallbackInformation::ResponseHandler{layers: prev_layers, variable_indicators, template, max_body_size, extraction_error, ..} => {
                 // We append the possible layers from this level
                 prev_layers.extend(layers);
                 variable_indicators.push(is_var);
+                template.push(token);
+                // Inner branches have priority
+                *max_body_size = max_body_size.or(branch_max_body_size);
+                if extraction_error.is_none() {
+                    *extraction_error = branch_extraction_error;
+                }
             },
             #[cfg(feature = "stream")]
-            CallbackInformation::StreamHandler{variable_indicators, ..} => {
+            CallbackInformation::StreamHandler{variable_indicators, template, max_body_size, extraction_error, ..} => {
                 variable_indicators.push(is_var);
+                template.push(token);
+                *max_body_size = max_body_size.or(branch_max_body_size);
+                if extraction_error.is_none() {
+                    *extraction_error = branch_extraction_error;
+                }
             }
         }
     }
 
+    fn max_body_size(&self) -> Option<usize> {
+        match self {
+            CallbackInformation::ResponseHandler{max_body_size, ..} => *max_body_size,
+            #[cfg(feature = "stream")]
+            CallbackInformation::StreamHandler{max_body_size, ..} => *max_body_size
+        }
+    }
+
     #[cfg(feature = "full_log")]
     fn update_tracker<A: AsRef<str>>(&mut self, token: A) {
         match self {
@@ -687,18 +1083,45 @@ impl<T> CallbackInformation<T> {
 /// It is just a cleaner version of the Branch.
 pub(crate) struct PureBranch<T> {
     exact_branches: HashMap<String, PureBranch<T>>,
-    pattern_branches: Vec<(Regex, PureBranch<T>)>,
+    pattern_branches: Vec<PureBranch<T>>,
+    /// Patterns of the `pattern_branches`, in the same order, to match them all in a single pass
+    pattern_set: RegexSet,
     variable_branch: Option<(String, Box<PureBranch<T>>)>,
     method_callbacks: HashMap<Method, Arc<CoreFn<T>>>,
     default_method_callback: Option<Arc<CoreFn<T>>>,
     default_callback: Option<Arc<CoreFn<T>>>,
     files_callback: Option<Arc<CoreFn<T>>>,
+    files_index: Option<PathBuf>,
     layers: Vec<Arc<LayerFn<T>>>,
+    max_body_size: Option<usize>,
+    extraction_error: Option<Arc<ExtractionErrorFn>>,
     #[cfg(feature = "stream")]
     stream_handler: Option<Arc<HandlerFn<T>>>
 }
 
 impl<T> PureBranch<T> {
+    /// Indicates if the file callback should reply to this trail, as it points to a directory
+    fn serves_directory<A: AsRef<str>>(&self, trail: A) -> bool {
+        match &self.files_index {
+            Some(files_location) => {
+                let path = std::path::Path::new(trail.as_ref());
+                is_plain(path) && files_location.join(path).is_dir()
+            },
+            None => false
+        }
+    }
+
+    /// Indicates if the request would be dealt with by a stream handler, which reads the body by itself
+    #[cfg(feature = "stream")]
+    pub(crate) fn is_stream_handler(&self, request: &Request) -> bool {
+        matches!(self.callback_information(request.url().path(), &request.method), Some(CallbackInformation::StreamHandler{..}))
+    }
+
+    /// Gives back the body size limit of the branch that would deal with the request, if it sets one
+    pub(crate) fn max_body_size(&self, request: &Request) -> Option<usize> {
+        self.callback_information(request.url().path(), &request.method).and_then(|c_info| c_info.max_body_size())
+    }
+
     /// Creates the pipeline of futures to be processed by the server
     pub(crate) fn pipeline(&self, request: &mut Request) -> Option<PipelineInfo<T>> {
         // We get the core handler, and the possible layers
@@ -707,14 +1130,17 @@ impl<T> PureBranch<T> {
             let pipeline_track = c_info.tracker();
 
             match c_info {
-                CallbackInformation::ResponseHandler{callback, layers, variable_indicators, ..} => {
+                CallbackInformation::ResponseHandler{callback, layers, variable_indicators, template, extraction_error, ..} => {
                     // We have to update the variable locations
                     request.depth = variable_indicators.len();
 
                     request.variable_indices = variable_indicators
                         .iter().rev().enumerate().filter(|(_idx, v)| **v)
                         .map(|(idx, _v)| idx).collect();
+                    request.route = Some(route_template(&template));
+                    request.extraction_error = extraction_error;
 
+                    // Layers come from the innermost branch to the outermost one, so the last one wraps everything
                     let mut pipeline_layer = Pipeline::Core(Arc::clone(&callback));
                     for function in &layers {
                         pipeline_layer = Pipeline::Layer(Arc::clone(function), Box::new(pipeline_layer));
@@ -727,13 +1153,15 @@ impl<T> PureBranch<T> {
                     })
                 },
                 #[cfg(feature = "stream")]
-                CallbackInformation::StreamHandler{callback, variable_indicators, ..} => {
+                CallbackInformation::StreamHandler{callback, variable_indicators, template, extraction_error, ..} => {
                     // We have to update the variable locations
                     request.depth = variable_indicators.len();
 
                     request.variable_indices = variable_indicators
                         .iter().rev().enumerate().filter(|(_idx, v)| **v)
                         .map(|(idx, _v)| idx).collect();
+                    request.route = Some(route_template(&template));
+                    request.extraction_error = extraction_error;
                     
                     Some(PipelineInfo{
                         #[cfg(feature = "full_log")]
@@ -747,6 +1175,19 @@ impl<T> PureBranch<T> {
         }
     }
 
+    /// Creates the pipeline for the server's fallback handler, wrapped by the layers of this branch
+    pub(crate) fn fallback_pipeline(&self, callback: &Arc<CoreFn<T>>) -> PipelineInfo<T> {
+        let mut pipeline_layer = Pipeline::Core(Arc::clone(callback));
+        for function in &self.layers {
+            pipeline_layer = Pipeline::Layer(Arc::clone(function), Box::new(pipeline_layer));
+        }
+        PipelineInfo {
+            #[cfg(feature = "full_log")]
+            pipeline_track: PipelineTrack::NotFound,
+            pipeline_kind: PipelineKind::NormalPipeline{pipeline: pipeline_layer}
+        }
+    }
+
     /// Gives back the supported methods on each path, in case the branch was found
     pub fn supported_methods<A: AsRef<str>>(&self, trail: A) -> Option<HashSet<Method>> {
         // Tokenizamos la cadena
@@ -760,7 +1201,10 @@ impl<T> PureBranch<T> {
                 return if self.default_callback.is_some() || self.default_method_callback.is_some() {
                     Some(vec![Method::Get, Method::Post, Method::Put, Method::Head, Method::Delete, Method::Patch, Method::Options].into_iter().collect())
                 } else {
-                    let methods: HashSet<_> = self.method_callbacks.keys().map(|m| m.clone()).collect();
+                    let mut methods: HashSet<_> = self.method_callbacks.keys().map(|m| m.clone()).collect();
+                    if self.files_callback.is_some() && self.serves_directory(trimmed_trail) {
+                        methods.insert(Method::Get);
+                    }
                     Some(methods)
                 }
             } else {
@@ -774,12 +1218,9 @@ impl<T> PureBranch<T> {
         if let Some(branch) = self.exact_branches.get(&base) {
             result = branch.supported_methods(rest);
         } else {
-            // Now, O(n) regex pattern matching
-            for (pattern, branch) in self.pattern_branches.iter() {
-                if pattern.is_match(&base) {
-                    result = branch.supported_methods(&rest);
-                    break;
-                }
+            // Now, single pass regex pattern matching. The first declared pattern wins
+            if let Some(idx) = self.pattern_set.matches(&base).iter().next() {
+                result = self.pattern_branches[idx].supported_methods(&rest);
             }
 
             if result.is_none() {
@@ -792,7 +1233,7 @@ impl<T> PureBranch<T> {
 
         if result.is_none() {
             // We check if we are checking out a file, and there is a file callback
-            if std::path::Path::new(trimmed_trail).extension().is_some() {
+            if std::path::Path::new(trimmed_trail).extension().is_some() || self.serves_directory(trimmed_trail) {
                 if self.files_callback.is_some() {
                     result = Some(vec![Method::Get].into_iter().collect());
                 }
@@ -826,7 +1267,10 @@ impl<T> PureBranch<T> {
                         tracker: PipelineTrack::Exact("".to_string()),
                         callback: mc.clone(),
                         layers: self.layers.clone(),
-                        variable_indicators: vec![]
+                        variable_indicators: vec![],
+                        template: vec![],
+                        max_body_size: self.max_body_size,
+                        extraction_error: self.extraction_error.clone()
                     })
                 } else if let Some(dmc) = &self.default_method_callback {
                     Some(CallbackInformation::ResponseHandler {
@@ -834,7 +1278,21 @@ impl<T> PureBranch<T> {
                         tracker: PipelineTrack::UnmatchedMethod("".to_string()),
                         callback: dmc.clone(),
                         layers: self.layers.clone(),
-                        variable_indicators: vec![]
+                        variable_indicators: vec![],
+                        template: vec![],
+                        max_body_size: self.max_body_size,
+                        extraction_error: self.extraction_error.clone()
+                    })
+                } else if let Some(fc) = self.files_callback.as_ref().filter(|_| self.serves_directory(trimmed_trail)) {
+                    Some(CallbackInformation::ResponseHandler {
+                        #[cfg(feature = "full_log")]
+                        tracker: PipelineTrack::File("".to_string()),
+                        callback: fc.clone(),
+                        layers: self.layers.clone(),
+                        variable_indicators: vec![],
+                        template: vec![],
+                        max_body_size: self.max_body_size,
+                        extraction_error: self.extraction_error.clone()
                     })
                 } else if let Some(dc) = &self.default_callback {
                     Some(CallbackInformation::ResponseHandler {
@@ -842,7 +1300,10 @@ impl<T> PureBranch<T> {
                         tracker: PipelineTrack::Default("".to_string()),
                         callback: dc.clone(),
                         layers: self.layers.clone(),
-                        variable_indicators: vec![]
+                        variable_indicators: vec![],
+                        template: vec![],
+                        max_body_size: self.max_body_size,
+                        extraction_error: self.extraction_error.clone()
                     })
                 } else {
                     #[cfg(feature = "stream")]
@@ -852,7 +1313,10 @@ impl<T> PureBranch<T> {
                                 #[cfg(feature = "full_log")]
                                 tracker: PipelineTrack::Stream("".to_string()),
                                 callback: sh.clone(),
-                                variable_indicators: vec![]
+                                variable_indicators: vec![],
+                                template: vec![],
+                                max_body_size: self.max_body_size,
+                                extraction_error: self.extraction_error.clone()
                             })
                         } else {
                             None
@@ -872,24 +1336,25 @@ impl<T> PureBranch<T> {
         let mut result = None;
         // Indicator of a variable part of the route
         let mut is_var = true;
+        // Token of the route template that matched at this level
+        let mut token = base.clone();
 
         if let Some(branch) = self.exact_branches.get(&base) {
             // Hubo un match exacto con rama exacta
             is_var = false;
             result = branch.callback_information(rest, method);
         } else {
-            // Iteramos por todas las ramas que tienen regex, tiempo O(n)
-            for (pattern, branch) in self.pattern_branches.iter() {
-                if pattern.is_match(&base) {
-                    result = branch.callback_information(&rest, method);
-                    break;
-                }
+            // Todas las ramas con regex se revisan en una sola pasada, gana la primera declarada
+            if let Some(idx) = self.pattern_set.matches(&base).iter().next() {
+                result = self.pattern_branches[idx].callback_information(&rest, method);
+                token = format!("{{regex:{}}}", self.pattern_set.patterns()[idx]);
             }
 
             if result.is_none() {
                 // Si hay rama con variable, aquí se llama de inmediato
-                if let Some((_id, branch)) = &self.variable_branch {
+                if let Some((id, branch)) = &self.variable_branch {
                     result = branch.callback_information(rest, method);
+                    token = format!("{{:{}}}", id);
                 }
             }
         }
@@ -897,7 +1362,7 @@ impl<T> PureBranch<T> {
         match result.iter_mut().next() {
             Some(c_info) => {
                 // Hubo una coincidencia, concatenamos capas si es que existen, y añadimos los indicadores de variables
-                c_info.update(self.layers.clone(), is_var);
+                c_info.update(self.layers.clone(), is_var, token, self.max_body_size, self.extraction_error.clone());
 
                 #[cfg(feature = "full_log")]
                 {
@@ -919,14 +1384,17 @@ impl<T> PureBranch<T> {
             },
             None => {
                 // No hubo coincidencia alguna. Podría ser un archivo y el endpoint de archivos estar habilitado
-                if std::path::Path::new(trimmed_trail).extension().is_some() {
+                if std::path::Path::new(trimmed_trail).extension().is_some() || self.serves_directory(trimmed_trail) {
                     if let Some(fc) = &self.files_callback {
                         result = Some(CallbackInformation::ResponseHandler {
                             #[cfg(feature = "full_log")]
                             tracker: PipelineTrack::File("".to_string()),
                             callback: Arc::clone(fc),
                             layers: self.layers.clone(),
-                            variable_indicators: vec![]
+                            variable_indicators: vec![],
+                            template: vec!["*".to_string()],
+                            max_body_size: self.max_body_size,
+                            extraction_error: self.extraction_error.clone()
                         });
                     }
                 }
@@ -939,7 +1407,10 @@ impl<T> PureBranch<T> {
                             tracker: PipelineTrack::Default("".to_string()),
                             callback: Arc::clone(dc),
                             layers: self.layers.clone(),
-                            variable_indicators: vec![]
+                            variable_indicators: vec![],
+                            template: vec!["*".to_string()],
+                            max_body_size: self.max_body_size,
+                            extraction_error: self.extraction_error.clone()
                         });
                     }
                 }
@@ -950,6 +1421,132 @@ impl<T> PureBranch<T> {
     }
 }
 
+/// Joins the tokens of a matched route, which come from the innermost branch to the outermost one
+fn route_template(template: &[String]) -> String {
+    format!("/{}", template.iter().rev().map(|token| token.as_str()).collect::<Vec<_>>().join("/"))
+}
+
+/// Builds the response for a served file, with a default caching policy
+///
+/// Html documents get revalidated on every use, so new deployments are picked up, while the rest of the files can be cached for an hour. Text files are declared as utf-8.
+fn file_response(content: Vec<u8>, content_type: &str) -> Response {
+    let response = Response::file_content(content, content_type);
+    if content_type == "text/html" {
+        response.no_cache()
+    } else {
+        response.max_age(std::time::Duration::from_secs(3_600))
+    }
+}
+
+/// Looks for precompressed versions of a file (`.br` and `.gz` sidecars), picking the first one whose encoding the client accepts
+///
+/// Also indicates if any sidecar exists, in which case the response depends on the `Accept-Encoding` header.
+fn precompressed_sidecar(root: &std::path::Path, file: &std::path::Path, request: &Request) -> (Option<(PathBuf, &'static str)>, bool) {
+    let mut chosen = None;
+    let mut has_sidecars = false;
+    for (encoding, suffix) in [("br", "br"), ("gzip", "gz")] {
+        let mut sidecar = file.as_os_str().to_owned();
+        sidecar.push(".");
+        sidecar.push(suffix);
+        let sidecar = match resolve_inside(root, std::path::Path::new(&sidecar)) {
+            Ok(sidecar) if sidecar.is_file() => sidecar,
+            _ => continue
+        };
+        has_sidecars = true;
+        if chosen.is_none() && accepts_encoding(request, encoding) {
+            chosen = Some((sidecar, encoding));
+        }
+    }
+    (chosen, has_sidecars)
+}
+
+/// Checks the `Accept-Encoding` header for the given encoding, taking into account `q=0` exclusions and the `*` wildcard
+fn accepts_encoding(request: &Request, encoding: &str) -> bool {
+    let mut wildcard = false;
+    for item in request.header_list("Accept-Encoding") {
+        let mut parts = item.split(';');
+        let name = parts.next().unwrap_or("").trim();
+        let quality = parts.find_map(|p| p.trim().strip_prefix("q=")).and_then(|q| q.trim().parse::<f32>().ok()).unwrap_or(1.0);
+        if name.eq_ignore_ascii_case(encoding) {
+            return quality > 0.0
+        } else if name == "*" {
+            wildcard = quality > 0.0;
+        }
+    }
+    wildcard
+}
+
+/// Indicates if the path contains only normal components (no `..`, root or prefixes)
+fn is_plain(path: &std::path::Path) -> bool {
+    path.components().all(|c| matches!(c, std::path::Component::Normal(_)))
+}
+
+/// Canonicalizes the candidate path, verifying that it is still contained in the root path
+///
+/// A candidate that falls outside of the root results in a `PermissionDenied` error.
+fn resolve_inside(root: &std::path::Path, candidate: &std::path::Path) -> std::io::Result<PathBuf> {
+    let root = root.canonicalize()?;
+    let candidate = candidate.canonicalize()?;
+    if candidate.starts_with(&root) {
+        Ok(candidate)
+    } else {
+        Err(std::io::Error::new(std::io::ErrorKind::PermissionDenied, format!("{} is outside of {}", candidate.display(), root.display())))
+    }
+}
+
+/// Maps a path resolution error to the corresponding response
+fn resolution_response(_e: std::io::Error) -> Response {
+    #[cfg(feature = "full_log")]
+    log::debug!("could not resolve requested file, {}", _e);
+    match _e.kind() {
+        std::io::ErrorKind::PermissionDenied => Response::forbidden(),
+        _ => Response::not_found()
+    }
+}
+
+/// Renders an html page with the contents of a directory
+fn directory_listing(directory: &std::path::Path, request_path: &str) -> Response {
+    let entries = match std::fs::read_dir(directory) {
+        Ok(entries) => entries,
+        Err(_) => return Response::internal_server_error()
+    };
+    let mut names: Vec<String> = entries.filter_map(|entry| entry.ok()).map(|entry| {
+        let mut name = entry.file_name().to_string_lossy().to_string();
+        if entry.path().is_dir() {
+            name.push('/');
+        }
+        name
+    }).collect();
+    names.sort();
+
+    let base = if request_path.ends_with('/') {
+        request_path.to_string()
+    } else {
+        format!("{}/", request_path)
+    };
+    let title = html_escape(&base);
+
+    let mut content = format!("<!DOCTYPE html>\n<html>\n<head><meta charset=\"utf-8\"><title>Index of {0}</title></head>\n<body>\n<h1>Index of {0}</h1>\n<ul>\n", title);
+    if base != "/" {
+        content += &format!("<li><a href=\"{}../\">../</a></li>\n", html_escape(&base));
+    }
+    for name in names {
+        let encoded: String = name.bytes().map(|b| match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' | b'/' => (b as char).to_string(),
+            _ => format!("%{:02X}", b)
+        }).collect();
+        content += &format!("<li><a href=\"{}{}\">{}</a></li>\n", html_escape(&base), html_escape(&encoded), html_escape(&name));
+    }
+    content += "</ul>\n</body>\n</html>\n";
+
+    Response::ok().header("Content-Type", "text/html; charset=utf-8").body(content)
+}
+
+/// Escapes the html special characters
+pub(crate) fn html_escape(source: &str) -> String {
+    source.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;").replace('\'', "&#39;")
+}
+
 // Helper trait to split the path, even with regex components that contain a slash
 pub(crate) trait Tokenizable {
     /// A replacement for split("/") that detects regex
@@ -0,0 +1,54 @@
+use crate::http::Request;
+use flate2::read::{GzDecoder, ZlibDecoder, DeflateDecoder};
+use std::io::Read;
+
+/// Reasons for a request body to be rejected while decompressing it
+pub(crate) enum DecompressionError {
+    /// The decompressed body exceeds the maximum body size
+    TooLarge,
+    /// The body is not valid for the declared encoding
+    Invalid
+}
+
+/// Decompresses the body of a request in place, when it comes with a `gzip` or `deflate` content encoding
+///
+/// Other encodings are left untouched. The `Content-Encoding` header is removed and the `Content-Length` one updated, so extractors see a plain body.
+pub(crate) fn decompress_body(request: &mut Request, max_body_size: Option<usize>) -> Result<(), DecompressionError> {
+    let encoding = match request.headers.iter().find(|(k, _)| k.eq_ignore_ascii_case("content-encoding")).and_then(|(_, v)| v.last()) {
+        Some(encoding) => encoding.trim().to_ascii_lowercase(),
+        None => return Ok(())
+    };
+
+    let content = match encoding.as_str() {
+        "gzip" | "x-gzip" => read_limited(GzDecoder::new(request.content.as_slice()), max_body_size)?,
+        // Although the standard asks for the zlib format, some clients send raw deflate
+        "deflate" => match read_limited(ZlibDecoder::new(request.content.as_slice()), max_body_size) {
+            Err(DecompressionError::Invalid) => read_limited(DeflateDecoder::new(request.content.as_slice()), max_body_size)?,
+            other => other?
+        },
+        _ => return Ok(())
+    };
+
+    request.headers.retain(|k, _| !k.eq_ignore_ascii_case("content-encoding") && !k.eq_ignore_ascii_case("content-length"));
+    request.headers.insert("content-length".to_string(), vec![format!("{}", content.len())]);
+    request.content = content;
+    Ok(())
+}
+
+// Reads at most one byte more than the limit, to detect oversized bodies without inflating them completely
+fn read_limited<R: Read>(decoder: R, max_body_size: Option<usize>) -> Result<Vec<u8>, DecompressionError> {
+    let mut content = Vec::new();
+    match max_body_size {
+        Some(max_body_size) => {
+            decoder.take(max_body_size as u64 + 1).read_to_end(&mut content).map_err(|_| DecompressionError::Invalid)?;
+            if content.len() > max_body_size {
+                return Err(DecompressionError::TooLarge);
+            }
+        },
+        None => {
+            let mut decoder = decoder;
+            decoder.read_to_end(&mut content).map_err(|_| DecompressionError::Invalid)?;
+        }
+    }
+    Ok(content)
+}
\ No newline at end of file
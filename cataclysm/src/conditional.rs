@@ -0,0 +1,72 @@
+use crate::{LayerFn, Additional, Pipeline, http::{Request, Response, Method}};
+use futures::future::FutureExt;
+use std::sync::Arc;
+
+/// Layer that answers conditional requests with a `304 Not Modified`
+///
+/// Once the handler produces a successful response to a `GET` or `HEAD` request, its `ETag` and `Last-Modified` headers are compared against the `If-None-Match` and `If-Modified-Since` headers from the request. If the client's copy is still valid, the body is dropped and the status becomes `304`, keeping the rest of the headers.
+///
+/// ```rust,no_run
+/// use cataclysm::{Branch, conditional_layer, http::{Response, Method}};
+///
+/// let branch: Branch<()> = Branch::new("/report")
+///     .with(Method::Get.to(|| async {
+///         Response::ok().header("ETag", "\"v42\"").body("expensive report")
+///     }))
+///     .layer(conditional_layer());
+/// ```
+///
+/// As in the specification, `If-Modified-Since` is ignored when `If-None-Match` is present. Responses with the `no-cache` directive in their `Cache-Control` header are always sent in full.
+pub fn conditional_layer<T: 'static + Sync + Send>() -> LayerFn<T> {
+    Box::new(move |req: Request, pipeline: Box<Pipeline<T>>, additional: Arc<Additional<T>>| {
+        async move {
+            let conditional = matches!(req.method(), Method::Get | Method::Head);
+            let if_none_match = Some(req.header_list("If-None-Match").map(|v| v.to_string()).collect::<Vec<_>>()).filter(|candidates| !candidates.is_empty());
+            let if_modified_since = req.header("If-Modified-Since").map(|v| v.to_string());
+            let response: Response = pipeline.execute(req, additional).await;
+            if !conditional || response.status_code() != 200 || has_no_cache(&response) {
+                return response;
+            }
+
+            let not_modified = match (if_none_match, if_modified_since) {
+                (Some(if_none_match), _) => header(&response, "etag").map(|etag| etag_matches(&if_none_match, etag)).unwrap_or(false),
+                (None, Some(if_modified_since)) => match (parse_http_date(&if_modified_since), header(&response, "last-modified").and_then(parse_http_date)) {
+                    (Some(since), Some(last_modified)) => last_modified <= since,
+                    _ => false
+                },
+                (None, None) => false
+            };
+
+            if not_modified {
+                let mut response = response.body(Vec::new()).with_status(304, "Not Modified");
+                response.remove_header("Content-Type");
+                response
+            } else {
+                response
+            }
+        }.boxed()
+    })
+}
+
+/// Retrieves the first value of a response header, in a case-insensitive way
+fn header<'a>(response: &'a Response, name: &str) -> Option<&'a str> {
+    response.header_values(name).next()
+}
+
+/// Indicates if the response asks not to be reused without revalidation
+fn has_no_cache(response: &Response) -> bool {
+    response.header_list("cache-control").any(|directive| directive.eq_ignore_ascii_case("no-cache"))
+}
+
+/// Weak comparison of the `If-None-Match` candidates against the entity tag of the response
+fn etag_matches(if_none_match: &[String], etag: &str) -> bool {
+    let etag = etag.trim().trim_start_matches("W/");
+    if_none_match.iter().any(|candidate| {
+        candidate == "*" || candidate.trim_start_matches("W/") == etag
+    })
+}
+
+/// Parses a date in the http format, like `Sun, 06 Nov 1994 08:49:37 GMT`
+fn parse_http_date(date: &str) -> Option<chrono::DateTime<chrono::FixedOffset>> {
+    chrono::DateTime::parse_from_rfc2822(date.trim()).ok()
+}
\ No newline at end of file
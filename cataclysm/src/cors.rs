@@ -118,10 +118,7 @@ pub struct Cors {
 }
 
 impl Cors {
-    pub(crate) fn apply(&self, request: &Request, response: &mut Response) {
-        let origin_source = request.headers.get("Origin").map(|o| o.get(0)).flatten().or_else(||
-            request.headers.get("origin").map(|o| o.get(0)).flatten()
-        );
+    pub(crate) fn apply(&self, origin_source: Option<&str>, response: &mut Response) {
         let acao = match &self.origins {
             CorsOrigin::None => None,
             CorsOrigin::All => {
@@ -162,9 +159,7 @@ impl Cors {
 
     /// Computed the preflight response
     pub(crate) fn preflight(&self, request: &Request, methods: &HashSet<Method>) -> Response {
-        let origin_source = request.headers.get("Origin").map(|o| o.get(0)).flatten().or_else(||
-            request.headers.get("origin").map(|o| o.get(0)).flatten()
-        );
+        let origin_source = request.header("Origin");
         let acao = match &self.origins {
             CorsOrigin::None => None,
             CorsOrigin::All => {
@@ -198,7 +193,7 @@ impl Cors {
             // Found allowed origin
             let mut response = Response::no_content();
 
-            let methods = match request.headers.get("Access-Control-Request-Method") {
+            let methods = match request.header("Access-Control-Request-Method") {
                 Some(_) => {
                     if let Some(override_methods) = &self.methods {
                         override_methods.iter()
@@ -216,8 +211,8 @@ impl Cors {
             let headers = if let Some(override_headers) = &self.headers {
                 override_headers.iter().cloned().collect::<Vec<_>>().join(", ")
             } else {
-                match request.headers.get("Access-Control-Request-Headers").map(|acrh| acrh.get(0)).flatten() {
-                    Some(headers) => headers.clone(),
+                match request.header("Access-Control-Request-Headers") {
+                    Some(headers) => headers.to_string(),
                     None => {
                         #[cfg(feature = "full_log")]
                         log::debug!("the Access-Control-Request-Headers field was not found");
@@ -1,5 +1,5 @@
 #[cfg(feature = "full_log")]
-use crate::http::Response;
+use crate::http::{Request, Response};
 
 /// Errors returned by this library
 #[derive(Debug)]
@@ -36,21 +36,60 @@ impl Error {
         Error::Custom(message.into())
     }
 
+    /// Builds the json response for this error, with its detail
     #[cfg(feature = "full_log")]
     pub fn as_response(&self) -> Response {
-        let (mut base_response, content) = match self {
-            Error::Io(e) => (Response::internal_server_error(), ErrorResponse{detail: format!("{}", e)}),
-            Error::Parse(e) => (Response::bad_request(), ErrorResponse{detail: e.to_string()}),
-            Error::Timeout => (Response::bad_request(), ErrorResponse{detail: format!("timeout reached")}),
-            Error::Url(e) => (Response::bad_request(), ErrorResponse{detail: format!("{}", e)}),
-            Error::ExtractionBR(e) => (Response::bad_request(), ErrorResponse{detail: e.to_string()}),
-            Error::ExtractionSE(e) => (Response::internal_server_error(), ErrorResponse{detail: e.to_string()}),
-            Error::Ring(ring::error::Unspecified) => (Response::internal_server_error(), ErrorResponse{detail: "no detail".to_string()}),
-            Error::NoSessionCreator => (Response::internal_server_error(), ErrorResponse{detail: "missconfiguration".to_string()}),
-            Error::Custom(e) => (Response::internal_server_error(), ErrorResponse{detail: e.to_string()})
-        };
+        let (base_response, detail) = self.status_and_detail();
+        Error::json_response(base_response, detail)
+    }
+
+    /// Builds the response for this error in the format that the request prefers
+    ///
+    /// Json, plain text and html are offered, json being the choice when the `Accept` header does not tell (see [negotiate](crate::http::Request::negotiate)). Text based responses always declare the utf-8 charset. This is the response given to failed extractions when no custom handler is set.
+    ///
+    /// ```rust,no_run
+    /// # use cataclysm::{Error, http::{Request, Response}};
+    /// async fn parse_number(req: Request) -> Response {
+    ///     match String::from_utf8(req.body().clone()) {
+    ///         Ok(number) => Response::ok().body(number),
+    ///         Err(e) => Error::ExtractionBR(e.to_string()).as_response_for(&req)
+    ///     }
+    /// }
+    /// ```
+    #[cfg(feature = "full_log")]
+    pub fn as_response_for(&self, request: &Request) -> Response {
+        let (base_response, detail) = self.status_and_detail();
+        match request.negotiate(&["application/json", "text/plain", "text/html"]) {
+            Some("text/plain") => base_response.header("Content-Type", "text/plain; charset=utf-8").body(detail),
+            Some("text/html") => {
+                let title = format!("{} {}", base_response.status.0, crate::branch::html_escape(&base_response.status.1));
+                let page = format!("<!DOCTYPE html>\n<html>\n<head><meta charset=\"utf-8\"><title>{}</title></head>\n<body>\n<h1>{}</h1>\n<p>{}</p>\n</body>\n</html>\n", title, title, crate::branch::html_escape(&detail));
+                base_response.header("Content-Type", "text/html; charset=utf-8").body(page)
+            },
+            _ => Error::json_response(base_response, detail)
+        }
+    }
 
-        let content = match serde_json::to_string(&content) {
+    /// Status of the response that corresponds to the error, and its detail
+    #[cfg(feature = "full_log")]
+    fn status_and_detail(&self) -> (Response, String) {
+        match self {
+            Error::Io(e) if e.kind() == std::io::ErrorKind::NotFound => (Response::not_found(), format!("{}", e)),
+            Error::Io(e) => (Response::internal_server_error(), format!("{}", e)),
+            Error::Parse(e) => (Response::bad_request(), e.to_string()),
+            Error::Timeout => (Response::bad_request(), format!("timeout reached")),
+            Error::Url(e) => (Response::bad_request(), format!("{}", e)),
+            Error::ExtractionBR(e) => (Response::bad_request(), e.to_string()),
+            Error::ExtractionSE(e) => (Response::internal_server_error(), e.to_string()),
+            Error::Ring(ring::error::Unspecified) => (Response::internal_server_error(), "no detail".to_string()),
+            Error::NoSessionCreator => (Response::internal_server_error(), "missconfiguration".to_string()),
+            Error::Custom(e) => (Response::internal_server_error(), e.to_string())
+        }
+    }
+
+    #[cfg(feature = "full_log")]
+    fn json_response(mut base_response: Response, detail: String) -> Response {
+        let content = match serde_json::to_string(&ErrorResponse{detail}) {
             Ok(v) => v,
             Err(_) => {
                 base_response = Response::internal_server_error();
@@ -58,7 +97,7 @@ impl Error {
             }
         };
 
-        base_response.header("Content-Type", "application/json").body(content)
+        base_response.header("Content-Type", "application/json; charset=utf-8").body(content)
     }
 }
 
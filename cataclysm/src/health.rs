@@ -0,0 +1,23 @@
+/// Outcome of a readiness check, see [Branch::readiness_check](crate::Branch::readiness_check)
+///
+/// Implemented for `bool`, and for `Result<(), E>` where the error gets reported in the response.
+pub trait Readiness {
+    /// Gives back the reason why the service is not ready, if any
+    fn readiness(self) -> Result<(), Option<String>>;
+}
+
+impl Readiness for bool {
+    fn readiness(self) -> Result<(), Option<String>> {
+        if self {
+            Ok(())
+        } else {
+            Err(None)
+        }
+    }
+}
+
+impl<E: std::fmt::Display> Readiness for Result<(), E> {
+    fn readiness(self) -> Result<(), Option<String>> {
+        self.map_err(|e| Some(e.to_string()))
+    }
+}
\ No newline at end of file
@@ -1,17 +1,44 @@
 pub use self::method::{Method, MultipleMethod, MethodHandler};
-pub use self::response::{Response};
+pub use self::response::{Response, IntoResponse};
+pub use self::status::StatusCode;
 pub use self::request::{Request, BasicRequest};
+pub use self::version::Version;
+pub use self::content_type::ContentType;
 pub use self::path::{Path};
 pub use self::multipart::{Multipart, File};
+#[cfg(feature = "stream")]
+pub use self::streaming_multipart::{StreamingMultipart, Field};
 pub use self::query::Query;
 pub use self::json::Json;
-pub(crate) use self::mime::MIME_TYPES;
+pub use self::basic_auth::BasicAuth;
+pub use self::bearer::Bearer;
+pub use self::client_ip::ClientIp;
+pub use self::peer_addr::PeerAddr;
+pub use self::matched_route::MatchedRoute;
+pub use self::cookies::Cookies;
+pub use self::context::RequestContext;
+pub(crate) use self::mime::{file_content_type, is_textual};
+pub(crate) use self::request::split_list;
+/// Re-export of the [cookie](https://docs.rs/cookie) crate's `Cookie`, to be used with [Response::cookie](crate::http::Response::cookie)
+pub use cookie::Cookie;
 
 mod method;
 mod response;
+mod status;
 mod request;
+mod version;
+mod content_type;
 mod path;
 mod multipart;
+#[cfg(feature = "stream")]
+mod streaming_multipart;
 mod query;
 mod json;
+mod basic_auth;
+mod bearer;
+mod client_ip;
+mod peer_addr;
+mod matched_route;
+mod cookies;
+mod context;
 mod mime;
\ No newline at end of file
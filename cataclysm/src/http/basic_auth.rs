@@ -0,0 +1,55 @@
+use crate::{Error, Additional, Extractor, http::Request};
+use base64::{Engine, engine::general_purpose};
+use std::sync::Arc;
+
+/// Basic authentication extractor
+///
+/// Parses the `Authorization: Basic ...` header from the request, yielding the decoded credentials
+///
+/// ```rust, no_run
+/// use cataclysm::http::{Response, BasicAuth};
+///
+/// async fn admin(auth: BasicAuth) -> Response {
+///     if auth.username == "admin" && auth.password == "secret" {
+///         Response::ok().body("Welcome!")
+///     } else {
+///         Response::unauthorized().header("WWW-Authenticate", "Basic realm=\"admin\"")
+///     }
+/// }
+/// ```
+///
+/// A missing or malformed header will result always in a bad request response
+#[derive(Clone, Debug)]
+pub struct BasicAuth {
+    /// User name contained in the credentials
+    pub username: String,
+    /// Password contained in the credentials
+    pub password: String
+}
+
+impl<T: Sync> Extractor<T> for BasicAuth {
+    fn extract(req: &Request, _additional: Arc<Additional<T>>) -> Result<Self, Error> {
+        let authorization = req.header("Authorization")
+            .ok_or_else(|| Error::ExtractionBR("missing header Authorization required for basic auth".to_string()))?;
+
+        let (scheme, credentials) = authorization.trim().split_once(' ')
+            .ok_or_else(|| Error::ExtractionBR("malformed Authorization header".to_string()))?;
+
+        if !scheme.eq_ignore_ascii_case("basic") {
+            return Err(Error::ExtractionBR(format!("authorization scheme should be 'Basic' (found {})", scheme)));
+        }
+
+        let decoded = general_purpose::STANDARD.decode(credentials.trim())
+            .map_err(|e| Error::ExtractionBR(format!("basic auth credentials are not valid base64, {}", e)))?;
+        let decoded = String::from_utf8(decoded)
+            .map_err(|e| Error::ExtractionBR(format!("basic auth credentials encoding error, {}", e)))?;
+
+        let (username, password) = decoded.split_once(':')
+            .ok_or_else(|| Error::ExtractionBR("basic auth credentials should be in the form `username:password`".to_string()))?;
+
+        Ok(BasicAuth {
+            username: username.to_string(),
+            password: password.to_string()
+        })
+    }
+}
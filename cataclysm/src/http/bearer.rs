@@ -0,0 +1,58 @@
+use crate::{Error, Additional, Extractor, http::Request};
+use std::sync::Arc;
+use std::ops::{Deref};
+
+/// Bearer token extractor
+///
+/// Reads the `Authorization: Bearer <token>` header from the request, and yields the raw token. The token is not verified in any way.
+///
+/// ```rust, no_run
+/// use cataclysm::http::{Response, Bearer};
+///
+/// async fn protected(token: Bearer) -> Response {
+///     if token.as_str() == "my-api-key" {
+///         Response::ok().body("Welcome!")
+///     } else {
+///         Response::unauthorized()
+///     }
+/// }
+/// ```
+///
+/// A missing or malformed header will result always in a bad request response
+#[derive(Clone, Debug)]
+pub struct Bearer(pub String);
+
+impl Bearer {
+    /// Retrieves the inner token
+    pub fn into_inner(self) -> String {
+        self.0
+    }
+}
+
+impl<T: Sync> Extractor<T> for Bearer {
+    fn extract(req: &Request, _additional: Arc<Additional<T>>) -> Result<Self, Error> {
+        let authorization = req.header("Authorization")
+            .ok_or_else(|| Error::ExtractionBR("missing header Authorization required for bearer token".to_string()))?;
+
+        let authorization = authorization.trim();
+        // The prefix is compared in a case-insensitive way
+        let token = match authorization.get(..7) {
+            Some(prefix) if prefix.eq_ignore_ascii_case("bearer ") => authorization[7..].trim(),
+            _ => return Err(Error::ExtractionBR("authorization scheme should be 'Bearer'".to_string()))
+        };
+
+        if token.is_empty() {
+            Err(Error::ExtractionBR("bearer token is empty".to_string()))
+        } else {
+            Ok(Bearer(token.to_string()))
+        }
+    }
+}
+
+impl Deref for Bearer {
+    type Target = String;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
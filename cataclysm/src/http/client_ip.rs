@@ -0,0 +1,65 @@
+use crate::{Error, Additional, Extractor, http::Request};
+use std::net::IpAddr;
+use std::sync::Arc;
+use std::ops::{Deref};
+
+/// Client ip extractor, aware of reverse proxies
+///
+/// When the server is configured with [trusted_proxies](crate::ServerBuilder::trusted_proxies), the client ip is taken from the `X-Forwarded-For` header (or `X-Real-IP`, if the former is missing), skipping as many trusted hops. Otherwise, it is the ip of the socket connection, the same one given by the `SocketAddr` extractor.
+///
+/// ```rust, no_run
+/// use cataclysm::http::{Response, ClientIp};
+///
+/// async fn whoami(client_ip: ClientIp) -> Response {
+///     Response::ok().body(format!("{}", *client_ip))
+/// }
+/// ```
+///
+/// A forwarded address that can't be parsed as an ip results in a bad request response
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ClientIp(pub IpAddr);
+
+impl ClientIp {
+    /// Retrieves the inner ip address
+    pub fn into_inner(self) -> IpAddr {
+        self.0
+    }
+}
+
+impl<T: Sync> Extractor<T> for ClientIp {
+    fn extract(req: &Request, additional: Arc<Additional<T>>) -> Result<Self, Error> {
+        // Unix domain socket peers have no ip, only the forwarding headers can give one
+        let peer_ip = req.peer_addr().ip();
+        let peer_ip = || peer_ip.map(ClientIp).ok_or_else(|| Error::ExtractionBR("the peer has no ip address".to_string()));
+        if additional.trusted_proxies == 0 {
+            return peer_ip();
+        }
+
+        let header_values = |name: &str| req.header_list(name)
+            .map(|v| v.to_string())
+            .collect::<Vec<_>>();
+
+        // The chain of hops, the last one being the proxy that connected to us
+        let mut hops = header_values("x-forwarded-for");
+        if hops.is_empty() {
+            hops = header_values("x-real-ip");
+        }
+        if hops.is_empty() {
+            return peer_ip();
+        }
+
+        // Entries added by the trusted proxies are the last ones, anything before could be spoofed
+        let index = hops.len().saturating_sub(additional.trusted_proxies);
+        hops[index].parse::<IpAddr>()
+            .map(ClientIp)
+            .map_err(|e| Error::ExtractionBR(format!("forwarded address is not a valid ip, {}", e)))
+    }
+}
+
+impl Deref for ClientIp {
+    type Target = IpAddr;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
\ No newline at end of file
@@ -0,0 +1,76 @@
+use crate::http::multipart::header_parameters;
+
+/// Parsed value of a `Content-Type` header, with its media type and parameters
+///
+/// ```rust
+/// # use cataclysm::http::ContentType;
+/// let content_type = ContentType::parse("Text/HTML; Charset=\"ISO-8859-1\"").unwrap();
+/// assert!(content_type.is("text/html"));
+/// assert_eq!(content_type.mime_type(), "text/html");
+/// assert_eq!(content_type.charset(), Some("ISO-8859-1"));
+/// ```
+///
+/// The media type is kept in lowercase, as well as parameter names. Parameter values are unquoted, but keep their case, as some of them (like `boundary`) are case-sensitive.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ContentType {
+    mime_type: String,
+    parameters: Vec<(String, String)>
+}
+
+impl ContentType {
+    /// Parses the value of a `Content-Type` header
+    ///
+    /// Returns `None` if the media type does not have the `type/subtype` form.
+    pub fn parse<A: AsRef<str>>(source: A) -> Option<ContentType> {
+        let mut parameters = header_parameters(source.as_ref()).into_iter();
+        let (mime_type, _) = parameters.next()?;
+        let (main_type, subtype) = mime_type.split_once('/')?;
+        if main_type.trim().is_empty() || subtype.trim().is_empty() {
+            return None
+        }
+        Some(ContentType {
+            mime_type: mime_type.to_ascii_lowercase(),
+            parameters: parameters.map(|(key, value)| (key.to_ascii_lowercase(), value)).collect()
+        })
+    }
+
+    /// Returns the media type, like `text/html`, without parameters
+    pub fn mime_type(&self) -> &str {
+        &self.mime_type
+    }
+
+    /// Indicates if the media type is the given one, compared in a case-insensitive way
+    pub fn is<A: AsRef<str>>(&self, mime_type: A) -> bool {
+        self.mime_type.eq_ignore_ascii_case(mime_type.as_ref())
+    }
+
+    /// Returns the value of a parameter, looked up in a case-insensitive way
+    pub fn parameter<A: AsRef<str>>(&self, name: A) -> Option<&str> {
+        self.parameters.iter()
+            .find(|(key, _)| key.eq_ignore_ascii_case(name.as_ref()))
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// Returns the `charset` parameter, if any
+    pub fn charset(&self) -> Option<&str> {
+        self.parameter("charset")
+    }
+
+    /// Returns the `boundary` parameter, if any, used by multipart bodies
+    pub fn boundary(&self) -> Option<&str> {
+        self.parameter("boundary")
+    }
+
+    /// Decodes a body according to the declared charset, utf-8 when there is none
+    ///
+    /// Only `utf-8`, `us-ascii` and `iso-8859-1` are supported, `None` is returned for other charsets, or if the body is not valid in the charset.
+    pub(crate) fn decode(&self, body: &[u8]) -> Option<String> {
+        match self.charset() {
+            None => String::from_utf8(body.to_vec()).ok(),
+            Some(charset) if charset.eq_ignore_ascii_case("utf-8") || charset.eq_ignore_ascii_case("utf8") => String::from_utf8(body.to_vec()).ok(),
+            Some(charset) if charset.eq_ignore_ascii_case("us-ascii") => body.is_ascii().then(|| body.iter().map(|b| char::from(*b)).collect()),
+            Some(charset) if charset.eq_ignore_ascii_case("iso-8859-1") || charset.eq_ignore_ascii_case("latin1") => Some(body.iter().map(|b| char::from(*b)).collect()),
+            Some(_) => None
+        }
+    }
+}
\ No newline at end of file
@@ -0,0 +1,69 @@
+use crate::{Error, Additional, Extractor, http::Request};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+/// Per-request context, shared among layers, callbacks and the server
+///
+/// Every clone of a [Request](crate::http::Request) points to the same context, so values written by a layer can be read by outer layers, by the callback (through this extractor) and by the server itself.
+///
+/// ```rust,no_run
+/// use cataclysm::{Branch, Additional, Pipeline, http::{Request, Response, Method}};
+/// use futures::future::FutureExt;
+/// use std::sync::Arc;
+///
+/// let branch = Branch::new("/hello")
+///     .with(Method::Get.to(|| async {Response::ok().body("¡Hola!")}))
+///     .layer(|req: Request, pipeline: Box<Pipeline<()>>, ad: Arc<Additional<()>>| async {
+///         let context = req.context().clone();
+///         let now = std::time::Instant::now();
+///         let response = pipeline.execute(req, ad).await;
+///         // The `%T` log token will use this value
+///         context.set_handler_time(now.elapsed());
+///         response
+///     }.boxed()
+/// );
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct RequestContext {
+    inner: Arc<Mutex<ContextInner>>
+}
+
+#[derive(Debug, Default)]
+struct ContextInner {
+    handler_time: Option<Duration>,
+    values: HashMap<String, String>
+}
+
+impl RequestContext {
+    /// Sets the time taken by the handler, which takes precedence over the server measurement in the logs
+    pub fn set_handler_time(&self, duration: Duration) {
+        self.lock().handler_time = Some(duration);
+    }
+
+    /// Retrieves the handler time, if any layer has set it
+    pub fn handler_time(&self) -> Option<Duration> {
+        self.lock().handler_time
+    }
+
+    /// Stores a value in the context, returning the previous one (if any)
+    pub fn insert<A: Into<String>, B: Into<String>>(&self, key: A, value: B) -> Option<String> {
+        self.lock().values.insert(key.into(), value.into())
+    }
+
+    /// Retrieves a copy of a value from the context
+    pub fn get<A: AsRef<str>>(&self, key: A) -> Option<String> {
+        self.lock().values.get(key.as_ref()).cloned()
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, ContextInner> {
+        // A poisoned context is still usable, as the values are simple ones
+        self.inner.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+impl<T: Sync> Extractor<T> for RequestContext {
+    fn extract(req: &Request, _additional: Arc<Additional<T>>) -> Result<Self, Error> {
+        Ok(req.context().clone())
+    }
+}
\ No newline at end of file
@@ -0,0 +1,83 @@
+use crate::{Error, Additional, Extractor, http::Request};
+use std::collections::HashMap;
+use std::sync::Arc;
+use cookie::Cookie;
+
+/// Cookies extractor
+///
+/// Parses all the cookies contained in the `Cookie` header(s) of the request, percent-decoding their values
+///
+/// ```rust, no_run
+/// use cataclysm::http::{Response, Cookies};
+///
+/// async fn index(cookies: Cookies) -> Response {
+///     match cookies.get("theme") {
+///         Some(theme) => Response::ok().body(format!("Using the {} theme", theme)),
+///         None => Response::ok().body("Using the default theme")
+///     }
+/// }
+/// ```
+///
+/// Cookies that can't be parsed are ignored, so this extractor never fails
+#[derive(Clone, Debug)]
+pub struct Cookies {
+    values: HashMap<String, String>
+}
+
+impl Cookies {
+    /// Retrieves the value of a cookie by its name
+    pub fn get<A: AsRef<str>>(&self, name: A) -> Option<&String> {
+        self.values.get(name.as_ref())
+    }
+
+    /// Returns an iterator over the name-value pairs of the cookies
+    pub fn iter(&self) -> impl Iterator<Item = (&String, &String)> {
+        self.values.iter()
+    }
+
+    /// Number of cookies found in the request
+    pub fn len(&self) -> usize {
+        self.values.len()
+    }
+
+    /// Indicates if the request had no cookies
+    pub fn is_empty(&self) -> bool {
+        self.values.is_empty()
+    }
+}
+
+impl IntoIterator for Cookies {
+    type Item = (String, String);
+    type IntoIter = std::collections::hash_map::IntoIter<String, String>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.values.into_iter()
+    }
+}
+
+impl<T: Sync> Extractor<T> for Cookies {
+    fn extract(req: &Request, _additional: Arc<Additional<T>>) -> Result<Self, Error> {
+        let mut values = HashMap::new();
+        let cookie_headers = req.header_values("Cookie");
+        for cookie_header in cookie_headers {
+            for single_cookie in cookie_header.split(';') {
+                let single_cookie = single_cookie.trim();
+                if single_cookie.is_empty() {
+                    continue;
+                }
+                match Cookie::parse_encoded(single_cookie) {
+                    Ok(cookie) => {
+                        values.insert(cookie.name().to_string(), cookie.value().to_string());
+                    },
+                    Err(_e) => {
+                        #[cfg(feature = "full_log")]
+                        log::debug!("ignoring malformed cookie `{}`, {}", single_cookie, _e);
+                    }
+                }
+            }
+        }
+        Ok(Cookies {
+            values
+        })
+    }
+}
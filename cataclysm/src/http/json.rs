@@ -35,26 +35,23 @@ impl<J> Json<J> {
 
 impl<T: Sync, J: 'static + DeserializeOwned + Send + Sync> Extractor<T> for Json<J> {
     fn extract(req: &Request, _additional: Arc<Additional<T>>) -> Result<Self, Error> {
-        let content_type_header = req.headers.get("content-type").map(|ct| ct.get(0)).flatten().or_else(|| 
-            req.headers.get("Content-Type").map(|ct| ct.get(0)).flatten()
-        );
-        if let Some(content_type_header) = content_type_header {
-            if content_type_header == "application/json" {
-                match String::from_utf8(req.content.clone()) {
-                    Ok(body) => {
+        if let Some(content_type) = req.content_type() {
+            if content_type.is("application/json") {
+                match content_type.decode(&req.content) {
+                    Some(body) => {
                         serde_json::from_str::<J>(&body)
                             .map(|j| Json(j))
                             .map_err(|e| Error::ExtractionBR(format!("json deserialization failure, {}", e)))
                     },
-                    Err(e) => {
-                        Err(Error::ExtractionBR(format!("body encoding error, {}", e)))
+                    None => {
+                        Err(Error::ExtractionBR(format!("body encoding error, expected {}", content_type.charset().unwrap_or("utf-8"))))
                     }
                 }
             } else {
-                Err(Error::ExtractionBR(format!("content-type header should be 'application/json' (found {}) for correct parsing", content_type_header)))
+                Err(Error::ExtractionBR(format!("content-type header should be 'application/json' (found {}) for correct parsing", content_type.mime_type())))
             }
         } else {
-            Err(Error::ExtractionBR(format!("missing header content-type (or Content-Type) required for json parsing")))
+            Err(Error::ExtractionBR(format!("missing or malformed header content-type, required for json parsing")))
         }
     }
 }
@@ -0,0 +1,42 @@
+use crate::{Error, Additional, Extractor, http::Request};
+use std::sync::Arc;
+use std::ops::{Deref};
+
+/// Matched route extractor
+///
+/// Gives the template of the route that matched the request, like `/users/{:id}`, instead of the concrete path. Useful to group metrics by endpoint.
+///
+/// ```rust, no_run
+/// use cataclysm::http::{Response, MatchedRoute};
+///
+/// async fn user(route: MatchedRoute) -> Response {
+///     log::info!("serving {}", *route);
+///     Response::ok()
+/// }
+/// ```
+///
+/// As there is no route for them, requests that reach the server's [not_found](crate::ServerBuilder::not_found) callback result in an internal server error
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct MatchedRoute(pub String);
+
+impl MatchedRoute {
+    /// Retrieves the inner route template
+    pub fn into_inner(self) -> String {
+        self.0
+    }
+}
+
+impl<T: Sync> Extractor<T> for MatchedRoute {
+    fn extract(req: &Request, _additional: Arc<Additional<T>>) -> Result<Self, Error> {
+        req.route().map(|route| MatchedRoute(route.to_string()))
+            .ok_or_else(|| Error::ExtractionSE("no route matched the request".to_string()))
+    }
+}
+
+impl Deref for MatchedRoute {
+    type Target = String;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
\ No newline at end of file
@@ -2,7 +2,7 @@ use futures::future::FutureExt;
 use std::future::Future;
 use std::pin::Pin;
 use std::sync::Arc;
-use crate::{Callback, additional::Additional, Extractor, http::{Response, Request}};
+use crate::{Callback, additional::Additional, Extractor, metafunctions::extraction_error_response, http::{Response, Request}};
 use std::collections::HashSet;
 
 /// Available methods for HTTP Requests
@@ -23,8 +23,12 @@ pub enum Method {
     /// Options method
     Options,
     /// Trace method
+    ///
+    /// Routed like any other method, unless the server refuses it through [allow_trace](crate::ServerBuilder::allow_trace).
     Trace,
     /// Connect method
+    ///
+    /// The server does not open tunnels. Only origin-form targets (`/some/path`) get routed, like any other method, while authority-form targets (`example.com:443`) are rejected with a `400 Bad Request`.
     Connect,
     /// Custom method
     Custom(String)
@@ -76,19 +80,11 @@ impl MultipleMethod {
         MethodHandler{
             methods: self.0,
             handler: Box::new(move |req: Request, additional: Arc<Additional<T>>|  {
-                match <A as Extractor<T>>::extract(&req, additional) {
+                match <A as Extractor<T>>::extract(&req, additional.clone()) {
                     Ok(args) => handler.invoke(args).boxed(),
-                    Err(_e) => {
-                        #[cfg(feature = "full_log")]
-                        {
-                            log::error!("extractor error: {}", _e);
-                            let response = _e.as_response();
-                            (async {response}).boxed()
-                        }
-                        #[cfg(not(feature = "full_log"))]
-                        {
-                            (async {Response::bad_request()}).boxed()
-                        }
+                    Err(e) => {
+                        let response = extraction_error_response(&req, &additional, e);
+                        (async {response}).boxed()
                     }
                 }
             })
@@ -116,19 +112,11 @@ impl Method {
         MethodHandler{
             methods: vec![self].into_iter().collect(),
             handler: Box::new(move |req: Request, additional: Arc<Additional<T>>|  {
-                match <A as Extractor<T>>::extract(&req, additional) {
+                match <A as Extractor<T>>::extract(&req, additional.clone()) {
                     Ok(args) => handler.invoke(args).boxed(),
-                    Err(_e) => {
-                        #[cfg(feature = "full_log")]
-                        {
-                            log::error!("extractor error: {}", _e);
-                            let response = _e.as_response();
-                            (async {response}).boxed()
-                        }
-                        #[cfg(not(feature = "full_log"))]
-                        {
-                            (async {Response::bad_request()}).boxed()
-                        }
+                    Err(e) => {
+                        let response = extraction_error_response(&req, &additional, e);
+                        (async {response}).boxed()
                     }
                 }
             })
@@ -166,6 +154,29 @@ impl Method {
     pub fn and(self, rhs: Method) -> MultipleMethod {
         MultipleMethod(vec![self, rhs].into_iter().collect())
     }
+
+    /// Makes a [MultipleMethod](crate::http::MultipleMethod) with all the standard methods
+    ///
+    /// Custom methods are not included, see [Branch::any](crate::Branch::any) to reply to them as well.
+    /// ```rust
+    /// # use cataclysm::{Branch, http::{Method, Response}};
+    /// let branch: Branch<()> = Branch::new("/echo").with(Method::all().to(|method: Method| async move {
+    ///     Response::ok().body(method.to_str().to_string())
+    /// }));
+    /// ```
+    pub fn all() -> MultipleMethod {
+        MultipleMethod(vec![
+            Method::Get,
+            Method::Post,
+            Method::Put,
+            Method::Head,
+            Method::Delete,
+            Method::Patch,
+            Method::Options,
+            Method::Trace,
+            Method::Connect
+        ].into_iter().collect())
+    }
 }
 
 /// Contains a group of methods, and a handler function.
@@ -5,6 +5,64 @@ lazy_static::lazy_static! {
     pub static ref MIME_TYPES: HashMap<&'static str, &'static str> = read_csv();
 }
 
+/// Gives back the mime type of a file, by its extension, or by its content if sniffing is enabled
+pub(crate) fn file_content_type(extension: &str, content: &[u8], content_sniffing: bool) -> &'static str {
+    match MIME_TYPES.get(extension) {
+        Some(mime_type) => mime_type,
+        None => {
+            if content_sniffing {
+                sniff(content).unwrap_or("application/octet-stream")
+            } else {
+                "application/octet-stream"
+            }
+        }
+    }
+}
+
+/// Indicates if the mime type is a text based one, which needs a charset to be decoded properly
+pub(crate) fn is_textual(content_type: &str) -> bool {
+    content_type.starts_with("text/") || matches!(content_type, "application/json" | "application/ld+json" | "application/xml" | "application/xhtml+xml" | "image/svg+xml")
+}
+
+/// Guesses the mime type of a file by its first bytes (a.k.a., magic numbers)
+pub(crate) fn sniff(content: &[u8]) -> Option<&'static str> {
+    let signatures: [(&[u8], &'static str); 15] = [
+        (b"\x89PNG\r\n\x1a\n", "image/png"),
+        (b"\xff\xd8\xff", "image/jpeg"),
+        (b"GIF87a", "image/gif"),
+        (b"GIF89a", "image/gif"),
+        (b"%PDF-", "application/pdf"),
+        (b"BM", "image/bmp"),
+        (b"\x00\x00\x01\x00", "image/vnd.microsoft.icon"),
+        (b"PK\x03\x04", "application/zip"),
+        (b"\x1f\x8b", "application/gzip"),
+        (b"7z\xbc\xaf\x27\x1c", "application/x-7z-compressed"),
+        (b"Rar!\x1a\x07", "application/vnd.rar"),
+        (b"OggS", "application/ogg"),
+        (b"ID3", "audio/mpeg"),
+        (b"wOFF", "font/woff"),
+        (b"wOF2", "font/woff2")
+    ];
+
+    for (signature, mime_type) in signatures {
+        if content.starts_with(signature) {
+            return Some(mime_type);
+        }
+    }
+
+    // RIFF containers have the format at bytes 8 to 12
+    if content.starts_with(b"RIFF") {
+        match content.get(8..12) {
+            Some(b"WEBP") => return Some("image/webp"),
+            Some(b"WAVE") => return Some("audio/wav"),
+            Some(b"AVI ") => return Some("video/x-msvideo"),
+            _ => ()
+        }
+    }
+
+    None
+}
+
 pub(crate) fn read_csv() -> HashMap<&'static str, &'static str> {
     let result = vec![
         ("aac", "audio/aac"), //AAC audio
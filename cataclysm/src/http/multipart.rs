@@ -56,194 +56,237 @@ impl IntoIterator for Multipart {
 
 impl<T: Sync> Extractor<T> for Multipart {
     fn extract(req: &Request, _additional: Arc<Additional<T>>) -> Result<Self, Error> {
-        if let Some(content_type) = req.headers.get("Content-Type").map(|ct| ct.get(0)).flatten() {
-            if content_type == "application/x-www-form-urlencoded" {
-                match String::from_utf8(req.content.clone()) {
-                    Ok(content) => {
-                        match serde_qs::from_str::<HashMap<String, String>>(&content) {
-                            Ok(pairs) => {
-                                Ok(Multipart {
-                                    raw_files: pairs.into_iter().map(|(k, v)| (k, vec![File{content: v.into(), filename: None, content_type: None}])).collect()
-                                })
-                            },
-                            Err(e) => {
-                                Err(Error::ExtractionBR(format!("the content of the multipart does not seem to be urlencoded, {}", e)))
-                            }
-                        }
-                    },
-                    Err(_) => {
-                        return Err(Error::ExtractionBR(format!("the content of the multipart request should be a utf8 string this time")));
+        let content_type = req.content_type()
+            .ok_or_else(|| Error::ExtractionBR("multipart request requires the content-type header".to_string()))?;
+
+        if content_type.is("application/x-www-form-urlencoded") {
+            let content = content_type.decode(&req.content)
+                .ok_or_else(|| Error::ExtractionBR("the content of the urlencoded request is not valid in its charset".to_string()))?;
+            let pairs = serde_qs::from_str::<HashMap<String, String>>(&content)
+                .map_err(|e| Error::ExtractionBR(format!("the content of the multipart does not seem to be urlencoded, {}", e)))?;
+            return Ok(Multipart {
+                raw_files: pairs.into_iter().map(|(k, v)| (k, vec![File{content: v.into(), filename: None, content_type: None}])).collect()
+            });
+        }
+
+        let boundary = multipart_boundary(req)?;
+
+        // File holder
+        let mut raw_files = HashMap::new();
+
+        for part in split_parts(&req.content, &boundary)? {
+            // The header finishes with an empty line, unless the part has no header at all
+            let (inner_header, multipart_content) = if let Some(content) = part.strip_prefix(b"\r\n").or_else(|| part.strip_prefix(b"\n")) {
+                (&part[..0], content)
+            } else {
+                match (find(part, b"\r\n\r\n"), find(part, b"\n\n")) {
+                    (Some(crlf), Some(lf)) if lf + 1 < crlf => (&part[..lf], &part[lf + 2..]),
+                    (Some(crlf), _) => (&part[..crlf], &part[crlf + 4..]),
+                    (None, Some(lf)) => (&part[..lf], &part[lf + 2..]),
+                    (None, None) => return Err(Error::ExtractionBR("no end of inner-header was found for multipart".to_string()))
+                }
+            };
+
+            // The header needs to be a string
+            let inner_header = std::str::from_utf8(inner_header).map_err(|e| Error::ExtractionBR(format!("incorrect inner header format, {}", e)))?;
+            let part_header = PartHeader::parse(inner_header)?;
+
+            if let Some(content_length) = part_header.content_length {
+                if content_length != multipart_content.len() {
+                    return Err(Error::ExtractionBR("Content-Length of multipart part does not match the size of the content".to_string()))
+                }
+            }
+
+            let file = File {
+                filename: part_header.filename,
+                content_type: part_header.content_type,
+                content: multipart_content.to_vec()
+            };
+
+            let name = part_header.name.ok_or_else(|| Error::ExtractionBR("a name for a part of the multipart was not found".to_string()))?;
+
+            raw_files.entry(name).or_insert_with(Vec::new).push(file);
+        }
+
+        Ok(Multipart {
+            raw_files
+        })
+    }
+}
+
+/// Splits a multipart body into its parts, each one still containing its header
+///
+/// Delimiters are only recognized at the beginning of a line. The line break preceding each delimiter belongs to it, and both `\r\n` and `\n` are accepted. The preamble and epilogue are ignored.
+fn split_parts<'a>(content: &'a [u8], boundary: &str) -> Result<Vec<&'a [u8]>, Error> {
+    let dash_boundary = format!("--{}", boundary).into_bytes();
+
+    let mut parts = Vec::new();
+    // Start of the part being read, if any
+    let mut part_start: Option<usize> = None;
+    let mut from = 0;
+    while let Some(relative_idx) = find(&content[from..], &dash_boundary) {
+        let idx = from + relative_idx;
+        from = idx + 1;
+        if idx != 0 && content[idx - 1] != b'\n' {
+            // Not at the beginning of a line, so it is part of the content
+            continue;
+        }
+
+        let after = idx + dash_boundary.len();
+        let closing = content[after..].starts_with(b"--");
+        // Transport padding might appear before the line break
+        let line_end = find(&content[after..], b"\n").map(|line_end| after + line_end);
+        if !closing && line_end.map(|line_end| content[after..line_end].iter().any(|c| !matches!(c, b' ' | b'\t' | b'\r'))).unwrap_or(false) {
+            // Just a line that starts like the boundary
+            continue;
+        }
+
+        if let Some(start) = part_start.take() {
+            let mut end = idx;
+            if end > start && content[end - 1] == b'\n' {
+                end -= 1;
+                if end > start && content[end - 1] == b'\r' {
+                    end -= 1;
+                }
+            }
+            parts.push(&content[start..end]);
+        }
+
+        if closing {
+            // Closing delimiter, whatever follows is the epilogue
+            return Ok(parts);
+        }
+        let line_end = line_end
+            .ok_or_else(|| Error::ExtractionBR("the content of the multipart request does not finish properly".to_string()))?;
+        part_start = Some(line_end + 1);
+        from = line_end + 1;
+    }
+
+    if part_start.is_none() && parts.is_empty() {
+        Err(Error::ExtractionBR("the content of the multipart request does not start properly".to_string()))
+    } else {
+        Err(Error::ExtractionBR("the content of the multipart request does not finish properly".to_string()))
+    }
+}
+
+/// Finds the first occurrence of a sequence of bytes
+pub(crate) fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack.windows(needle.len()).position(|window| window == needle)
+}
+
+/// Information from the header of each part in a multipart body
+pub(crate) struct PartHeader {
+    pub(crate) name: Option<String>,
+    pub(crate) filename: Option<String>,
+    pub(crate) content_type: Option<String>,
+    pub(crate) content_length: Option<usize>
+}
+
+impl PartHeader {
+    /// Parses the header lines of a part
+    pub(crate) fn parse(inner_header: &str) -> Result<PartHeader, Error> {
+        let mut part_header = PartHeader {
+            name: None,
+            filename: None,
+            content_type: None,
+            content_length: None
+        };
+        for line in inner_header.lines().filter(|line| !line.trim().is_empty()) {
+            let (tag, details) = line.split_once(':').ok_or_else(|| Error::ExtractionBR("malformed header".to_string()))?;
+            let details = details.trim();
+            if tag.eq_ignore_ascii_case("content-disposition") {
+                let mut token_iter = header_parameters(details).into_iter();
+                // The first token needs to be "form-data"
+                if !token_iter.next().map(|(disposition, _)| disposition.eq_ignore_ascii_case("form-data")).unwrap_or(false) {
+                    return Err(Error::ExtractionBR("each document in multiform must be form-data content type".to_string()))
+                }
+                // The extended filename takes precedence, no matter the order
+                let mut extended_filename = None;
+                for (key, value) in token_iter {
+                    if key.eq_ignore_ascii_case("name") {
+                        part_header.name = Some(value);
+                    } else if key.eq_ignore_ascii_case("filename") {
+                        part_header.filename = Some(value);
+                    } else if key.eq_ignore_ascii_case("filename*") {
+                        extended_filename = decode_extended_value(&value).or(extended_filename);
                     }
                 }
-            } else {
-                if let Some((multipart_tag, boundary_pair)) = content_type.trim().split_once(";") {
-                    if multipart_tag == "multipart/form-data" {
-                        if let Some((tag, boundary)) = boundary_pair.trim().split_once("=") {
-                            if tag == "boundary" {
-                                // We create a pair of iterators, subsequent
-                                let mut main_iter = req.content.iter().zip(req.content.iter().skip(1)).enumerate();
-                                let mut parts: Vec<&[u8]> = Vec::new();
-    
-                                let mut previous = 0;
-                                loop {
-                                    if let Some((idx, (one, two))) = main_iter.next() {
-                                        if one == two && two == &b'-' {
-                                            // Let's see if this is a boundary
-                                            if idx + 2 + boundary.len() < req.content.len() {
-                                                // We extract it
-                                                if req.content.get(idx+2..idx+2+boundary.len()) == Some(boundary.as_bytes()) {
-                                                    // We advance the main_iter by the length of the boundary, and the remaining hyphen
-                                                    main_iter.nth(1 + boundary.len());
-    
-                                                    // We add it to the parts vector
-                                                    parts.push(req.content.get(previous..idx).ok_or_else(|| Error::ExtractionBR(format!("internal error 1")))?);
-    
-                                                    previous = idx + 2 + boundary.len();
-                                                }
-                                            }
-                                        }
-                                        // Else, we do nothing
-                                    } else {
-                                        break;
-                                    }
-                                }
-    
-                                // The first token needs to contain nothing
-                                if parts.len() > 0 && parts[0].is_empty() {
-                                   parts.drain(0..1); 
-                                } else {
-                                    return Err(Error::ExtractionBR(format!("the content of the multipart request does not start properly")));
-                                }
-    
-                                // If this multiform is properly formatted, then it needs to finish in `--\r\n`
-                                if !(previous < req.content.len() && req.content.get(previous..req.content.len()).unwrap() == vec![b'-', b'-', b'\r', b'\n']) {
-                                    return Err(Error::ExtractionBR(format!("the content of the multipart request does not finish properly")));
-                                }
-    
-                                // Now, for each token, we will remove the first and last 2 characters, which must be `\r\n`
-                                for (idx, part) in parts.iter_mut().enumerate() {
-                                    if part.len() < 4 || part.get(0..2) != Some(&[b'\r', b'\n']) || part.get(part.len()-2..part.len()) != Some(&[b'\r', b'\n']) {
-                                        return Err(Error::ExtractionBR(format!("part {} of the multipart is not properly finished or started", idx)));
-                                    }
-                                    *part = part.get(2..part.len()-2).ok_or_else(|| Error::ExtractionBR(format!("internal error 2")))?;
-                                }
-    
-                                // File holder
-                                let mut raw_files = HashMap::new();
-    
-                                // Now, we will turn each part into a file
-                                for part in parts {
-                                    let secondary_iter = part.iter().zip(part.iter().skip(2)).enumerate();
-                                    let mut split_index = None;
-                                    for (idx, (a, b)) in secondary_iter {
-                                        if a==b && b==&b'\n' && idx > 0 && part[idx-1] == b'\r' && part[idx+1] == b'\r' {
-                                            split_index = Some(idx);
-                                            break;
-                                        }
-                                    }
-    
-                                    let split_index = split_index.ok_or(Error::ExtractionBR(format!("no end of inner-header was found for multipart")))?;
-    
-                                    // We split one character before, because of the `\r`. This operation is safe, due to the secondary_iter search
-                                    let (inner_header, inner_content) = part.split_at(split_index - 1);
-                                    // The header needs to be a string
-                                    let inner_header = String::from_utf8(inner_header.to_vec()).map_err(|e| Error::ExtractionBR(format!("incorrect inner header format, {}", e)))?;
-                                    // We have to remove the `\r\n\r\n` that is at the beginning of the remaining bytes
-                                    let (_, inner_content) = inner_content.split_at(4);
-    
-                                    let mut multipart_name: Option<&str> = None;
-                                    let mut multipart_filename: Option<&str> = None;
-                                    let mut multipart_content_type: Option<String> = None;
-                                    let multipart_content = inner_content.to_vec();
-    
-                                    for line in inner_header.split("\r\n") {
-                                        if let Some((tag, details)) = line.split_once(": ") {
-                                            match tag {
-                                                "Content-Disposition" => {
-                                                    let mut token_iter = details.split("; ");
-                                                    // The first token needs to be "form-data"
-                                                    if let Some(form_data_candidate) = token_iter.next() {
-                                                        if form_data_candidate != "form-data" {
-                                                            return Err(Error::ExtractionBR(format!("each document in multiform must be form-data content type")))
-                                                        }
-    
-                                                        for remaining_token in token_iter {
-                                                            if let Some((key, value)) = remaining_token.split_once("=") {
-                                                                if key == "name" {
-                                                                    // We need to remove the surrounding quoted
-                                                                    if let Some(unquoted) = value.strip_prefix("\"").map(|v| v.strip_suffix("\"")).flatten() {
-                                                                        multipart_name = Some(unquoted);
-                                                                    } else {
-                                                                        #[cfg(feature = "full_log")]
-                                                                        log::debug!("multipart field will be ignored as `name` had no surrounding quotes")
-                                                                    }
-                                                                } else if key == "filename" {
-                                                                    // We need to remove the surrounding quoted
-                                                                    if let Some(unquoted) = value.strip_prefix("\"").map(|v| v.strip_suffix("\"")).flatten() {
-                                                                        multipart_filename = Some(unquoted);
-                                                                    } else {
-                                                                        #[cfg(feature = "full_log")]
-                                                                        log::debug!("multipart `filename` will be ignored as it had no surrounding quotes")
-                                                                    }
-                                                                }
-                                                            }
-                                                        }
-                                                    } else {
-                                                        return Err(Error::ExtractionBR(format!("Content-Disposition header seems to be empty")))
-                                                    }
-                                                },
-                                                "Content-Type" => {
-                                                    multipart_content_type = Some(details.into());
-                                                },
-                                                "Content-Length" => {
-                                                    match details.parse::<usize>() {
-                                                        Ok(val) => {
-                                                            if val != multipart_content.len() {
-                                                                return Err(Error::ExtractionBR(format!("Content-Length of multipart part does not match the size of the content")))
-                                                            }
-                                                        },
-                                                        Err(e) => {
-                                                            return Err(Error::ExtractionBR(format!("Content-Length of multipart part could not be parse as an integer, {}", e)))
-                                                        }
-                                                    }
-                                                },
-                                                // Any other type of header will be ignored
-                                                _ => ()
-                                            }
-                                        } else {
-                                            return Err(Error::ExtractionBR(format!("malformed header")))
-                                        }
-                                    }
-    
-                                    let file = File {
-                                        filename: multipart_filename.map(|s| s.to_string()),
-                                        content_type: multipart_content_type.map(|s| s.to_string()),
-                                        content: multipart_content
-                                    };
-    
-                                    let name = multipart_name.ok_or_else(|| Error::ExtractionBR(format!("a name for a part of the multipart was not found")))?.to_string();
-                    
-                                    raw_files.entry(name).or_insert_with(|| Vec::new()).push(file);
-                                }
-                                
-                                Ok(Multipart {
-                                    raw_files
-                                })
-                            } else {
-                                Err(Error::ExtractionBR(format!("boundary tag was not found")))
-                            }
-                        } else {
-                            Err(Error::ExtractionBR(format!("the boundary should be specified as `boundary=???`")))
-                        }
-                    } else {
-                        Err(Error::ExtractionBR(format!("multipart content-type must be multipart/form-data (received `{}`)", multipart_tag)))
+                if extended_filename.is_some() {
+                    part_header.filename = extended_filename;
+                }
+            } else if tag.eq_ignore_ascii_case("content-type") {
+                part_header.content_type = Some(details.to_string());
+            } else if tag.eq_ignore_ascii_case("content-length") {
+                part_header.content_length = Some(details.parse::<usize>().map_err(|e| Error::ExtractionBR(format!("Content-Length of multipart part could not be parse as an integer, {}", e)))?);
+            }
+        }
+        Ok(part_header)
+    }
+}
+
+/// Splits the value of a header like `Content-Disposition` or `Content-Type` into its parameters
+///
+/// The disposition (or media) type comes first, with an empty value. Quoted values may contain semicolons and escaped characters, and get unquoted.
+pub(crate) fn header_parameters(details: &str) -> Vec<(String, String)> {
+    let mut parameters = Vec::new();
+    let mut chars = details.chars().peekable();
+    loop {
+        let mut key = String::new();
+        while let Some(c) = chars.next_if(|c| *c != '=' && *c != ';') {
+            key.push(c);
+        }
+        let mut value = String::new();
+        if chars.next_if_eq(&'=').is_some() {
+            while chars.next_if(|c| c.is_whitespace()).is_some() {}
+            if chars.next_if_eq(&'"').is_some() {
+                while let Some(c) = chars.next() {
+                    match c {
+                        '"' => break,
+                        '\\' => value.extend(chars.next()),
+                        c => value.push(c)
                     }
-                } else {
-                    Err(Error::ExtractionBR(format!("multipart content-type requires the multipart/form-data tag, and a boundary")))
                 }
+                // Anything between the closing quote and the next parameter is ignored
+                while chars.next_if(|c| *c != ';').is_some() {}
+            } else {
+                while let Some(c) = chars.next_if(|c| *c != ';') {
+                    value.push(c);
+                }
+                value = value.trim_end().to_string();
             }
-        } else {
-            Err(Error::ExtractionBR(format!("multipart request requires the content-type header")))
         }
+        if !key.trim().is_empty() {
+            parameters.push((key.trim().to_string(), value));
+        }
+        if chars.next().is_none() {
+            break parameters;
+        }
+    }
+}
+
+/// Decodes a parameter value in the extended notation of RFC 5987, like `UTF-8''na%C3%AFve.txt`
+fn decode_extended_value(value: &str) -> Option<String> {
+    let mut tokens = value.splitn(3, '\'');
+    let (charset, _language, encoded) = (tokens.next()?, tokens.next()?, tokens.next()?);
+    let decoded = percent_encoding::percent_decode_str(encoded);
+    if charset.eq_ignore_ascii_case("utf-8") {
+        decoded.decode_utf8().ok().map(|decoded| decoded.to_string())
+    } else if charset.eq_ignore_ascii_case("iso-8859-1") {
+        Some(decoded.map(char::from).collect())
+    } else {
+        None
+    }
+}
+
+/// Retrieves the boundary of a `multipart/form-data` request
+pub(crate) fn multipart_boundary(req: &Request) -> Result<String, Error> {
+    let content_type = req.content_type()
+        .ok_or_else(|| Error::ExtractionBR("multipart request requires the content-type header".to_string()))?;
+    if !content_type.is("multipart/form-data") {
+        return Err(Error::ExtractionBR(format!("multipart content-type must be multipart/form-data (received `{}`)", content_type.mime_type())));
     }
+    content_type.boundary()
+        .filter(|boundary| !boundary.is_empty())
+        .map(|boundary| boundary.to_string())
+        .ok_or_else(|| Error::ExtractionBR("boundary tag was not found".to_string()))
 }
\ No newline at end of file
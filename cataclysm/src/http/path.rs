@@ -6,6 +6,16 @@ use std::ops::{Deref, DerefMut};
 /// Token extractor from the path from a request
 ///
 /// The `Path` extractors allows for tuple extraction from a path with variable or regex components.
+///
+/// Both kinds of components are captured, in the order they appear in the route.
+///
+/// ```rust,no_run
+/// # use cataclysm::{Branch, http::{Response, Method, Path}};
+/// let branch: Branch<()> = Branch::new("/{regex:^\\d+$}/{:slug}").with(Method::Get.to(|path: Path<(u32, String)>| async move {
+///     let (id, slug) = path.into_inner();
+///     Response::ok().body(format!("post {} ({})", id, slug))
+/// }));
+/// ```
 pub struct Path<T>(pub T);
 
 // Convenience deref implementation
@@ -0,0 +1,68 @@
+use crate::{Error, Additional, Extractor, http::Request};
+use std::net::{IpAddr, SocketAddr};
+use std::sync::Arc;
+
+/// Address of the peer that sent a request
+///
+/// Connections accepted by [run](crate::Server::run) come from an ip address, while the ones accepted by [run_unix](crate::Server::run_unix) come from a Unix domain socket, which usually has no name at all. It works as an extractor as well.
+///
+/// ```rust, no_run
+/// use cataclysm::http::{Response, PeerAddr};
+///
+/// async fn whoami(peer: PeerAddr) -> Response {
+///     match peer.ip() {
+///         Some(ip) => Response::ok().body(format!("{}", ip)),
+///         None => Response::ok().body("local peer")
+///     }
+/// }
+/// ```
+#[derive(Clone, Debug)]
+pub enum PeerAddr {
+    /// The peer connected through tcp
+    Tcp(SocketAddr),
+    /// The peer connected through a Unix domain socket
+    #[cfg(unix)]
+    Unix(tokio::net::unix::SocketAddr)
+}
+
+impl PeerAddr {
+    /// Returns the socket address of the peer, if it connected through tcp
+    pub fn as_socket_addr(&self) -> Option<SocketAddr> {
+        match self {
+            PeerAddr::Tcp(addr) => Some(*addr),
+            #[cfg(unix)]
+            PeerAddr::Unix(_) => None
+        }
+    }
+
+    /// Returns the ip address of the peer, if it connected through tcp
+    pub fn ip(&self) -> Option<IpAddr> {
+        self.as_socket_addr().map(|addr| addr.ip())
+    }
+}
+
+impl From<SocketAddr> for PeerAddr {
+    fn from(addr: SocketAddr) -> PeerAddr {
+        PeerAddr::Tcp(addr)
+    }
+}
+
+/// Unix peers are shown as `unix:` followed by their path, if they have one, like reverse proxies usually do
+impl std::fmt::Display for PeerAddr {
+    fn fmt(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            PeerAddr::Tcp(addr) => write!(formatter, "{}", addr),
+            #[cfg(unix)]
+            PeerAddr::Unix(addr) => match addr.as_pathname() {
+                Some(path) => write!(formatter, "unix:{}", path.display()),
+                None => write!(formatter, "unix:")
+            }
+        }
+    }
+}
+
+impl<T: Sync> Extractor<T> for PeerAddr {
+    fn extract(req: &Request, _additional: Arc<Additional<T>>) -> Result<Self, Error> {
+        Ok(req.peer_addr().clone())
+    }
+}
@@ -1,14 +1,28 @@
 use std::collections::HashMap;
-use crate::{Error, http::Method};
+use crate::{Error, http::{Method, RequestContext, Version, ContentType, PeerAddr}, metafunctions::ExtractionErrorFn};
+use std::sync::Arc;
 use url::Url;
 
 /// Contains the data from an http request.
+///
+/// The request itself is an extractor, useful when several parts of it are needed at once. Extractors must own their data, so the callback receives a copy of the request (body included).
+///
+/// ```rust,no_run
+/// use cataclysm::http::{Request, Response, Method};
+///
+/// async fn inspect(req: Request) -> Response {
+///     let user_agent = req.header("User-Agent").unwrap_or_default();
+///     Response::ok().body(format!("{} {} ({} bytes) from {}", req.method().to_str(), req.path(), req.body().len(), user_agent))
+/// }
+/// ```
 #[derive(Clone)]
 pub struct Request {
     /// Method that the request used
     pub(crate) method: Method,
     /// Route that the user requested
     pub(crate) url: Url,
+    /// Protocol version from the request line
+    pub(crate) version: Version,
     /// Variable positions, if any (set by the pure branch)
     pub(crate) variable_indices: Vec<usize>,
     /// How deep in the tree this endpoint finds itself (set by the pure branch)
@@ -18,24 +32,195 @@ pub struct Request {
     /// Header size in bytes
     pub(crate) header_size: usize,
     /// Address from the request
-    pub(crate) addr: std::net::SocketAddr,
-    pub(crate) content: Vec<u8>
+    pub(crate) addr: PeerAddr,
+    pub(crate) content: Vec<u8>,
+    /// Context shared by all the clones of this request
+    pub(crate) context: RequestContext,
+    /// Prefix removed from the path, if the server has a base path
+    pub(crate) base_path: Option<String>,
+    /// Template of the route that matched (set by the pure branch)
+    pub(crate) route: Option<String>,
+    /// Indicates if the request targets the whole server, as in `OPTIONS *`
+    pub(crate) asterisk_form: bool,
+    /// Response for failed extractions of the branch that matched (set by the pure branch)
+    pub(crate) extraction_error: Option<Arc<ExtractionErrorFn>>,
+    /// Number of reverse proxies whose forwarding headers can be trusted (set by the server)
+    pub(crate) trusted_proxies: usize
 }
 
 impl Request {
-    /// Returns the [Url](https://docs.rs/url/latest/url/struct.Url.html) object for this request
+    /// Returns the method of this request
     pub fn method(&self) -> &Method {
         &self.method
     }
+
+    /// Returns the http version of this request
+    pub fn version(&self) -> Version {
+        self.version
+    }
     
     /// Returns the [Url](https://docs.rs/url/latest/url/struct.Url.html) object for this request
     pub fn url(&self) -> &Url {
         &self.url
     }
 
+    /// Returns a mutable reference to the [Url](https://docs.rs/url/latest/url/struct.Url.html) object for this request
+    ///
+    /// Only useful before routing, see [rewrite](crate::ServerBuilder::rewrite).
+    pub fn url_mut(&mut self) -> &mut Url {
+        &mut self.url
+    }
+
+    /// Returns the path of this request, still percent-encoded
+    pub fn path(&self) -> &str {
+        self.url.path()
+    }
+
+    /// Returns the first value of a header, looked up in a case-insensitive way
+    ///
+    /// ```rust,no_run
+    /// # use cataclysm::http::{Request, Response};
+    /// async fn agent(req: Request) -> Response {
+    ///     Response::ok().body(req.header("user-agent").unwrap_or("unknown").to_string())
+    /// }
+    /// ```
+    pub fn header<A: AsRef<str>>(&self, name: A) -> Option<&str> {
+        self.header_values(name).next()
+    }
+
+    /// Returns every value of a header, looked up in a case-insensitive way
+    ///
+    /// Values sent under different casings of the same name (like `Cookie` and `cookie`) are all returned, in the order in which they were received.
+    ///
+    /// ```rust,no_run
+    /// # use cataclysm::http::{Request, Response};
+    /// async fn languages(req: Request) -> Response {
+    ///     Response::ok().body(req.header_values("accept-language").collect::<Vec<_>>().join(", "))
+    /// }
+    /// ```
+    pub fn header_values<A: AsRef<str>>(&self, name: A) -> impl Iterator<Item = &str> {
+        self.headers.iter()
+            .filter(move |(key, _)| key.eq_ignore_ascii_case(name.as_ref()))
+            .flat_map(|(_, values)| values.iter())
+            .map(|value| value.as_str())
+    }
+
+    /// Returns the items of a comma separated header, like `Accept-Encoding` or `X-Forwarded-For`
+    ///
+    /// Items from every value of the header are returned in order, trimmed, and empty ones are skipped. Commas inside quoted strings do not split items.
+    ///
+    /// ```rust,no_run
+    /// # use cataclysm::http::{Request, Response};
+    /// async fn hops(req: Request) -> Response {
+    ///     Response::ok().body(format!("{} hops", req.header_list("x-forwarded-for").count()))
+    /// }
+    /// ```
+    pub fn header_list<A: AsRef<str>>(&self, name: A) -> impl Iterator<Item = &str> {
+        self.header_values(name).flat_map(split_list)
+    }
+
+    /// Returns the parsed `Content-Type` header, if present and well formed
+    ///
+    /// ```rust,no_run
+    /// # use cataclysm::http::{Request, Response};
+    /// async fn upload(req: Request) -> Response {
+    ///     match req.content_type() {
+    ///         Some(content_type) if content_type.is("text/csv") => Response::ok(),
+    ///         _ => Response::bad_request()
+    ///     }
+    /// }
+    /// ```
+    pub fn content_type(&self) -> Option<ContentType> {
+        self.header("Content-Type").and_then(ContentType::parse)
+    }
+
+    /// Returns all the headers of this request, with every value each one received
+    ///
+    /// Keys are kept in lowercase, as header names are case-insensitive, so repeated headers share a single entry with their values in the order in which they were received. Still, [header](Request::header) is usually more convenient for lookups.
+    pub fn headers(&self) -> &HashMap<String, Vec<String>> {
+        &self.headers
+    }
+
+    /// Returns the base path that was removed from the path of the request, or an empty string
+    ///
+    /// Handy to create links that work when the server is mounted under a [base_path](crate::ServerBuilder::base_path).
+    ///
+    /// ```rust,no_run
+    /// # use cataclysm::http::{Request, Response};
+    /// async fn index(req: Request) -> Response {
+    ///     Response::ok().body(format!("<a href=\"{}/users\">Users</a>", req.base_path()))
+    /// }
+    /// ```
+    pub fn base_path(&self) -> &str {
+        self.base_path.as_deref().unwrap_or_default()
+    }
+
+    /// Returns the template of the route that matched the request, like `/users/{:id}`
+    ///
+    /// Variable and pattern tokens are kept as declared in the branch, and a trailing `*` indicates that a [files](crate::Branch::files) or [defaults_to](crate::Branch::defaults_to) callback took the rest of the path. The [base_path](crate::ServerBuilder::base_path) is not included. It is `None` before routing, or when no route matched.
+    pub fn route(&self) -> Option<&str> {
+        self.route.as_deref()
+    }
+
+    /// Returns the raw query string of the request, without the leading `?`
+    pub fn query(&self) -> Option<&str> {
+        self.url.query()
+    }
+
+    /// Returns the key-value pairs of the query, in order and percent-decoded
+    ///
+    /// Keys that appear several times produce several pairs. For a typed alternative, see the [Query](crate::http::Query) extractor.
+    pub fn query_pairs(&self) -> Vec<(String, String)> {
+        self.url.query_pairs().map(|(key, value)| (key.into_owned(), value.into_owned())).collect()
+    }
+
+    /// Returns the first value of a query parameter, percent-decoded
+    ///
+    /// ```rust,no_run
+    /// # use cataclysm::http::{Request, Response};
+    /// async fn search(req: Request) -> Response {
+    ///     match req.query_param("q") {
+    ///         Some(term) => Response::ok().body(format!("searching for {}", term)),
+    ///         None => Response::bad_request()
+    ///     }
+    /// }
+    /// ```
+    pub fn query_param<A: AsRef<str>>(&self, name: A) -> Option<String> {
+        self.url.query_pairs().find(|(key, _)| key == name.as_ref()).map(|(_, value)| value.into_owned())
+    }
+
     /// Returns the IP address from which this call has been made
+    ///
+    /// Calls received through a Unix domain socket (see [run_unix](crate::Server::run_unix)) have no ip address, so they report an unspecified one, `0.0.0.0:0`. Use [peer_addr](Request::peer_addr) to tell them apart.
     pub fn address(&self) -> std::net::SocketAddr {
-        self.addr
+        self.addr.as_socket_addr().unwrap_or_else(|| std::net::SocketAddr::from(([0, 0, 0, 0], 0)))
+    }
+
+    /// Returns the address of the peer that sent the request, either an ip address or a Unix domain socket
+    pub fn peer_addr(&self) -> &PeerAddr {
+        &self.addr
+    }
+
+    /// Returns the scheme that the client used to reach the server, either `http` or `https`
+    ///
+    /// ```rust,no_run
+    /// # use cataclysm::http::{Request, Response};
+    /// async fn canonical(req: Request) -> Response {
+    ///     let host = req.header("Host").unwrap_or("localhost");
+    ///     Response::ok().body(format!("{}://{}{}", req.scheme(), host, req.path()))
+    /// }
+    /// ```
+    ///
+    /// The server itself only speaks plain http, so this is `https` only when a reverse proxy terminates tls and reports it in the `X-Forwarded-Proto` header. As with the [ClientIp](crate::http::ClientIp) extractor, the header is ignored unless the server is configured with [trusted_proxies](crate::ServerBuilder::trusted_proxies), and only the entry added by the outermost trusted proxy is considered.
+    pub fn scheme(&self) -> &str {
+        if self.trusted_proxies == 0 {
+            return "http"
+        }
+        let protocols = self.header_list("X-Forwarded-Proto").collect::<Vec<_>>();
+        match protocols.get(protocols.len().saturating_sub(self.trusted_proxies)) {
+            Some(protocol) if protocol.eq_ignore_ascii_case("https") => "https",
+            _ => "http"
+        }
     }
 
     /// Returns the body as bytes of the content
@@ -43,32 +228,102 @@ impl Request {
         &self.content
     }
 
-    pub(crate) fn parse(mut source: Vec<u8>, addr: std::net::SocketAddr) -> Result<Request, Error> {
-        // http call should have at least 3 bytes. For sure
-        let (one, two) = (source.iter(), source.iter().skip(2));
+    /// Returns the per-request context, shared among layers, callbacks and the server
+    pub fn context(&self) -> &RequestContext {
+        &self.context
+    }
+
+    /// Picks the best content type among the offered ones, according to the `Accept` header
+    ///
+    /// Quality values (`q=`) are taken into account, and ties are resolved by the order of the offered types. If the request has no `Accept` header, or it is malformed, the first offered type is returned. `None` is returned only when none of the offered types is acceptable for the client.
+    ///
+    /// ```rust,no_run
+    /// # use cataclysm::http::{Request, Response};
+    /// async fn index(req: Request) -> Response {
+    ///     match req.negotiate(&["application/json", "text/html"]) {
+    ///         Some("application/json") => Response::ok().header("Content-Type", "application/json").body("{\"hello\": \"world\"}"),
+    ///         Some(_) => Response::ok().body("<h1>Hello, world</h1>"),
+    ///         None => Response::from((406, "Not Acceptable"))
+    ///     }
+    /// }
+    /// ```
+    pub fn negotiate<'a>(&self, offered: &[&'a str]) -> Option<&'a str> {
+        let accept_values = self.header_list("Accept").collect::<Vec<_>>();
+        if accept_values.is_empty() {
+            return offered.first().copied()
+        }
 
-        let mut split_index = None;
-        for (idx, (a, b)) in one.zip(two).enumerate() {
-            if a==b && b==&b'\n' && idx > 0 && source[idx-1] == b'\r' && source[idx+1] == b'\r' {
-                split_index = Some(idx);
-                break;
+        // We collect the media ranges with their quality value
+        let mut media_ranges = Vec::new();
+        for media_range in accept_values {
+            let mut tokens = media_range.split(';').map(|t| t.trim());
+            let range = match tokens.next() {
+                Some(range) if !range.is_empty() => range,
+                _ => continue
+            };
+            let (range_type, range_subtype) = match range.split_once('/') {
+                Some(pair) => pair,
+                None => return offered.first().copied()
+            };
+            let mut quality = 1.0f32;
+            for parameter in tokens {
+                if let Some((key, value)) = parameter.split_once('=') {
+                    if key.trim() == "q" {
+                        quality = match value.trim().parse::<f32>() {
+                            Ok(q) if (0.0..=1.0).contains(&q) => q,
+                            _ => return offered.first().copied()
+                        };
+                    }
+                }
             }
+            media_ranges.push((range_type, range_subtype, quality));
         }
 
-        let split_index = split_index.ok_or(Error::Parse(format!("no end of header was found")))?;
+        if media_ranges.is_empty() {
+            return offered.first().copied();
+        }
 
-        // The minus one is a safe operation, due to the upper for loop
-        let mut content: Vec<_> = source.drain((split_index - 1)..).collect();
-        // We have to remove the `\r\n\r\n` that is at the beginning of the remaining bytes
-        content.drain(..4);
-        // The request header needs to be a string
+        let mut best: Option<(&'a str, f32)> = None;
+        for candidate in offered {
+            let (candidate_type, candidate_subtype) = candidate.split_once('/').unwrap_or((candidate, ""));
+            // The most specific matching range is the one that determines the quality
+            let mut matched: Option<(u8, f32)> = None;
+            for (range_type, range_subtype, quality) in &media_ranges {
+                let specificity = if range_type.eq_ignore_ascii_case(candidate_type) && range_subtype.eq_ignore_ascii_case(candidate_subtype) {
+                    2
+                } else if range_type.eq_ignore_ascii_case(candidate_type) && *range_subtype == "*" {
+                    1
+                } else if *range_type == "*" && *range_subtype == "*" {
+                    0
+                } else {
+                    continue
+                };
+                if matched.map(|(s, _)| specificity > s).unwrap_or(true) {
+                    matched = Some((specificity, *quality));
+                }
+            }
+            if let Some((_, quality)) = matched {
+                if quality > 0.0 && best.map(|(_, q)| quality > q).unwrap_or(true) {
+                    best = Some((candidate, quality));
+                }
+            }
+        }
+
+        best.map(|(candidate, _)| candidate)
+    }
+
+    /// Parses the header of a request, that is, everything before the `\r\n\r\n` separator
+    ///
+    /// The returned request has no content, it has to be attached afterwards.
+    pub(crate) fn parse_header(source: &[u8], addr: PeerAddr) -> Result<Request, Error> {
+        // The separator is also part of the header size
         let header_size = source.len() + 4;
-        let request_string = String::from_utf8(source).map_err(|e| Error::Parse(format!("{}", e)))?;
+        let request_string = std::str::from_utf8(source).map_err(|e| Error::Parse(format!("{}", e)))?;
 
         let mut lines = request_string.split("\r\n");
         let first_line = lines.next().ok_or(Error::Parse("request has no first line".into()))?;
         let tokens = first_line.split(" ").collect::<Vec<_>>();
-        let (method, path, version) = if tokens.len() != 3 {
+        let (method, path, version): (Method, _, _) = if tokens.len() != 3 {
             return Err(Error::Parse("request's first has incorrect format".into()));
         } else {
             (
@@ -82,33 +337,119 @@ impl Request {
         for line in lines {
             let idx = line.find(":").ok_or(Error::Parse(format!("corrupted header missing colon")))?;
             let (key, value) = line.split_at(idx);
-            let (key, value) = (key.to_string(), value.trim_start_matches(": ").trim_end().to_string());
+            // Names are case-insensitive, so different casings of a repeated header keep their order in a single entry
+            let (key, value) = (key.to_ascii_lowercase(), value.trim_start_matches(": ").trim_end().to_string());
             headers.entry(key).or_insert_with(|| Vec::new()).push(value);
         }
 
-        if !version.starts_with("HTTP") {
-            return Err(Error::Parse("unsupported protocol".into()))
+        let version = Version::parse(version).ok_or(Error::Parse("unsupported protocol".into()))?;
+        // Chunked encoding does not exist in HTTP/1.0, so the body length can't be trusted
+        if version == Version::Http10 && headers.keys().any(|k| k.eq_ignore_ascii_case("transfer-encoding")) {
+            return Err(Error::Parse("transfer-encoding is not supported in HTTP/1.0".into()))
         }
         // And we construct the request
-        let host = headers.get("Host").map(|h| h.get(0).map(|v| &v[..])).flatten().unwrap_or_else(|| "missing.host");
-        let url = Url::parse(&format!("http://{}{}", host, path)).map_err(Error::Url)?;
-        //let _version = tokens[2];
+        let host = headers.iter().find(|(k, _)| k.eq_ignore_ascii_case("host")).and_then(|(_, h)| h.first()).map(|v| &v[..]).unwrap_or_else(|| "missing.host");
+        // The target can come in origin-form (`/path`), absolute-form (`http://host/path`, used by proxies), or asterisk-form (`*`)
+        let asterisk_form = path == "*";
+        let url = if asterisk_form {
+            if method != Method::Options {
+                return Err(Error::Parse("only OPTIONS requests can target *".into()))
+            }
+            Url::parse(&format!("http://{}/", host)).map_err(Error::Url)?
+        } else if path.starts_with('/') {
+            Url::parse(&format!("http://{}{}", host, path)).map_err(Error::Url)?
+        } else {
+            let url = Url::parse(path).map_err(Error::Url)?;
+            if !matches!(url.scheme(), "http" | "https") || !url.has_host() {
+                return Err(Error::Parse(format!("unsupported request target {}", path)))
+            }
+            url
+        };
         // Parse following lines
         Ok(Request {
             method,
             url,
+            version,
             variable_indices: vec![],
             depth: 0,
             headers,
             header_size,
             addr,
-            content
+            content: Vec::new(),
+            context: RequestContext::default(),
+            base_path: None,
+            route: None,
+            asterisk_form,
+            extraction_error: None,
+            trusted_proxies: 0
         })
     }
 
+    /// Removes the base path from the beginning of the path, if it is there
+    pub(crate) fn strip_base_path(&mut self, base_path: &str) {
+        let path = self.url.path();
+        let remaining = match path.strip_prefix(base_path) {
+            Some("") => "/".to_string(),
+            Some(remaining) if remaining.starts_with('/') => remaining.to_string(),
+            _ => return
+        };
+        self.url.set_path(&remaining);
+        self.base_path = Some(base_path.to_string());
+    }
+
+    /// Indicates if the client expects the connection to remain open
+    ///
+    /// HTTP/1.1 connections persist unless closed explicitly, while HTTP/1.0 ones need to request it.
     pub(crate) fn requests_keep_alive(&self) -> bool {
-        self.headers.get("Connection").map(|values| values.into_iter().find(|v| *v == "keep-alive")).flatten().is_some()
+        match self.version {
+            Version::Http10 => self.has_connection_option("keep-alive"),
+            Version::Http11 => !self.requests_close()
+        }
+    }
+
+    pub(crate) fn requests_close(&self) -> bool {
+        self.has_connection_option("close")
     }
+
+    /// Checks the comma separated options of the `Connection` header, in a case-insensitive way
+    fn has_connection_option(&self, option: &str) -> bool {
+        self.header_list("connection").any(|v| v.eq_ignore_ascii_case(option))
+    }
+}
+
+/// Splits a comma separated header value into its trimmed, non empty items
+///
+/// Commas inside quoted strings (like in `If-None-Match` entity tags) are kept within their item.
+pub(crate) fn split_list(value: &str) -> impl Iterator<Item = &str> {
+    let mut rest = Some(value);
+    std::iter::from_fn(move || loop {
+        let current = rest?;
+        let (mut quoted, mut escaped) = (false, false);
+        let end = current.char_indices().find(|&(_, c)| {
+            let separator = c == ',' && !quoted;
+            if escaped {
+                escaped = false;
+            } else if c == '\\' && quoted {
+                escaped = true;
+            } else if c == '"' {
+                quoted = !quoted;
+            }
+            separator
+        });
+        let item = match end {
+            Some((index, _)) => {
+                rest = Some(&current[index + 1..]);
+                &current[..index]
+            },
+            None => {
+                rest = None;
+                current
+            }
+        };
+        if !item.trim().is_empty() {
+            return Some(item.trim())
+        }
+    })
 }
 
 pub struct BasicRequest {
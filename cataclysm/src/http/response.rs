@@ -1,19 +1,117 @@
 use std::collections::HashMap;
-use crate::Error;
+use crate::{Error, http::{StatusCode, split_list, file_content_type, is_textual}};
+use cookie::Cookie;
 
 /// Contains the data of an http response
 pub struct Response {
     protocol: String,
     pub(crate) status: (u32, String),
     pub(crate) headers: HashMap<String, Vec<String>>,
+    /// Fields sent after the body, which requires chunked transfer encoding
+    pub(crate) trailers: Vec<(String, String)>,
     pub content: Vec<u8>
 }
 
-impl<A: Into<Response>, B: Into<Response>> Into<Response> for Result<A, B> {
-    fn into(self) -> Response {
+/// Conversion into a response, required for the values returned by the callbacks
+///
+/// Anything that implements `Into<Response>` gets it for free, as well as strings (plain text), bytes, `()` (no content), json values and tuples with a [StatusCode](crate::http::StatusCode) in front. Results are supported as long as both variants implement it, so handlers can use the `?` operator.
+///
+/// ```rust,no_run
+/// use cataclysm::{Error, http::{Response, Path}};
+///
+/// async fn double(path: Path<(String,)>) -> Result<Response, Error> {
+///     let (number,) = path.into_inner();
+///     let number = number.parse::<i64>().map_err(|e| Error::ExtractionBR(e.to_string()))?;
+///     Ok(Response::ok().body(format!("{}", number * 2)))
+/// }
+/// ```
+pub trait IntoResponse {
+    /// Performs the conversion
+    fn into_response(self) -> Response;
+}
+
+impl<A: Into<Response>> IntoResponse for A {
+    fn into_response(self) -> Response {
+        self.into()
+    }
+}
+
+impl<A: IntoResponse, B: IntoResponse> IntoResponse for Result<A, B> {
+    fn into_response(self) -> Response {
         match self {
-            Ok(v) => v.into(),
-            Err(e) => e.into()
+            Ok(v) => v.into_response(),
+            Err(e) => e.into_response()
+        }
+    }
+}
+
+/// Plain text, with a 200 status code
+impl IntoResponse for String {
+    fn into_response(self) -> Response {
+        Response::text(self)
+    }
+}
+
+/// Plain text, with a 200 status code
+impl IntoResponse for &'static str {
+    fn into_response(self) -> Response {
+        Response::text(self)
+    }
+}
+
+/// Raw bytes, with a 200 status code
+impl IntoResponse for Vec<u8> {
+    fn into_response(self) -> Response {
+        Response::ok().header("Content-Type", "application/octet-stream").body(self)
+    }
+}
+
+/// Empty response, with a 204 status code
+impl IntoResponse for () {
+    fn into_response(self) -> Response {
+        Response::no_content()
+    }
+}
+
+/// Json content, with a 200 status code
+impl IntoResponse for serde_json::Value {
+    fn into_response(self) -> Response {
+        Response::ok().header("Content-Type", "application/json").body(self.to_string())
+    }
+}
+
+/// Replaces the status of the inner response
+///
+/// ```rust,no_run
+/// use cataclysm::http::StatusCode;
+///
+/// async fn create() -> (StatusCode, String) {
+///     (StatusCode::CREATED, "created!".to_string())
+/// }
+/// ```
+impl<A: IntoResponse> IntoResponse for (StatusCode, A) {
+    fn into_response(self) -> Response {
+        let (status, inner) = self;
+        inner.into_response().with_status(status.as_u16() as u32, status.canonical_reason().unwrap_or_default())
+    }
+}
+
+impl IntoResponse for Error {
+    /// Bad requests for errors caused by the client, not found for missing files, and internal server errors for the rest
+    ///
+    /// With the `full_log` feature, the body contains the error detail as json.
+    fn into_response(self) -> Response {
+        #[cfg(feature = "full_log")]
+        {
+            self.as_response()
+        }
+        #[cfg(not(feature = "full_log"))]
+        {
+            match self {
+                Error::Parse(_) | Error::Timeout | Error::Url(_) | Error::ExtractionBR(_) => Response::bad_request(),
+                Error::Io(e) if e.kind() == std::io::ErrorKind::NotFound => Response::not_found(),
+                _ => Response::internal_server_error()
+            }
         }
     }
 }
@@ -24,6 +122,7 @@ impl<A: Into<String>> From<(u32, A)> for Response {
             protocol: "HTTP/1.1".into(),
             status: (source.0, source.1.into()),
             headers: HashMap::new(),
+            trailers: Vec::new(),
             content: Vec::new()
         }
     }
@@ -44,6 +143,9 @@ impl Response {
     const PARTIAL_CONTENT: (u32, &'static str) = (206, "Partial Content");
 
     // Redirection Messages
+    const MOVED_PERMANENTLY: (u32, &'static str) = (301, "Moved Permanently");
+    const FOUND: (u32, &'static str) = (302, "Found");
+    const NOT_MODIFIED: (u32, &'static str) = (304, "Not Modified");
 
     // Client error responses
     const BAD_REQUEST: (u32, &'static str) = (400, "Bad Request");
@@ -51,6 +153,10 @@ impl Response {
     const PAYMENT_REQUIRED: (u32, &'static str) = (402, "Payment Required");
     const FORBIDDEN: (u32, &'static str) = (403, "Forbidden");
     const NOT_FOUND: (u32, &'static str) = (404, "Not Found");
+    const METHOD_NOT_ALLOWED: (u32, &'static str) = (405, "Method Not Allowed");
+    const PAYLOAD_TOO_LARGE: (u32, &'static str) = (413, "Payload Too Large");
+    const UPGRADE_REQUIRED: (u32, &'static str) = (426, "Upgrade Required");
+    const REQUEST_HEADER_FIELDS_TOO_LARGE: (u32, &'static str) = (431, "Request Header Fields Too Large");
 
     // Server error responses
     const INTERNAL_SERVER_ERROR: (u32, &'static str) = (500, "Internal Server Error");
@@ -78,6 +184,13 @@ impl Response {
     /// Creates a Partial Content response, with a 206 status code
     pub fn partial_content() -> Response { Response::PARTIAL_CONTENT.into() }
 
+    /// Creates a Moved Permanently response, with a 301 status code
+    pub fn moved_permanently() -> Response { Response::MOVED_PERMANENTLY.into() }
+    /// Creates a Found response, with a 302 status code
+    pub fn found() -> Response { Response::FOUND.into() }
+    /// Creates a Not Modified response, with a 304 status code
+    pub fn not_modified() -> Response { Response::NOT_MODIFIED.into() }
+
     /// Creates a Bad Request response, with a 400 status code
     pub fn bad_request() -> Response { Response::BAD_REQUEST.into() }
     /// Creates an Unauthorized response, with a 401 status code
@@ -88,6 +201,14 @@ impl Response {
     pub fn forbidden() -> Response { Response::FORBIDDEN.into() }
     /// Creates a Not Found response, with a 404 status code
     pub fn not_found() -> Response { Response::NOT_FOUND.into() }
+    /// Creates a Method Not Allowed response, with a 405 status code
+    pub fn method_not_allowed() -> Response { Response::METHOD_NOT_ALLOWED.into() }
+    /// Creates a Payload Too Large response, with a 413 status code
+    pub fn payload_too_large() -> Response { Response::PAYLOAD_TOO_LARGE.into() }
+    /// Creates an Upgrade Required response, with a 426 status code
+    pub fn upgrade_required() -> Response { Response::UPGRADE_REQUIRED.into() }
+    /// Creates a Request Header Fields Too Large response, with a 431 status code
+    pub fn request_header_fields_too_large() -> Response { Response::REQUEST_HEADER_FIELDS_TOO_LARGE.into() }
 
     /// Creates an Internal Server Error response, with a 500 status code
     pub fn internal_server_error() -> Response { Response::INTERNAL_SERVER_ERROR.into() }
@@ -98,33 +219,355 @@ impl Response {
     /// Creates a Service Unavailable response, with a 503 status code
     pub fn service_unavailable() -> Response { Response::SERVICE_UNAVAILABLE.into() }
 
-    /// Creates a new response, with defaut response status 200, and a text/html content type
+    /// Creates a new response, with defaut response status 200, and no content type
     pub fn new() -> Response {
         Response::OK.into()
     }
 
+    /// Creates an Ok response with a plain text body, declared as utf-8
+    ///
+    /// ```rust
+    /// # use cataclysm::http::Response;
+    /// let response = Response::text("¡Hola, señor!");
+    /// assert_eq!(response.headers()["Content-Type"], vec!["text/plain; charset=utf-8"]);
+    /// ```
+    pub fn text<A: AsRef<str>>(body: A) -> Response {
+        Response::ok().header("Content-Type", "text/plain; charset=utf-8").body(body.as_ref())
+    }
+
+    /// Creates a temporary redirection to the given location, with a 302 status code
+    ///
+    /// ```rust
+    /// # use cataclysm::http::Response;
+    /// let response = Response::redirect("/login");
+    /// assert_eq!(response.status_code(), 302);
+    /// assert_eq!(response.headers()["Location"], vec!["/login"]);
+    /// ```
+    pub fn redirect<A: Into<String>>(location: A) -> Response {
+        Response::found().header("Location", location)
+    }
+
+    /// Creates a permanent redirection to the given location, with a 301 status code
+    ///
+    /// Clients and caches are allowed to remember it, so it is meant for resources that moved for good.
+    pub fn permanent_redirect<A: Into<String>>(location: A) -> Response {
+        Response::moved_permanently().header("Location", location)
+    }
+
+    /// Creates an Ok response with the contents of a file, read asynchronously
+    ///
+    /// ```rust,no_run
+    /// # use cataclysm::{Error, http::{Response, Query}};
+    /// # use std::collections::HashMap;
+    /// async fn terms(Query(query): Query<HashMap<String, String>>) -> Result<Response, Error> {
+    ///     match query.get("lang").map(|lang| lang.as_str()) {
+    ///         Some("es") => Response::from_file("./legal/terminos.html").await,
+    ///         _ => Response::from_file("./legal/terms.html").await
+    ///     }
+    /// }
+    /// ```
+    ///
+    /// The `Content-Type` header comes from the extension of the file (`application/octet-stream` for unknown ones), with the utf-8 charset for text files. A missing file gives back an io error, which turns into a `404 Not Found` when returned from a handler. Unlike with [files](crate::Branch::files), the path is used as is, so it should not come from the request.
+    pub async fn from_file<P: AsRef<std::path::Path>>(path: P) -> Result<Response, Error> {
+        let content = tokio::fs::read(path.as_ref()).await.map_err(Error::Io)?;
+        let extension = path.as_ref().extension().and_then(|e| e.to_str()).unwrap_or_default();
+        let content_type = file_content_type(extension, &content, false);
+        Ok(Response::file_content(content, content_type))
+    }
+
+    /// Creates an Ok response with the contents of a file, declaring the charset for text files
+    pub(crate) fn file_content(content: Vec<u8>, content_type: &str) -> Response {
+        if is_textual(content_type) {
+            Response::ok().body(content).header("Content-Type", format!("{}; charset=utf-8", content_type))
+        } else {
+            Response::ok().body(content).header("Content-Type", content_type)
+        }
+    }
+
     /// Inserts a header into the response
     pub fn header<A: Into<String>, B: Into<String>>(mut self, key: A, value: B) -> Response {
         self.headers.entry(key.into()).or_insert_with(|| Vec::new()).push(value.into());
         self
     }
 
+    /// Sets a header in place, replacing any previous value for it
+    ///
+    /// Header names are compared in a case-insensitive way, and the new name replaces the old one.
+    ///
+    /// ```rust,no_run
+    /// # use cataclysm::{Additional, Pipeline, http::{Request, Response}};
+    /// # use futures::future::FutureExt;
+    /// # use std::sync::Arc;
+    /// // Layer that adds some security headers to every response
+    /// let layer = |req: Request, pipeline: Box<Pipeline<()>>, ad: Arc<Additional<()>>| async {
+    ///     let mut response = pipeline.execute(req, ad).await;
+    ///     response.insert_header("X-Content-Type-Options", "nosniff");
+    ///     response.insert_header("X-Frame-Options", "DENY");
+    ///     response.insert_header("Strict-Transport-Security", "max-age=63072000");
+    ///     response
+    /// }.boxed();
+    /// ```
+    pub fn insert_header<A: Into<String>, B: Into<String>>(&mut self, key: A, value: B) {
+        let key = key.into();
+        self.headers.retain(|k, _| !k.eq_ignore_ascii_case(&key));
+        self.headers.insert(key, vec![value.into()]);
+    }
+
+    /// Adds a value to a header in place, keeping the previous ones
+    ///
+    /// Header names are compared in a case-insensitive way.
+    pub fn append_header<A: Into<String>, B: Into<String>>(&mut self, key: A, value: B) {
+        let key = key.into();
+        match self.headers.iter_mut().find(|(k, _)| k.eq_ignore_ascii_case(&key)) {
+            Some((_, values)) => values.push(value.into()),
+            None => {
+                self.headers.insert(key, vec![value.into()]);
+            }
+        }
+    }
+
+    /// Removes a header, returning its values if it was present
+    ///
+    /// Header names are compared in a case-insensitive way.
+    pub fn remove_header<A: AsRef<str>>(&mut self, key: A) -> Option<Vec<String>> {
+        let keys = self.headers.keys().filter(|k| k.eq_ignore_ascii_case(key.as_ref())).cloned().collect::<Vec<_>>();
+        keys.into_iter().filter_map(|k| self.headers.remove(&k)).reduce(|mut values, other| {
+            values.extend(other);
+            values
+        })
+    }
+
+    /// Adds a trailer field, sent after the body
+    ///
+    /// Trailers can only follow a chunked body, so a response with trailers is sent with `Transfer-Encoding: chunked` instead of `Content-Length`, and announces the field names in the `Trailer` header.
+    ///
+    /// ```rust,no_run
+    /// # use cataclysm::http::Response;
+    /// let response = Response::ok()
+    ///     .header("Content-Type", "application/grpc-web")
+    ///     .body(vec![0u8; 5])
+    ///     .trailer("Grpc-Status", "0")
+    ///     .trailer("Grpc-Message", "OK");
+    /// ```
+    ///
+    /// HTTP/1.0 clients don't support chunked encoding, so trailers are dropped for them. They are dropped as well for responses without a body, like `204 No Content` or the ones to `HEAD` requests.
+    pub fn trailer<A: Into<String>, B: Into<String>>(mut self, key: A, value: B) -> Response {
+        self.trailers.push((key.into(), value.into()));
+        self
+    }
+
+    /// Asks the server to close the connection after sending this response
+    ///
+    /// ```rust
+    /// # use cataclysm::http::Response;
+    /// // After a credentials reset, the client has to start over with a fresh connection
+    /// let response = Response::ok().body("credentials reset").close_connection();
+    /// assert!(response.closes_connection());
+    /// ```
+    ///
+    /// This sets the `Connection: close` header, which the server honors instead of waiting for another request on a keep-alive connection.
+    pub fn close_connection(mut self) -> Response {
+        self.insert_header("Connection", "close");
+        self
+    }
+
+    /// Indicates if the connection gets closed after sending this response, see [close_connection](Response::close_connection)
+    pub fn closes_connection(&self) -> bool {
+        self.header_list("connection").any(|option| option.eq_ignore_ascii_case("close"))
+    }
+
+    /// Returns the trailer fields of the response
+    pub fn trailers(&self) -> &[(String, String)] {
+        &self.trailers
+    }
+
+    /// Returns the headers of the response
+    pub fn headers(&self) -> &HashMap<String, Vec<String>> {
+        &self.headers
+    }
+
+    /// Returns every value of a header, looked up in a case-insensitive way
+    pub fn header_values<A: AsRef<str>>(&self, name: A) -> impl Iterator<Item = &str> {
+        self.headers.iter()
+            .filter(move |(key, _)| key.eq_ignore_ascii_case(name.as_ref()))
+            .flat_map(|(_, values)| values.iter())
+            .map(|value| value.as_str())
+    }
+
+    /// Returns the items of a comma separated header, like `Cache-Control`, see [Request::header_list](crate::http::Request::header_list)
+    pub fn header_list<A: AsRef<str>>(&self, name: A) -> impl Iterator<Item = &str> {
+        self.header_values(name).flat_map(split_list)
+    }
+
+    /// Returns a mutable reference to the headers of the response
+    ///
+    /// Keys are kept as they were inserted, so prefer [insert_header](Response::insert_header) and the like for case-insensitive handling.
+    pub fn headers_mut(&mut self) -> &mut HashMap<String, Vec<String>> {
+        &mut self.headers
+    }
+
+    /// Appends a `Set-Cookie` header to the response
+    ///
+    /// The cookie gets percent-encoded, and calling this method multiple times will result in multiple `Set-Cookie` headers.
+    ///
+    /// ```rust
+    /// # use cataclysm::http::{Response, Cookie};
+    /// let response = Response::ok()
+    ///     .cookie(Cookie::build(("theme", "dark")).path("/").http_only(true))
+    ///     .cookie(Cookie::new("language", "es"));
+    /// ```
+    pub fn cookie<'a, A: Into<Cookie<'a>>>(self, cookie: A) -> Response {
+        let cookie: Cookie<'a> = cookie.into();
+        self.header("Set-Cookie", format!("{}", cookie.encoded()))
+    }
+
+    /// Adds the `no-cache` directive to the `Cache-Control` header, so caches revalidate the response before using it
+    ///
+    /// All the cache helpers compose into a single `Cache-Control` header.
+    ///
+    /// ```rust
+    /// # use cataclysm::http::Response;
+    /// # use std::time::Duration;
+    /// let response = Response::ok().private().max_age(Duration::from_secs(60)).public().max_age(Duration::from_secs(31_536_000)).immutable();
+    /// assert_eq!(response.headers()["Cache-Control"], vec!["public, max-age=31536000, immutable"]);
+    /// ```
+    pub fn no_cache(self) -> Response {
+        self.cache_directive("no-cache")
+    }
+
+    /// Adds the `no-store` directive to the `Cache-Control` header, so the response is not stored by any cache
+    pub fn no_store(self) -> Response {
+        self.cache_directive("no-store")
+    }
+
+    /// Sets the `max-age` directive of the `Cache-Control` header, in seconds, replacing any previous one
+    pub fn max_age(self, max_age: std::time::Duration) -> Response {
+        self.cache_directive(format!("max-age={}", max_age.as_secs()))
+    }
+
+    /// Adds the `immutable` directive to the `Cache-Control` header, indicating that the response will not change while fresh
+    pub fn immutable(self) -> Response {
+        self.cache_directive("immutable")
+    }
+
+    /// Adds the `public` directive to the `Cache-Control` header, replacing `private` if present
+    pub fn public(self) -> Response {
+        self.cache_directive("public")
+    }
+
+    /// Adds the `private` directive to the `Cache-Control` header, replacing `public` if present
+    pub fn private(self) -> Response {
+        self.cache_directive("private")
+    }
+
+    /// Merges a directive into the `Cache-Control` header, replacing the ones with the same name
+    fn cache_directive<A: Into<String>>(mut self, directive: A) -> Response {
+        let directive = directive.into();
+        let name = directive.split('=').next().unwrap_or_default().to_string();
+        let mut directives = self.remove_header("Cache-Control").unwrap_or_default().iter()
+            .flat_map(|value| split_list(value).map(|d| d.to_string()).collect::<Vec<_>>())
+            .filter(|d| {
+                let other = d.split('=').next().unwrap_or_default().trim();
+                let exclusive = matches!((name.as_str(), other), ("public", "private") | ("private", "public"));
+                !other.eq_ignore_ascii_case(&name) && !exclusive
+            })
+            .collect::<Vec<_>>();
+        directives.push(directive);
+        self.insert_header("Cache-Control", directives.join(", "));
+        self
+    }
+
     /// Inserts a body in the response
     pub fn body<T: AsRef<[u8]>>(mut self, body: T) -> Response {
         self.content = Vec::from(body.as_ref());
         self
     }
 
-    /// Returns the status code contained in the response
+    /// Appends a chunk at the end of the body, in place
+    ///
+    /// ```rust
+    /// # use cataclysm::http::Response;
+    /// let mut response = Response::ok().header("Content-Type", "text/csv");
+    /// response.append_body("id,name\n");
+    /// for (id, name) in [(1, "alice"), (2, "bob")] {
+    ///     response.append_body(format!("{},{}\n", id, name));
+    /// }
+    /// assert_eq!(response.content, b"id,name\n1,alice\n2,bob\n");
+    /// ```
+    pub fn append_body<T: AsRef<[u8]>>(&mut self, chunk: T) {
+        self.content.extend_from_slice(chunk.as_ref());
+    }
+
+    /// Returns a mutable reference to the body, handy to write into it with [std::io::Write]
+    ///
+    /// ```rust
+    /// # use cataclysm::http::Response;
+    /// use std::io::Write;
+    ///
+    /// let mut response = Response::ok();
+    /// write!(response.body_mut(), "{} + {} = {}", 1, 2, 3).unwrap();
+    /// assert_eq!(response.content, b"1 + 2 = 3");
+    /// ```
+    pub fn body_mut(&mut self) -> &mut Vec<u8> {
+        &mut self.content
+    }
+
+    /// Replaces the status of the response, keeping its headers and body
+    ///
+    /// ```rust,no_run
+    /// # use cataclysm::{Additional, Pipeline, http::{Request, Response}};
+    /// # use futures::future::FutureExt;
+    /// # use std::sync::Arc;
+    /// // Layer that reports crashes as a temporary unavailability
+    /// let layer = |req: Request, pipeline: Box<Pipeline<()>>, ad: Arc<Additional<()>>| async {
+    ///     let response = pipeline.execute(req, ad).await;
+    ///     if response.status_code() == 500 {
+    ///         response.with_status(503, "Service Unavailable")
+    ///     } else {
+    ///         response
+    ///     }
+    /// }.boxed();
+    /// ```
+    pub fn with_status<A: Into<String>>(mut self, code: u32, text: A) -> Response {
+        self.set_status(code, text);
+        self
+    }
+
+    /// Replaces the status of the response in place, keeping its headers and body
+    pub fn set_status<A: Into<String>>(&mut self, code: u32, text: A) {
+        self.status = (code, text.into());
+    }
+
+    /// Returns the status code contained in the response, as a number
     pub fn status_code(&self) -> u32 {
         self.status.0
     }
 
+    /// Returns the status code contained in the response
+    ///
+    /// Codes that do not fit in three digits, which can only be created on purpose, are reported as `500 Internal Server Error`.
+    pub fn status(&self) -> StatusCode {
+        u16::try_from(self.status.0).ok().filter(|code| *code < 1_000).map(StatusCode::from).unwrap_or(StatusCode::INTERNAL_SERVER_ERROR)
+    }
+
     /// Serializes the response to be sent to the client
     pub(crate) fn serialize(&mut self) -> Vec<u8> {
         let mut response = format!("{} {} {}\r\n", self.protocol, self.status.0, self.status.1);
 
-        self.headers.entry("Content-Length".to_string()).or_insert_with(|| Vec::new()).push(format!("{}", self.content.len()));
+        // The date of the response, in the IMF-fixdate format
+        if !self.headers.keys().any(|k| k.eq_ignore_ascii_case("date")) {
+            self.headers.insert("Date".to_string(), vec![chrono::Utc::now().format("%a, %d %b %Y %H:%M:%S GMT").to_string()]);
+        }
+        // A not modified response has no body, and its length would refer to the cached one. Interim and no content responses have no body either
+        let has_body = self.status.0 != 204 && self.status.0 != 304 && self.status.0 >= 200;
+        let chunked = has_body && !self.trailers.is_empty();
+        if chunked {
+            self.headers.retain(|k, _| !k.eq_ignore_ascii_case("content-length") && !k.eq_ignore_ascii_case("transfer-encoding"));
+            self.headers.insert("Transfer-Encoding".to_string(), vec!["chunked".to_string()]);
+            self.headers.insert("Trailer".to_string(), vec![self.trailers.iter().map(|(k, _)| k.as_str()).collect::<Vec<_>>().join(", ")]);
+        } else if has_body {
+            self.headers.entry("Content-Length".to_string()).or_insert_with(|| Vec::new()).push(format!("{}", self.content.len()));
+        }
         for (header_name, headers) in &self.headers {
             for header in headers {
                 response += &format!("{}: {}\r\n", header_name, header);
@@ -134,7 +577,21 @@ impl Response {
         response += "\r\n";
         // And now add the body, if any
         let mut response = response.into_bytes();
-        response.extend_from_slice(&self.content);
+        if chunked {
+            // The whole body goes in a single chunk, followed by the last (empty) one and the trailers
+            if !self.content.is_empty() {
+                response.extend_from_slice(format!("{:x}\r\n", self.content.len()).as_bytes());
+                response.extend_from_slice(&self.content);
+                response.extend_from_slice(b"\r\n");
+            }
+            response.extend_from_slice(b"0\r\n");
+            for (trailer_name, trailer) in &self.trailers {
+                response.extend_from_slice(format!("{}: {}\r\n", trailer_name, trailer).as_bytes());
+            }
+            response.extend_from_slice(b"\r\n");
+        } else {
+            response.extend_from_slice(&self.content);
+        }
         response
     }
 
@@ -186,6 +643,7 @@ impl Response {
             protocol,
             status: (code, status_text),
             headers,
+            trailers: Vec::new(),
             content
         })
     }
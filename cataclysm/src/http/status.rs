@@ -0,0 +1,128 @@
+/// Status code of an http response
+///
+/// Any three digit code can be represented, and the standard ones are available as associated constants.
+///
+/// ```rust
+/// use cataclysm::http::{Response, StatusCode};
+///
+/// let response = Response::not_found();
+/// assert_eq!(response.status(), StatusCode::NOT_FOUND);
+/// assert!(response.status().is_client_error());
+/// assert_eq!(StatusCode::from(503).canonical_reason(), Some("Service Unavailable"));
+/// ```
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Debug)]
+pub struct StatusCode(u16);
+
+macro_rules! status_codes {
+    ($(($name:ident, $code:expr, $reason:expr)),* $(,)?) => {
+        impl StatusCode {
+            $(
+                #[doc = concat!("`", stringify!($code), " ", $reason, "`")]
+                pub const $name: StatusCode = StatusCode($code);
+            )*
+
+            /// Returns the standard reason phrase for the code, if it is a known one
+            pub fn canonical_reason(&self) -> Option<&'static str> {
+                match self.0 {
+                    $($code => Some($reason),)*
+                    _ => None
+                }
+            }
+        }
+    }
+}
+
+status_codes! {
+    (CONTINUE, 100, "Continue"),
+    (SWITCHING_PROTOCOLS, 101, "Switching Protocols"),
+    (OK, 200, "OK"),
+    (CREATED, 201, "Created"),
+    (ACCEPTED, 202, "Accepted"),
+    (NON_AUTHORITATIVE_INFORMATION, 203, "Non-Authoritative Information"),
+    (NO_CONTENT, 204, "No Content"),
+    (RESET_CONTENT, 205, "Reset Content"),
+    (PARTIAL_CONTENT, 206, "Partial Content"),
+    (MULTIPLE_CHOICES, 300, "Multiple Choices"),
+    (MOVED_PERMANENTLY, 301, "Moved Permanently"),
+    (FOUND, 302, "Found"),
+    (SEE_OTHER, 303, "See Other"),
+    (NOT_MODIFIED, 304, "Not Modified"),
+    (TEMPORARY_REDIRECT, 307, "Temporary Redirect"),
+    (PERMANENT_REDIRECT, 308, "Permanent Redirect"),
+    (BAD_REQUEST, 400, "Bad Request"),
+    (UNAUTHORIZED, 401, "Unauthorized"),
+    (PAYMENT_REQUIRED, 402, "Payment Required"),
+    (FORBIDDEN, 403, "Forbidden"),
+    (NOT_FOUND, 404, "Not Found"),
+    (METHOD_NOT_ALLOWED, 405, "Method Not Allowed"),
+    (NOT_ACCEPTABLE, 406, "Not Acceptable"),
+    (REQUEST_TIMEOUT, 408, "Request Timeout"),
+    (CONFLICT, 409, "Conflict"),
+    (GONE, 410, "Gone"),
+    (LENGTH_REQUIRED, 411, "Length Required"),
+    (PRECONDITION_FAILED, 412, "Precondition Failed"),
+    (PAYLOAD_TOO_LARGE, 413, "Payload Too Large"),
+    (URI_TOO_LONG, 414, "URI Too Long"),
+    (UNSUPPORTED_MEDIA_TYPE, 415, "Unsupported Media Type"),
+    (RANGE_NOT_SATISFIABLE, 416, "Range Not Satisfiable"),
+    (EXPECTATION_FAILED, 417, "Expectation Failed"),
+    (UNPROCESSABLE_ENTITY, 422, "Unprocessable Entity"),
+    (UPGRADE_REQUIRED, 426, "Upgrade Required"),
+    (TOO_MANY_REQUESTS, 429, "Too Many Requests"),
+    (INTERNAL_SERVER_ERROR, 500, "Internal Server Error"),
+    (NOT_IMPLEMENTED, 501, "Not Implemented"),
+    (BAD_GATEWAY, 502, "Bad Gateway"),
+    (SERVICE_UNAVAILABLE, 503, "Service Unavailable"),
+    (GATEWAY_TIMEOUT, 504, "Gateway Timeout"),
+    (HTTP_VERSION_NOT_SUPPORTED, 505, "HTTP Version Not Supported"),
+}
+
+impl StatusCode {
+    /// Returns the numeric value of the code
+    pub fn as_u16(&self) -> u16 {
+        self.0
+    }
+
+    /// Checks if the code is in the `1xx` range
+    pub fn is_informational(&self) -> bool {
+        (100..200).contains(&self.0)
+    }
+
+    /// Checks if the code is in the `2xx` range
+    pub fn is_success(&self) -> bool {
+        (200..300).contains(&self.0)
+    }
+
+    /// Checks if the code is in the `3xx` range
+    pub fn is_redirection(&self) -> bool {
+        (300..400).contains(&self.0)
+    }
+
+    /// Checks if the code is in the `4xx` range
+    pub fn is_client_error(&self) -> bool {
+        (400..500).contains(&self.0)
+    }
+
+    /// Checks if the code is in the `5xx` range
+    pub fn is_server_error(&self) -> bool {
+        (500..600).contains(&self.0)
+    }
+}
+
+impl From<u16> for StatusCode {
+    fn from(code: u16) -> StatusCode {
+        StatusCode(code)
+    }
+}
+
+impl From<StatusCode> for u16 {
+    fn from(status: StatusCode) -> u16 {
+        status.0
+    }
+}
+
+impl std::fmt::Display for StatusCode {
+    fn fmt(&self, formatter: &mut std::fmt::Formatter) -> Result<(), std::fmt::Error> {
+        write!(formatter, "{}", self.0)
+    }
+}
\ No newline at end of file
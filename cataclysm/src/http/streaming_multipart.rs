@@ -0,0 +1,215 @@
+use crate::{Error, Stream, http::{Request, multipart::{PartHeader, multipart_boundary, find}}};
+use tokio::io::{AsyncWrite, AsyncWriteExt};
+
+const READ_CHUNK_SIZE: usize = 8_192;
+// Protection against endless part headers
+const MAX_PART_HEADER_SIZE: usize = 16_384;
+
+/// Header information of a field in a [StreamingMultipart]
+#[derive(Clone, Debug)]
+pub struct Field {
+    pub name: String,
+    pub filename: Option<String>,
+    pub content_type: Option<String>
+}
+
+#[derive(PartialEq)]
+enum State {
+    /// Before the first boundary
+    Preamble,
+    /// Right after a boundary, either another part or the end follows
+    Delimiter,
+    /// Reading the content of a part
+    Content,
+    /// The closing boundary was found
+    Finished
+}
+
+/// Multipart parser that reads the body as it arrives
+///
+/// Unlike the [Multipart](crate::http::Multipart) extractor, the body is never held completely in memory, which allows for large uploads. It is meant to be used inside a [stream_handler](crate::Branch::stream_handler), as those receive the body unread.
+///
+/// ```rust,no_run
+/// use cataclysm::{Branch, Stream, http::{Request, Response, StreamingMultipart}};
+///
+/// async fn upload(stream: Stream, req: Request) {
+///     let mut multipart = match StreamingMultipart::new(&stream, &req) {
+///         Ok(multipart) => multipart,
+///         Err(_) => return stream.response(Response::bad_request()).await.unwrap_or(())
+///     };
+///     while let Ok(Some(field)) = multipart.next_field().await {
+///         let mut file = tokio::fs::File::create(format!("/tmp/{}", field.name)).await.unwrap();
+///         multipart.copy_to(&mut file).await.unwrap();
+///     }
+///     stream.response(Response::ok()).await.unwrap_or(());
+/// }
+///
+/// let branch: Branch<()> = Branch::new("/upload").stream_handler(upload);
+/// ```
+pub struct StreamingMultipart<'a> {
+    stream: &'a Stream,
+    /// Boundary preceeded by a line break, as found between parts
+    delimiter: Vec<u8>,
+    buffer: Vec<u8>,
+    /// Bytes of the body still to be read from the stream, if known
+    remaining: Option<usize>,
+    state: State
+}
+
+impl<'a> StreamingMultipart<'a> {
+    /// Creates a streaming parser for the body of the request
+    pub fn new(stream: &'a Stream, req: &Request) -> Result<StreamingMultipart<'a>, Error> {
+        let boundary = multipart_boundary(req)?;
+        // Small bodies might have arrived along with the header, in which case the server already read them
+        let remaining = req.header("Content-Length")
+            .and_then(|v| v.trim().parse::<usize>().ok())
+            .map(|length| length.saturating_sub(req.body().len()));
+        // The leading line break lets the first boundary match the delimiter as well
+        let mut buffer = b"\r\n".to_vec();
+        buffer.extend_from_slice(req.body());
+        Ok(StreamingMultipart {
+            stream,
+            delimiter: format!("\r\n--{}", boundary).into_bytes(),
+            buffer,
+            remaining,
+            state: State::Preamble
+        })
+    }
+
+    /// Advances to the next field, skipping whatever is left from the current one
+    ///
+    /// Returns `None` once the closing boundary is found.
+    pub async fn next_field(&mut self) -> Result<Option<Field>, Error> {
+        while self.state == State::Content {
+            self.chunk().await?;
+        }
+        if self.state == State::Preamble {
+            let idx = self.find_or_fill(&self.delimiter.clone(), usize::MAX).await?;
+            self.buffer.drain(..idx + self.delimiter.len());
+            self.state = State::Delimiter;
+        }
+        if self.state == State::Finished {
+            return Ok(None);
+        }
+
+        // Either the closing `--`, or the end of the boundary line
+        while self.buffer.len() < 2 {
+            if !self.fill().await? {
+                return Err(Error::ExtractionBR("the content of the multipart request does not finish properly".to_string()));
+            }
+        }
+        if self.buffer.starts_with(b"--") {
+            self.state = State::Finished;
+            return Ok(None);
+        }
+        let line_end = self.find_or_fill(b"\r\n", MAX_PART_HEADER_SIZE).await?;
+        self.buffer.drain(..line_end + 2);
+
+        let header_end = if self.buffer.starts_with(b"\r\n") {
+            // A part without header
+            0
+        } else {
+            self.find_or_fill(b"\r\n\r\n", MAX_PART_HEADER_SIZE).await? + 2
+        };
+        let header_bytes: Vec<u8> = self.buffer.drain(..header_end + 2).collect();
+        let inner_header = String::from_utf8(header_bytes).map_err(|e| Error::ExtractionBR(format!("incorrect inner header format, {}", e)))?;
+        let part_header = PartHeader::parse(&inner_header)?;
+        self.state = State::Content;
+
+        Ok(Some(Field {
+            name: part_header.name.ok_or_else(|| Error::ExtractionBR("a name for a part of the multipart was not found".to_string()))?,
+            filename: part_header.filename,
+            content_type: part_header.content_type
+        }))
+    }
+
+    /// Reads the next piece of content of the current field
+    ///
+    /// Returns `None` when the field has no more content.
+    pub async fn chunk(&mut self) -> Result<Option<Vec<u8>>, Error> {
+        if self.state != State::Content {
+            return Ok(None);
+        }
+        loop {
+            if let Some(idx) = find(&self.buffer, &self.delimiter) {
+                let content: Vec<u8> = self.buffer.drain(..idx).collect();
+                self.buffer.drain(..self.delimiter.len());
+                self.state = State::Delimiter;
+                return Ok(if content.is_empty() { None } else { Some(content) });
+            }
+            // The tail might be the beginning of a delimiter, so it is kept
+            let safe_length = self.buffer.len().saturating_sub(self.delimiter.len() - 1);
+            if safe_length > 0 {
+                return Ok(Some(self.buffer.drain(..safe_length).collect()));
+            }
+            if !self.fill().await? {
+                return Err(Error::ExtractionBR("the content of the multipart request does not finish properly".to_string()));
+            }
+        }
+    }
+
+    /// Writes the rest of the content of the current field into the given sink
+    ///
+    /// Any [AsyncWrite](tokio::io::AsyncWrite) works, like a file or a socket to another service, so the field never needs to be held in memory. Gives back the amount of bytes written, and flushes the sink at the end.
+    ///
+    /// ```rust,no_run
+    /// # use cataclysm::{Error, http::StreamingMultipart};
+    /// async fn save_avatar(multipart: &mut StreamingMultipart<'_>) -> Result<u64, Error> {
+    ///     let mut file = tokio::fs::File::create("/tmp/avatar.png").await.map_err(Error::Io)?;
+    ///     multipart.copy_to(&mut file).await
+    /// }
+    /// ```
+    pub async fn copy_to<W: AsyncWrite + Unpin>(&mut self, writer: &mut W) -> Result<u64, Error> {
+        let mut written = 0;
+        while let Some(chunk) = self.chunk().await? {
+            writer.write_all(&chunk).await.map_err(Error::Io)?;
+            written += chunk.len() as u64;
+        }
+        writer.flush().await.map_err(Error::Io)?;
+        Ok(written)
+    }
+
+    // Looks for the token in the buffer, reading more bytes when needed
+    async fn find_or_fill(&mut self, token: &[u8], max_length: usize) -> Result<usize, Error> {
+        loop {
+            if let Some(idx) = find(&self.buffer, token) {
+                return Ok(idx);
+            }
+            if self.buffer.len() > max_length {
+                return Err(Error::ExtractionBR("multipart part header is too long".to_string()));
+            }
+            if self.state == State::Preamble && self.buffer.len() > token.len() {
+                // The preamble is meaningless, so it doesn't need to be kept
+                let trim = self.buffer.len() - token.len();
+                self.buffer.drain(..trim);
+            }
+            if !self.fill().await? {
+                return Err(Error::ExtractionBR("the content of the multipart request does not finish properly".to_string()));
+            }
+        }
+    }
+
+    // Reads more bytes into the buffer, returns false if the body is over
+    async fn fill(&mut self) -> Result<bool, Error> {
+        if self.remaining == Some(0) {
+            return Ok(false);
+        }
+        let to_read = self.remaining.unwrap_or(READ_CHUNK_SIZE).min(READ_CHUNK_SIZE);
+        let mut buf = vec![0; to_read];
+        loop {
+            self.stream.readable().await.map_err(Error::Io)?;
+            match self.stream.try_read(&mut buf) {
+                Ok(0) => return Ok(false),
+                Ok(n) => {
+                    self.buffer.extend_from_slice(&buf[..n]);
+                    if let Some(remaining) = &mut self.remaining {
+                        *remaining -= n;
+                    }
+                    return Ok(true);
+                },
+                Err(ref e) if e.kind() == std::io::ErrorKind::WouldBlock => continue,
+                Err(e) => return Err(Error::Io(e))
+            }
+        }
+    }
+}
\ No newline at end of file
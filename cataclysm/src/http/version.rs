@@ -0,0 +1,37 @@
+/// Version of the http protocol used by a request
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Version {
+    /// `HTTP/1.0`, where connections close after each response unless keep-alive is explicitly requested
+    Http10,
+    /// `HTTP/1.1`, where connections are kept alive by default
+    Http11
+}
+
+impl Version {
+    /// Parses the version token from the request line, like `HTTP/1.1`
+    ///
+    /// Versions older than 1.1 are treated as 1.0, and newer ones as 1.1.
+    pub(crate) fn parse(token: &str) -> Option<Version> {
+        let (major, minor) = token.strip_prefix("HTTP/")?.split_once('.')?;
+        let (major, minor) = (major.parse::<u8>().ok()?, minor.parse::<u8>().ok()?);
+        if (major, minor) < (1, 1) {
+            Some(Version::Http10)
+        } else {
+            Some(Version::Http11)
+        }
+    }
+
+    /// Returns the version as it appears in the request line
+    pub fn to_str(&self) -> &'static str {
+        match self {
+            Version::Http10 => "HTTP/1.0",
+            Version::Http11 => "HTTP/1.1"
+        }
+    }
+}
+
+impl std::fmt::Display for Version {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{}", self.to_str())
+    }
+}
\ No newline at end of file
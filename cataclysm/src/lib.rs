@@ -25,7 +25,7 @@
 
 pub use self::error::Error;
 mod error;
-pub use self::branch::{Branch};
+pub use self::branch::{Branch, RouteInfo};
 mod branch;
 
 /// Contains the specific functionality for http interaction
@@ -33,12 +33,20 @@ pub mod http;
 
 pub use self::server::{Server, ServerBuilder};
 mod server;
-pub use self::shared::{Shared};
+pub use self::shared::{Shared, Data};
 mod shared;
 pub use self::additional::Additional;
 mod additional;
 pub use self::cors::{CorsBuilder, Cors};
 mod cors;
+pub use self::security_headers::{SecurityHeaders, security_headers_layer};
+mod security_headers;
+pub use self::conditional::conditional_layer;
+mod conditional;
+pub use self::health::Readiness;
+mod health;
+pub use self::stats::ServerStats;
+mod stats;
 
 pub use self::metafunctions::{Callback, CoreFn, LayerFn, Pipeline, Extractor};
 #[cfg(feature = "stream")]
@@ -52,6 +60,14 @@ pub mod session;
 
 pub use self::stream::Stream;
 mod stream;
+pub use self::proxy::{proxy_buffered, proxy_buffered_timeout};
+mod proxy;
+#[cfg(feature = "stream")]
+pub use self::stream_thread::StreamThread;
+#[cfg(feature = "stream")]
+mod stream_thread;
+#[cfg(feature = "compression")]
+mod compression;
 
 /// Contains some basic websockets functionality
 #[cfg(feature = "ws")]
@@ -1,6 +1,6 @@
 use crate::{
     additional::Additional,
-    http::{Response, Request}
+    http::{Response, Request, IntoResponse}
 };
 #[cfg(feature = "stream")]
 use crate::Stream;
@@ -24,6 +24,7 @@ pub(crate) enum PipelineTrack {
     UnmatchedMethod(String),
     File(String),
     Default(String),
+    NotFound,
     #[cfg(feature = "stream")]
     Stream(String)
 }
@@ -40,6 +41,7 @@ impl PipelineTrack {
                     *s = format!("{}/{}", token.as_ref(), s);
                 }
             },
+            PipelineTrack::NotFound => (),
             #[cfg(feature = "stream")]
             PipelineTrack::Stream(s) => {
                 if s.is_empty() {
@@ -60,6 +62,7 @@ impl std::fmt::Display for PipelineTrack {
             PipelineTrack::UnmatchedMethod(s) => format!("UnmatchedMethod({})", s),
             PipelineTrack::File(s) => format!("File({})", s),
             PipelineTrack::Default(s) => format!("Default({})", s),
+            PipelineTrack::NotFound => "NotFound".to_string(),
             #[cfg(feature = "stream")]
             PipelineTrack::Stream(s) => format!("Stream({})", s)
         };
@@ -79,6 +82,26 @@ pub(crate) enum PipelineKind<T> {
 }
 
 /// Pipeline type, contains either a layer or the core of the pipeline
+///
+/// A layer doesn't need to execute the rest of the pipeline, it can answer by itself instead, which is the usual way to guard a branch.
+///
+/// ```rust,no_run
+/// use cataclysm::{Branch, Additional, Pipeline, http::{Request, Response, Method}};
+/// use futures::future::FutureExt;
+/// use std::sync::Arc;
+///
+/// let branch: Branch<()> = Branch::new("/admin")
+///     .with(Method::Get.to(|| async {Response::ok().body("welcome")}))
+///     .layer(|req: Request, pipeline: Box<Pipeline<()>>, ad: Arc<Additional<()>>| async move {
+///         if req.header("Authorization") == Some("Bearer admin") {
+///             pipeline.execute(req, ad).await
+///         } else {
+///             // Neither the handler, nor the inner layers, get to run
+///             pipeline.skip();
+///             Response::unauthorized()
+///         }
+///     }.boxed());
+/// ```
 pub enum Pipeline<T> {
     /// Processing layer
     Layer(Arc<LayerFn<T>>, Box<Pipeline<T>>),
@@ -87,6 +110,12 @@ pub enum Pipeline<T> {
 }
 
 impl<T> Pipeline<T> {
+    /// Discards the rest of the pipeline without executing it
+    ///
+    /// Dropping the pipeline has the same effect, as it only holds references to the handlers, this function just makes the intention explicit.
+    pub fn skip(self) {}
+
+    /// Executes the rest of the pipeline, that is, the inner layers and then the handler
     pub fn execute(self, s: Request, a: Arc<Additional<T>>) ->  Pin<Box<dyn Future<Output = Response> + Send>> {
         match self {
             Pipeline::Layer(func, pipeline_layer) => func(s, pipeline_layer, a),
@@ -107,25 +136,25 @@ pub trait Callback<A> {
 }
 
 // Callback implementation for empty tupple
-impl<F, R, Z: Into<Response>> Callback<()> for F where F: Fn() -> R, R: Future<Output = Z> + Send + 'static{
+impl<F, R, Z: IntoResponse> Callback<()> for F where F: Fn() -> R, R: Future<Output = Z> + Send + 'static{
     fn invoke(&self, _args: ()) -> Pin<Box<dyn Future<Output = Response>  + Send>> {
-        self().map(|v| v.into()).boxed()
+        self().map(|v| v.into_response()).boxed()
     }
 }
 
 /// This macro implements the trait for a given indexed tuple
 macro_rules! callback_for_many {
     ($struct_name:ident $index:tt) => {
-        impl<K, R, Z: Into<Response>, $struct_name> Callback<($struct_name,)> for K where K: Fn($struct_name) -> R, R: Future<Output = Z> + Send + 'static {
+        impl<K, R, Z: IntoResponse, $struct_name> Callback<($struct_name,)> for K where K: Fn($struct_name) -> R, R: Future<Output = Z> + Send + 'static {
             fn invoke(&self, args: ($struct_name,)) -> Pin<Box<dyn Future<Output = Response>  + Send>> {
-                self(args.$index).map(|v| v.into()).boxed()
+                self(args.$index).map(|v| v.into_response()).boxed()
             }
         }
     };
     ($($struct_name:ident $index:tt),+) => {
-        impl<K, R, Z: Into<Response>, $($struct_name),+> Callback<($($struct_name),+)> for K where K: Fn($($struct_name),+) -> R, R: Future<Output = Z> + Send + 'static {
+        impl<K, R, Z: IntoResponse, $($struct_name),+> Callback<($($struct_name),+)> for K where K: Fn($($struct_name),+) -> R, R: Future<Output = Z> + Send + 'static {
             fn invoke(&self, args: ($($struct_name),+)) -> Pin<Box<dyn Future<Output = Response>  + Send>> {
-                self($(args.$index,)+).map(|v| v.into()).boxed()
+                self($(args.$index,)+).map(|v| v.into_response()).boxed()
             }
         }
     }
@@ -1,7 +1,32 @@
-use crate::{http::{Request, Method}, Error, additional::Additional};
+use crate::{http::{Request, Response, Method}, Error, additional::Additional};
 use std::sync::Arc;
 use core::net::{SocketAddr};
 
+/// Function that creates the response for a failed extraction
+pub(crate) type ExtractionErrorFn = dyn Fn(Error) -> Response + Send + Sync;
+
+/// Creates the response for a failed extraction
+///
+/// The handler of the branch that matched the request takes precedence over the server's one. Without any of them, the error is answered with a `400 Bad Request` (with a json detail in `full_log` mode).
+pub(crate) fn extraction_error_response<T>(req: &Request, additional: &Additional<T>, error: Error) -> Response {
+    #[cfg(feature = "full_log")]
+    log::error!("extractor error: {}", error);
+    match req.extraction_error.as_ref().or(additional.extraction_error.as_ref()) {
+        Some(handler) => handler(error),
+        None => {
+            #[cfg(feature = "full_log")]
+            {
+                error.as_response_for(req)
+            }
+            #[cfg(not(feature = "full_log"))]
+            {
+                let _ = error;
+                Response::bad_request()
+            }
+        }
+    }
+}
+
 /// Extractor trait
 ///
 /// You could, if you wish, implement your own Extractor for other classes, which will allow you to construct an instance of `Self` from the `Request`, and from the additional information provided to this call through the Additional structure. The Extractor takes place during the request processing steps when the callback contains such extractor as argument.
@@ -4,6 +4,7 @@ pub use self::callback::{StreamCallback, HandlerFn};
 pub(crate) mod callback;
 
 pub use self::extractor::Extractor;
+pub(crate) use self::extractor::{ExtractionErrorFn, extraction_error_response};
 mod extractor;
 
 /*
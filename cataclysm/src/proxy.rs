@@ -0,0 +1,202 @@
+use crate::{Error, Stream, http::{Request, Response, BasicRequest, Method, StatusCode}};
+use tokio::net::TcpStream;
+
+const READ_CHUNK_SIZE: usize = 8_192;
+/// Time given to the upstream by [proxy_buffered]
+const DEFAULT_PROXY_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(30);
+
+/// Headers that are either connection specific or recomputed, and must not be forwarded as they are
+///
+/// `Expect` is dropped because the whole body is always sent along with the header, so there is nothing to wait for.
+const SKIPPED_HEADERS: [&str; 10] = ["connection", "keep-alive", "proxy-connection", "te", "trailer", "transfer-encoding", "upgrade", "host", "content-length", "expect"];
+
+/// Forwards a request to an upstream server, and gives back its response once it is fully read
+///
+/// The `Host` header gets rewritten to the upstream address, and the client address is appended to the `X-Forwarded-For` header. Both chunked and content-length delimited responses from the upstream are supported, and the body is read up to its declared end even if the upstream keeps the connection open.
+///
+/// The whole upstream response is buffered in memory before it is returned, so this helper is not suited for large downloads or long-lived responses, like server-sent events. Streaming the upstream response to the client as it arrives is not supported.
+///
+/// Connecting to the upstream and reading its response must take less than 30 seconds, or [Error::Timeout] is returned. See [proxy_buffered_timeout] to pick a different limit.
+///
+/// ```rust,no_run
+/// use cataclysm::{Server, Branch, proxy_buffered, http::{Request, Response, Method}};
+///
+/// async fn gateway(req: Request) -> Response {
+///     match proxy_buffered("127.0.0.1:9000", &req).await {
+///         Ok(response) => response,
+///         Err(_) => Response::bad_gateway()
+///     }
+/// }
+///
+/// #[tokio::main]
+/// async fn main() {
+///     let branch: Branch<()> = Branch::new("/api").with(Method::Get.to(gateway));
+///     let server = Server::builder(branch).build().unwrap();
+///     server.run("127.0.0.1:8000").await.unwrap();
+/// }
+/// ```
+///
+/// A new connection is opened for each call, and the upstream is asked to close it afterwards.
+pub async fn proxy_buffered<S: AsRef<str>>(upstream: S, request: &Request) -> Result<Response, Error> {
+    proxy_buffered_timeout(upstream, request, DEFAULT_PROXY_TIMEOUT).await
+}
+
+/// Same as [proxy_buffered], but fails with [Error::Timeout] if the upstream is not reached and its response fully read within the given time
+///
+/// ```rust,no_run
+/// use cataclysm::{proxy_buffered_timeout, http::{Request, Response}, Error};
+/// use std::time::Duration;
+///
+/// async fn gateway(req: Request) -> Response {
+///     match proxy_buffered_timeout("127.0.0.1:9000", &req, Duration::from_secs(5)).await {
+///         Ok(response) => response,
+///         Err(Error::Timeout) => Response::new().with_status(504, "Gateway Timeout"),
+///         Err(_) => Response::bad_gateway()
+///     }
+/// }
+/// ```
+pub async fn proxy_buffered_timeout<S: AsRef<str>>(upstream: S, request: &Request, duration: std::time::Duration) -> Result<Response, Error> {
+    tokio::time::timeout(duration, proxy(upstream.as_ref(), request)).await.map_err(|_| Error::Timeout)?
+}
+
+async fn proxy(upstream: &str, request: &Request) -> Result<Response, Error> {
+    let mut path = request.url().path().to_string();
+    if let Some(query) = request.url().query() {
+        path += &format!("?{}", query);
+    }
+
+    let mut basic_request = BasicRequest::new(request.method().clone(), format!("http://{}{}", upstream, path))?
+        .header("Host", upstream)
+        .header("Connection", "close");
+
+    for (key, values) in &request.headers {
+        if SKIPPED_HEADERS.iter().any(|h| key.eq_ignore_ascii_case(h)) || key.eq_ignore_ascii_case("x-forwarded-for") {
+            continue;
+        }
+        for value in values {
+            basic_request = basic_request.header(key, value);
+        }
+    }
+    // Peers from a Unix domain socket have no ip to add to the chain
+    let client_ip = request.peer_addr().ip().map(|ip| ip.to_string());
+    let forwarded_for = request.header_list("x-forwarded-for").chain(client_ip.as_deref()).collect::<Vec<_>>();
+    basic_request = basic_request.header("X-Forwarded-For", forwarded_for.join(", "));
+    if !request.body().is_empty() || request.headers.keys().any(|k| k.eq_ignore_ascii_case("content-length")) {
+        basic_request = basic_request.header("Content-Length", format!("{}", request.body().len())).content(request.body().clone());
+    }
+
+    let stream = Stream::new(TcpStream::connect(upstream).await.map_err(Error::Io)?, None);
+    stream.request(basic_request).await?;
+
+    let mut reader = UpstreamReader{stream: &stream, buffer: Vec::with_capacity(READ_CHUNK_SIZE)};
+
+    // First, the header of the response
+    let mut response = loop {
+        let header_end = loop {
+            if let Some(idx) = reader.buffer.windows(4).position(|w| w == b"\r\n\r\n") {
+                break idx + 4;
+            }
+            if !reader.fill().await? {
+                return Err(Error::Parse("upstream closed the connection before sending a complete response".into()));
+            }
+        };
+        let body_start: Vec<u8> = reader.buffer.drain(header_end..).collect();
+        let response = Response::parse(std::mem::replace(&mut reader.buffer, body_start))?;
+        // Interim responses, like a `100 Continue`, are followed by the final one
+        if response.status().is_informational() && response.status() != StatusCode::SWITCHING_PROTOCOLS {
+            continue;
+        }
+        break response;
+    };
+
+    let header_value = |response: &Response, name: &str| response.headers.iter()
+        .find(|(k, _)| k.eq_ignore_ascii_case(name))
+        .and_then(|(_, v)| v.last().cloned());
+    let chunked = header_value(&response, "transfer-encoding").map(|v| v.to_ascii_lowercase().contains("chunked")).unwrap_or(false);
+    let content_length = header_value(&response, "content-length").and_then(|v| v.trim().parse::<usize>().ok());
+
+    // Some responses never carry a body
+    let status = response.status();
+    let content = if request.method() == &Method::Head || status.is_informational() || status == StatusCode::NO_CONTENT || status == StatusCode::NOT_MODIFIED {
+        Vec::new()
+    } else if chunked {
+        reader.read_chunked().await?
+    } else if let Some(content_length) = content_length {
+        reader.read_exact(content_length).await?
+    } else {
+        reader.read_to_end().await?
+    };
+
+    response.headers.retain(|k, _| !SKIPPED_HEADERS.iter().any(|h| k.eq_ignore_ascii_case(h)));
+    Ok(response.body(content))
+}
+
+/// Small helper to read the upstream response
+struct UpstreamReader<'a> {
+    stream: &'a Stream,
+    buffer: Vec<u8>
+}
+
+impl UpstreamReader<'_> {
+    /// Reads more bytes into the buffer, returns false if the upstream closed the connection
+    async fn fill(&mut self) -> Result<bool, Error> {
+        loop {
+            self.stream.readable().await.map_err(Error::Io)?;
+            let mut buf = [0; READ_CHUNK_SIZE];
+            match self.stream.try_read(&mut buf) {
+                Ok(0) => return Ok(false),
+                Ok(n) => {
+                    self.buffer.extend_from_slice(&buf[0..n]);
+                    return Ok(true)
+                },
+                Err(ref e) if e.kind() == std::io::ErrorKind::WouldBlock => continue,
+                Err(e) => return Err(Error::Io(e))
+            }
+        }
+    }
+
+    async fn read_exact(&mut self, length: usize) -> Result<Vec<u8>, Error> {
+        while self.buffer.len() < length {
+            if !self.fill().await? {
+                return Err(Error::Parse("upstream closed the connection before sending the full body".into()));
+            }
+        }
+        Ok(self.buffer.drain(..length).collect())
+    }
+
+    async fn read_line(&mut self) -> Result<String, Error> {
+        loop {
+            if let Some(idx) = self.buffer.windows(2).position(|w| w == b"\r\n") {
+                let line: Vec<u8> = self.buffer.drain(..idx + 2).take(idx).collect();
+                return String::from_utf8(line).map_err(|e| Error::Parse(format!("{}", e)));
+            }
+            if !self.fill().await? {
+                return Err(Error::Parse("upstream closed the connection in the middle of a chunked body".into()));
+            }
+        }
+    }
+
+    async fn read_chunked(&mut self) -> Result<Vec<u8>, Error> {
+        let mut content = Vec::new();
+        loop {
+            let size_line = self.read_line().await?;
+            // Chunk extensions are ignored
+            let size_token = size_line.split(';').next().unwrap_or("").trim();
+            let size = usize::from_str_radix(size_token, 16).map_err(|e| Error::Parse(format!("invalid chunk size, {}", e)))?;
+            if size == 0 {
+                // Trailers are discarded, up to the final empty line
+                while !self.read_line().await?.is_empty() {}
+                return Ok(content);
+            }
+            content.extend(self.read_exact(size).await?);
+            if !self.read_line().await?.is_empty() {
+                return Err(Error::Parse("chunk is not followed by a line break".into()));
+            }
+        }
+    }
+
+    async fn read_to_end(&mut self) -> Result<Vec<u8>, Error> {
+        while self.fill().await? {}
+        Ok(std::mem::take(&mut self.buffer))
+    }
+}
\ No newline at end of file
@@ -0,0 +1,115 @@
+use crate::{LayerFn, Additional, Pipeline, http::{Request, Response}};
+use futures::future::FutureExt;
+use std::sync::Arc;
+
+/// Set of security headers to be added to every response of a branch
+///
+/// By default, it contains `X-Content-Type-Options: nosniff`, `X-Frame-Options: DENY` and `Referrer-Policy: strict-origin-when-cross-origin`. Strict transport security and a content security policy can be added through the builder methods.
+///
+/// ```rust,no_run
+/// use cataclysm::{Branch, SecurityHeaders, http::{Response, Method}};
+///
+/// let branch: Branch<()> = Branch::new("/")
+///     .with(Method::Get.to(|| async {Response::ok().body("hello")}))
+///     .layer(SecurityHeaders::new()
+///         .hsts(63_072_000, true)
+///         .content_security_policy("default-src 'self'")
+///         .layer()
+///     );
+/// ```
+///
+/// Headers already set by the handler (or by deeper layers) are never overwritten.
+#[derive(Clone)]
+pub struct SecurityHeaders {
+    headers: Vec<(String, String)>
+}
+
+impl SecurityHeaders {
+    /// Creates the default set of security headers
+    pub fn new() -> SecurityHeaders {
+        SecurityHeaders {
+            headers: vec![
+                ("X-Content-Type-Options".to_string(), "nosniff".to_string()),
+                ("X-Frame-Options".to_string(), "DENY".to_string()),
+                ("Referrer-Policy".to_string(), "strict-origin-when-cross-origin".to_string())
+            ]
+        }
+    }
+
+    /// Sets the `X-Frame-Options` value, `DENY` by default
+    pub fn frame_options<A: Into<String>>(self, value: A) -> Self {
+        self.set("X-Frame-Options", value)
+    }
+
+    /// Sets the `Referrer-Policy` value, `strict-origin-when-cross-origin` by default
+    pub fn referrer_policy<A: Into<String>>(self, value: A) -> Self {
+        self.set("Referrer-Policy", value)
+    }
+
+    /// Adds a `Strict-Transport-Security` header, with the given max age in seconds
+    ///
+    /// Only meaningful when the server is reached through https, as browsers ignore it otherwise.
+    pub fn hsts(self, max_age: u64, include_subdomains: bool) -> Self {
+        let value = if include_subdomains {
+            format!("max-age={}; includeSubDomains", max_age)
+        } else {
+            format!("max-age={}", max_age)
+        };
+        self.set("Strict-Transport-Security", value)
+    }
+
+    /// Adds a `Content-Security-Policy` header
+    pub fn content_security_policy<A: Into<String>>(self, policy: A) -> Self {
+        self.set("Content-Security-Policy", policy)
+    }
+
+    /// Removes one of the headers from the set, for example one of the defaults
+    pub fn remove<A: AsRef<str>>(mut self, name: A) -> Self {
+        self.headers.retain(|(k, _)| !k.eq_ignore_ascii_case(name.as_ref()));
+        self
+    }
+
+    /// Adds any other header to the set, replacing a previous value for it
+    pub fn set<A: Into<String>, B: Into<String>>(mut self, name: A, value: B) -> Self {
+        let name = name.into();
+        self.headers.retain(|(k, _)| !k.eq_ignore_ascii_case(&name));
+        self.headers.push((name, value.into()));
+        self
+    }
+
+    /// Builds the layer, to be passed to [Branch::layer](crate::Branch::layer)
+    pub fn layer<T: 'static + Sync + Send>(self) -> LayerFn<T> {
+        let headers = Arc::new(self.headers);
+        Box::new(move |req: Request, pipeline: Box<Pipeline<T>>, additional: Arc<Additional<T>>| {
+            let headers = headers.clone();
+            async move {
+                let mut response: Response = pipeline.execute(req, additional).await;
+                for (name, value) in headers.iter() {
+                    if !response.headers().keys().any(|k| k.eq_ignore_ascii_case(name)) {
+                        response.insert_header(name.clone(), value.clone());
+                    }
+                }
+                response
+            }.boxed()
+        })
+    }
+}
+
+impl Default for SecurityHeaders {
+    fn default() -> Self {
+        SecurityHeaders::new()
+    }
+}
+
+/// Layer with the default [SecurityHeaders](SecurityHeaders)
+///
+/// ```rust,no_run
+/// use cataclysm::{Branch, security_headers_layer, http::{Response, Method}};
+///
+/// let branch: Branch<()> = Branch::new("/")
+///     .with(Method::Get.to(|| async {Response::ok().body("hello")}))
+///     .layer(security_headers_layer());
+/// ```
+pub fn security_headers_layer<T: 'static + Sync + Send>() -> LayerFn<T> {
+    SecurityHeaders::new().layer()
+}
\ No newline at end of file
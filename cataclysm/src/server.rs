@@ -3,31 +3,64 @@ use tokio::{
     net::{TcpListener}
     //io::AsyncWriteExt
 };
-use bytes::Buf;
-use crate::metafunctions::callback::PipelineKind;
+#[cfg(unix)]
+use tokio::net::UnixListener;
+use bytes::{Buf, BytesMut};
+use futures::future::FutureExt;
+use crate::metafunctions::{ExtractionErrorFn, extraction_error_response, callback::PipelineKind};
 use crate::{
-    Stream,
-    Branch, Shared, Additional, Cors, branch::PureBranch, Pipeline, Error, session::SessionCreator,
-    http::{Request, Response, Method}
+    Stream, stream::Connection,
+    Branch, Shared, shared::DataMap, Additional, Cors, branch::PureBranch, CoreFn, Callback, Extractor, Error, session::SessionCreator, ServerStats, stats::Counters,
+    http::{Request, Response, Method, Version, PeerAddr}
 };
 use std::sync::{Arc};
+use std::panic::AssertUnwindSafe;
 
 // Default max connections for the server
 const MAX_CONNECTIONS: usize = 2_000;
+// Default max websocket connections for the server
+#[cfg(feature = "ws")]
+const MAX_WEBSOCKET_CONNECTIONS: usize = 10_000;
 const RESPONSE_CHUNK_SIZE: usize = 4_096;
 const READ_CHUNK_SIZE: usize = 8_192;
 
+/// Function that creates the response for a panicking handler
+type PanicFn = dyn Fn(String) -> Response + Send + Sync;
+/// Function that modifies requests before routing
+type RewriteFn = dyn Fn(&mut Request) + Send + Sync;
+
 /// Builder pattern for the server structure
 ///
 /// It is the main method for building a server and configuring certain behaviour
 pub struct ServerBuilder<T> {
     branch: Branch<T>,
     shared: Option<Shared<T>>,
+    data: DataMap,
     session_creator: Option<Arc<Box<dyn SessionCreator>>>,
     log_string: Option<String>,
+    log_json: bool,
     cors: Option<Cors>,
     max_connections: usize,
-    timeout: std::time::Duration
+    #[cfg(feature = "ws")]
+    max_websocket_connections: usize,
+    max_body_size: Option<usize>,
+    max_header_size: usize,
+    max_header_count: usize,
+    timeout: Option<std::time::Duration>,
+    read_timeout: Option<std::time::Duration>,
+    handler_timeout: Option<std::time::Duration>,
+    content_sniffing: bool,
+    trusted_proxies: usize,
+    allow_trace: bool,
+    method_override: bool,
+    tcp_nodelay: bool,
+    tcp_keepalive: Option<std::time::Duration>,
+    not_found: Option<Arc<CoreFn<T>>>,
+    panic_handler: Option<Box<PanicFn>>,
+    extraction_error: Option<Arc<ExtractionErrorFn>>,
+    rewrites: Vec<Box<RewriteFn>>,
+    base_path: Option<String>,
+    server_header: Option<String>
 }
 
 impl<T: Sync + Send> ServerBuilder<T> {
@@ -43,11 +76,32 @@ impl<T: Sync + Send> ServerBuilder<T> {
         ServerBuilder {
             branch,
             shared: None,
+            data: DataMap::new(),
             session_creator: None,
             log_string: None,
+            log_json: false,
             cors: None,
             max_connections: MAX_CONNECTIONS,
-            timeout: std::time::Duration::from_millis(15_000)
+            #[cfg(feature = "ws")]
+            max_websocket_connections: MAX_WEBSOCKET_CONNECTIONS,
+            max_body_size: None,
+            max_header_size: 32 * 1024,
+            max_header_count: 100,
+            timeout: None,
+            read_timeout: None,
+            handler_timeout: None,
+            content_sniffing: false,
+            trusted_proxies: 0,
+            allow_trace: true,
+            method_override: false,
+            tcp_nodelay: false,
+            tcp_keepalive: None,
+            not_found: None,
+            panic_handler: None,
+            extraction_error: None,
+            rewrites: Vec::new(),
+            base_path: None,
+            server_header: Some("cataclysm".to_string())
         }
     }
 
@@ -79,6 +133,37 @@ impl<T: Sync + Send> ServerBuilder<T> {
         self
     }
 
+    /// Declare a value to be retrieved by its type with the [Data](crate::Data) extractor
+    ///
+    /// ```rust,no_run
+    /// use cataclysm::{Server, Branch, Data, http::{Response, Method}};
+    ///
+    /// struct Config {
+    ///     greeting: String
+    /// }
+    ///
+    /// async fn index(config: Data<Config>, visits: Data<std::sync::atomic::AtomicUsize>) -> Response {
+    ///     let count = visits.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+    ///     Response::ok().body(format!("{}, visitor {}", config.greeting, count))
+    /// }
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let branch: Branch<()> = Branch::new("/").with(Method::Get.to(index));
+    ///     let server = Server::builder(branch)
+    ///         .data(Config{greeting: "Hello".to_string()})
+    ///         .data(std::sync::atomic::AtomicUsize::new(0))
+    ///         .build().unwrap();
+    ///     server.run("127.0.0.1:8000").await.unwrap();
+    /// }
+    /// ```
+    ///
+    /// Unlike [share](ServerBuilder::share), this function can be called several times, once per type. Declaring a second value of the same type replaces the first one. Layers can reach the values through [Additional::get](crate::Additional::get).
+    pub fn data<D: 'static + Send + Sync>(mut self, data: D) -> ServerBuilder<T> {
+        self.data.insert(std::any::TypeId::of::<D>(), Arc::new(data));
+        self
+    }
+
     /// Sets a session creator for the Session extractor to work
     ///
     /// ```rust,no_run
@@ -104,7 +189,7 @@ impl<T: Sync + Send> ServerBuilder<T> {
     /// }
     /// ```
     ///
-    /// If no secret is provided, a random key will be used (generated by ring).
+    /// The secret used to sign the cookies belongs to the session creator, see [CookieSession::secret](crate::session::CookieSession::secret). Without one, a random key is generated on each start, so sessions don't survive restarts nor are shared among instances.
     pub fn session_creator<A: 'static + SessionCreator>(mut self, session_creator: A) -> Self {
         self.session_creator = Some(Arc::new(Box::new(session_creator)));
         self
@@ -124,8 +209,11 @@ impl<T: Sync + Send> ServerBuilder<T> {
     /// 
     /// * `%M`: Method from the request
     /// * `%P`: Path from the request
+    /// * `%R`: Template of the route that matched, like `/users/{:id}`, or `-` if none did. Handy to group logs and metrics without a label per id
     /// * `%S`: Status from the response
     /// * `%A`: Socket address and port from the connection
+    /// * `%T`: Time taken to produce the response, in milliseconds. Layers can override it through the [RequestContext](crate::http::RequestContext)
+    /// * `%b`: Size of the response body, in bytes
     /// * `%F`: Responder path, where the callback was found (if any). Only available with the `full_log` feature.
     /// * `%f`: Same as previous but skipping file serving.
     /// (more data to be added soon)
@@ -134,6 +222,22 @@ impl<T: Sync + Send> ServerBuilder<T> {
         self
     }
 
+    /// Logs each call as a json object, for structured log ingestion
+    ///
+    /// ```rust,no_run
+    /// # use cataclysm::{Server, Branch, http::{Response, Method}};
+    /// // Tree structure
+    /// let branch: Branch<()> = Branch::new("/").with(Method::Get.to(|| async {Response::ok()}));
+    /// // Now we configure the server
+    /// let server = Server::builder(branch).log_json(true).build().unwrap();
+    /// ```
+    ///
+    /// Each line contains the `method`, `path`, `route` (null if no route matched), `status`, `addr`, `time_ms` and `bytes` fields. When enabled, this takes precedence over the [log_format](ServerBuilder::log_format) string.
+    pub fn log_json(mut self, log_json: bool) -> Self {
+        self.log_json = log_json;
+        self
+    }
+
     /// Adds the cors "middleware"
     ///
     /// ```rust,no_run
@@ -167,6 +271,67 @@ impl<T: Sync + Send> ServerBuilder<T> {
         self
     }
 
+    /// Sets up a maximum number of websocket connections, separate from the [max_connections](ServerBuilder::max_connections) limit
+    ///
+    /// ```rust,no_run
+    /// # use cataclysm::{Server, Branch, http::{Response, Method}};
+    /// // Tree structure
+    /// let branch: Branch<()> = Branch::new("/").with(Method::Get.to(|| async {Response::ok()}));
+    /// // Now we configure the server
+    /// let server = Server::builder(branch).max_websocket_connections(500).build().unwrap();
+    /// ```
+    ///
+    /// Upgrade requests hand back their http permit and take one from this pool instead, so long-lived websockets don't starve normal traffic. When the pool is exhausted, upgrade requests get a `503 Service Unavailable` response. Defaults to 10,000.
+    #[cfg(feature = "ws")]
+    pub fn max_websocket_connections(mut self, n: usize) -> Self {
+        self.max_websocket_connections = n;
+        self
+    }
+
+    /// Sets up a maximum size, in bytes, for request bodies
+    ///
+    /// ```rust,no_run
+    /// # use cataclysm::{Server, Branch, http::{Response, Method}};
+    /// // Tree structure
+    /// let branch: Branch<()> = Branch::new("/").with(Method::Get.to(|| async {Response::ok()}));
+    /// // Now we configure the server
+    /// let server = Server::builder(branch).max_body_size(1_048_576).build().unwrap();
+    /// ```
+    ///
+    /// Requests announcing a bigger `Content-Length` get a `413 Payload Too Large` response, and the connection is closed. With the `compression` feature, the limit also applies to the decompressed body, which prevents zip bombs. Unlimited by default. Branches can set their own limit with [Branch::max_body_size](crate::Branch::max_body_size).
+    pub fn max_body_size(mut self, n: usize) -> Self {
+        self.max_body_size = Some(n);
+        self
+    }
+
+    /// Sets the maximum size of the request header, in bytes, counting the request line
+    ///
+    /// ```rust,no_run
+    /// # use cataclysm::{Server, Branch, http::{Response, Method}};
+    /// let branch: Branch<()> = Branch::new("/").with(Method::Get.to(|| async {Response::ok()}));
+    /// let server = Server::builder(branch).max_header_size(8 * 1024).build().unwrap();
+    /// ```
+    ///
+    /// Bigger headers get a `431 Request Header Fields Too Large` response, and the connection is closed. 32 KiB by default.
+    pub fn max_header_size(mut self, n: usize) -> Self {
+        self.max_header_size = n;
+        self
+    }
+
+    /// Sets the maximum number of header lines a request can have
+    ///
+    /// ```rust,no_run
+    /// # use cataclysm::{Server, Branch, http::{Response, Method}};
+    /// let branch: Branch<()> = Branch::new("/").with(Method::Get.to(|| async {Response::ok()}));
+    /// let server = Server::builder(branch).max_header_count(50).build().unwrap();
+    /// ```
+    ///
+    /// Requests with more headers get a `431 Request Header Fields Too Large` response, and the connection is closed. 100 by default.
+    pub fn max_header_count(mut self, n: usize) -> Self {
+        self.max_header_count = n;
+        self
+    }
+
     /// Sets up a custom timeout for http requests to be finished
     ///
     /// ```rust,no_run
@@ -177,8 +342,277 @@ impl<T: Sync + Send> ServerBuilder<T> {
     /// // Now we configure the server
     /// let server = Server::builder(branch).timeout(Duration::from_millis(5_000)).build().unwrap();
     /// ```
+    ///
+    /// This value is used both as [read_timeout](ServerBuilder::read_timeout) and [handler_timeout](ServerBuilder::handler_timeout), unless they are set explicitly. When no timeout is configured, the read timeout defaults to 15 seconds and handlers are not limited.
     pub fn timeout(mut self, duration: std::time::Duration) -> Self {
-        self.timeout = duration;
+        self.timeout = Some(duration);
+        self
+    }
+
+    /// Sets up the maximum time to wait for the client to send a request
+    ///
+    /// ```rust,no_run
+    /// # use cataclysm::{Server, Branch, http::{Response, Method}};
+    /// use std::time::Duration;
+    /// // Tree structure
+    /// let branch: Branch<()> = Branch::new("/").with(Method::Get.to(|| async {Response::ok()}));
+    /// // Slow clients get disconnected quickly, while handlers can take up to a minute
+    /// let server = Server::builder(branch)
+    ///     .read_timeout(Duration::from_millis(2_000))
+    ///     .handler_timeout(Duration::from_secs(60))
+    ///     .build().unwrap();
+    /// ```
+    ///
+    /// When this time is exceeded, the connection gets closed. Defaults to 15 seconds. Keep-alive connections use a 5 seconds idle timeout after the first request.
+    pub fn read_timeout(mut self, duration: std::time::Duration) -> Self {
+        self.read_timeout = Some(duration);
+        self
+    }
+
+    /// Sets up the maximum time that the callback pipeline has to produce a response
+    ///
+    /// ```rust,no_run
+    /// # use cataclysm::{Server, Branch, http::{Response, Method}};
+    /// use std::time::Duration;
+    /// // Tree structure
+    /// let branch: Branch<()> = Branch::new("/").with(Method::Get.to(|| async {Response::ok()}));
+    /// // Now we configure the server
+    /// let server = Server::builder(branch).handler_timeout(Duration::from_secs(60)).build().unwrap();
+    /// ```
+    ///
+    /// By default, handlers can take as long as they need. When this time is exceeded, the client gets a `503 Service Unavailable` response, and the connection is closed. Stream callbacks are not affected by this timeout.
+    pub fn handler_timeout(mut self, duration: std::time::Duration) -> Self {
+        self.handler_timeout = Some(duration);
+        self
+    }
+
+    /// Enables content sniffing for static file serving
+    ///
+    /// When the extension of a served file is not found among the known mime types, the first bytes of the file get inspected to guess its type (png, jpeg, pdf, gif, etc.), instead of replying with `application/octet-stream`.
+    ///
+    /// ```rust,no_run
+    /// # use cataclysm::{Server, Branch};
+    /// // Tree structure
+    /// let branch: Branch<()> = Branch::new("/").files("./static");
+    /// // Now we configure the server
+    /// let server = Server::builder(branch).content_sniffing(true).build().unwrap();
+    /// ```
+    ///
+    /// Disabled by default.
+    pub fn content_sniffing(mut self, content_sniffing: bool) -> Self {
+        self.content_sniffing = content_sniffing;
+        self
+    }
+
+    /// Sets up the number of reverse proxies in front of the server, used by the [ClientIp](crate::http::ClientIp) extractor and by [Request::scheme](crate::http::Request::scheme)
+    ///
+    /// ```rust,no_run
+    /// # use cataclysm::{Server, Branch, http::{Response, Method}};
+    /// // Tree structure
+    /// let branch: Branch<()> = Branch::new("/").with(Method::Get.to(|| async {Response::ok()}));
+    /// // A single load balancer sits in front of the server
+    /// let server = Server::builder(branch).trusted_proxies(1).build().unwrap();
+    /// ```
+    ///
+    /// Only the last `n` entries of the `X-Forwarded-For` header are trusted, as anything before them could have been written by the client itself. Defaults to 0, meaning that forwarding headers are ignored.
+    pub fn trusted_proxies(mut self, n: usize) -> Self {
+        self.trusted_proxies = n;
+        self
+    }
+
+    /// Allows or refuses `TRACE` requests for the whole server
+    ///
+    /// ```rust,no_run
+    /// # use cataclysm::{Server, Branch, http::{Response, Method}};
+    /// // Tree structure
+    /// let branch: Branch<()> = Branch::new("/").with(Method::Get.to(|| async {Response::ok()}));
+    /// // Trace requests get a `405 Method Not Allowed`
+    /// let server = Server::builder(branch).allow_trace(false).build().unwrap();
+    /// ```
+    ///
+    /// Refused requests never reach the routing tree, not even the default callbacks, which avoids echoing sensitive headers back (what is known as cross-site tracing). Enabled by default, in which case `TRACE` is routed like any other method.
+    pub fn allow_trace(mut self, allow_trace: bool) -> Self {
+        self.allow_trace = allow_trace;
+        self
+    }
+
+    /// Lets `POST` requests choose the method they are routed with, for clients that can only send `GET` and `POST`, like html forms
+    ///
+    /// ```rust,no_run
+    /// # use cataclysm::{Server, Branch, http::{Response, Method}};
+    /// let branch: Branch<()> = Branch::new("/posts/{:id}").with(Method::Delete.to(|| async {Response::ok()}));
+    /// // A form posting `_method=DELETE` reaches the delete handler
+    /// let server = Server::builder(branch).allow_method_override().build().unwrap();
+    /// ```
+    ///
+    /// The method is taken from the `X-HTTP-Method-Override` header, or else from the `_method` field of a url encoded form. Only `PUT`, `DELETE` and `PATCH` are accepted, other values are ignored. Disabled by default.
+    pub fn allow_method_override(mut self) -> Self {
+        self.method_override = true;
+        self
+    }
+
+    /// Sets the `TCP_NODELAY` option on the accepted connections
+    ///
+    /// ```rust,no_run
+    /// # use cataclysm::{Server, Branch, http::{Response, Method}};
+    /// let branch: Branch<()> = Branch::new("/").with(Method::Get.to(|| async {Response::ok()}));
+    /// let server = Server::builder(branch).tcp_nodelay(true).build().unwrap();
+    /// ```
+    ///
+    /// Disabling Nagle's algorithm sends small responses right away, instead of waiting to group them with more data, which reduces latency. Disabled by default, as in the operating system.
+    pub fn tcp_nodelay(mut self, nodelay: bool) -> Self {
+        self.tcp_nodelay = nodelay;
+        self
+    }
+
+    /// Enables TCP keepalive probes on the accepted connections, after the given idle time
+    ///
+    /// ```rust,no_run
+    /// # use cataclysm::{Server, Branch, http::{Response, Method}};
+    /// # use std::time::Duration;
+    /// let branch: Branch<()> = Branch::new("/").with(Method::Get.to(|| async {Response::ok()}));
+    /// let server = Server::builder(branch).tcp_keepalive(Some(Duration::from_secs(60))).build().unwrap();
+    /// ```
+    ///
+    /// Probes let the operating system detect peers that disappeared without closing the connection, which matters for long lived connections like websockets. `None`, the default, leaves the option untouched.
+    pub fn tcp_keepalive(mut self, idle_time: Option<std::time::Duration>) -> Self {
+        self.tcp_keepalive = idle_time;
+        self
+    }
+
+    /// Sets up the handler for the requests that no branch matches
+    ///
+    /// ```rust,no_run
+    /// # use cataclysm::{Server, Branch, http::{Response, Method, Request}};
+    /// // Tree structure
+    /// let branch: Branch<()> = Branch::new("/").with(Method::Get.to(|| async {Response::ok()}));
+    /// // Unknown paths get a json error
+    /// let server = Server::builder(branch).not_found(|req: Request| async move {
+    ///     Response::not_found()
+    ///         .header("Content-Type", "application/json")
+    ///         .body(format!("{{\"error\": \"{} not found\"}}", req.path()))
+    /// }).build().unwrap();
+    /// ```
+    ///
+    /// The handler receives the same extractors as any other callback, and gets wrapped by the layers of the root branch. Unlike [defaults_to](crate::Branch::defaults_to), which only covers a subtree, this applies to every unmatched request, websocket upgrades included. By default, a plain `404 Not Found` is returned.
+    pub fn not_found<F: Callback<A> + Send + Sync + 'static, A: Extractor<T>>(mut self, callback: F) -> Self {
+        self.not_found = Some(Arc::new(Box::new(move |req: Request, additional: Arc<Additional<T>>|  {
+            match <A as Extractor<T>>::extract(&req, additional.clone()) {
+                Ok(args) => callback.invoke(args).boxed(),
+                Err(e) => {
+                    let response = extraction_error_response(&req, &additional, e);
+                    (async {response}).boxed()
+                }
+            }
+        })));
+        self
+    }
+
+    /// Sets up the response given when a handler panics
+    ///
+    /// ```rust,no_run
+    /// # use cataclysm::{Server, Branch, http::{Response, Method}};
+    /// // Tree structure
+    /// let branch: Branch<()> = Branch::new("/").with(Method::Get.to(|| async {
+    ///     panic!("something went really wrong");
+    ///     #[allow(unreachable_code)]
+    ///     Response::ok()
+    /// }));
+    /// // Panics get a json error
+    /// let server = Server::builder(branch).on_panic(|_message| {
+    ///     Response::internal_server_error()
+    ///         .header("Content-Type", "application/json")
+    ///         .body("{\"error\": \"internal\"}")
+    /// }).build().unwrap();
+    /// ```
+    ///
+    /// Panics from handlers, extractors and layers are always caught and logged, the function receives the panic message. By default, an empty `500 Internal Server Error` is returned.
+    pub fn on_panic<F: 'static + Fn(String) -> Response + Send + Sync>(mut self, panic_handler: F) -> Self {
+        self.panic_handler = Some(Box::new(panic_handler));
+        self
+    }
+
+    /// Sets up the response given when an extractor fails
+    ///
+    /// ```rust,no_run
+    /// # use cataclysm::{Server, Branch, http::{Response, Method, Json}};
+    /// // Tree structure
+    /// let branch: Branch<()> = Branch::new("/").with(Method::Post.to(|Json(value): Json<serde_json::Value>| async move {
+    ///     Response::ok().body(value.to_string())
+    /// }));
+    /// // Malformed bodies get the same error envelope as the rest of the api
+    /// let server = Server::builder(branch).on_extraction_error(|error| {
+    ///     Response::bad_request()
+    ///         .header("Content-Type", "application/json")
+    ///         .body(format!("{{\"error\": \"{}\"}}", error))
+    /// }).build().unwrap();
+    /// ```
+    ///
+    /// Branches can replace it for their subtree with [on_extraction_error](crate::Branch::on_extraction_error). By default, an empty `400 Bad Request` is returned (or a json detail of the error, with the `full_log` feature).
+    pub fn on_extraction_error<F: 'static + Fn(Error) -> Response + Send + Sync>(mut self, extraction_error: F) -> Self {
+        self.extraction_error = Some(Arc::new(extraction_error));
+        self
+    }
+
+    /// Adds a function that modifies each request before it gets routed
+    ///
+    /// ```rust,no_run
+    /// # use cataclysm::{Server, Branch, http::{Response, Method, Request}};
+    /// // Tree structure
+    /// let branch: Branch<()> = Branch::new("/users").with(Method::Get.to(|| async {Response::ok()}));
+    /// // Trailing slashes are ignored, and every request gets tagged
+    /// let server = Server::builder(branch).rewrite(|req: &mut Request| {
+    ///     let path = req.path().trim_end_matches('/').to_string();
+    ///     if !path.is_empty() {
+    ///         req.url_mut().set_path(&path);
+    ///     }
+    ///     req.headers.insert("X-Rewritten".to_string(), vec!["true".to_string()]);
+    /// }).build().unwrap();
+    /// ```
+    ///
+    /// Rewrites run as soon as the request header is parsed, in the order they were added. That is, before the body is read, before cors preflights, and before the routing tree picks a handler, so the layers and the handler only see the rewritten request.
+    pub fn rewrite<F: 'static + Fn(&mut Request) + Send + Sync>(mut self, rewrite: F) -> Self {
+        self.rewrites.push(Box::new(rewrite));
+        self
+    }
+
+    /// Mounts the whole routing tree under a path prefix
+    ///
+    /// ```rust,no_run
+    /// # use cataclysm::{Server, Branch, http::{Response, Method}};
+    /// // Tree structure, without any reference to the prefix
+    /// let branch: Branch<()> = Branch::new("/users").with(Method::Get.to(|| async {Response::ok()}));
+    /// // Replies on `/api/users`
+    /// let server = Server::builder(branch).base_path("/api").build().unwrap();
+    /// ```
+    ///
+    /// The prefix is removed from the path before any [rewrite](ServerBuilder::rewrite) and before routing, and it remains available through [Request::base_path](crate::http::Request::base_path) to create links. Requests whose path does not start with the prefix get a `404 Not Found`.
+    pub fn base_path<A: AsRef<str>>(mut self, base_path: A) -> Self {
+        let base_path = base_path.as_ref().trim_matches('/');
+        self.base_path = if base_path.is_empty() {
+            None
+        } else {
+            Some(format!("/{}", base_path))
+        };
+        self
+    }
+
+    /// Sets the value of the `Server` header sent with every response, `cataclysm` by default
+    ///
+    /// ```rust,no_run
+    /// # use cataclysm::{Server, Branch, http::{Response, Method}};
+    /// let branch: Branch<()> = Branch::new("/").with(Method::Get.to(|| async {Response::ok()}));
+    /// let server = Server::builder(branch).server_header("my-service/1.0").build().unwrap();
+    /// ```
+    ///
+    /// An empty value removes the header altogether. A `Server` header set by a handler is never replaced.
+    pub fn server_header<A: Into<String>>(mut self, server_header: A) -> Self {
+        let server_header = server_header.into();
+        self.server_header = if server_header.is_empty() {
+            None
+        } else {
+            Some(server_header)
+        };
         self
     }
 
@@ -207,12 +641,33 @@ impl<T: Sync + Send> ServerBuilder<T> {
             pure_branch: Arc::new(self.branch.purify()),
             additional: Arc::new(Additional {
                 shared: self.shared,
-                session_creator: self.session_creator
+                data: self.data,
+                session_creator: self.session_creator,
+                content_sniffing: self.content_sniffing,
+                trusted_proxies: self.trusted_proxies,
+                extraction_error: self.extraction_error,
+                stats: Arc::new(Counters::default())
             }),
             log_string: Arc::new(self.log_string),
+            log_json: self.log_json,
             cors: Arc::new(self.cors),
             max_connections: Arc::new(Semaphore::new(self.max_connections)),
-            timeout: Arc::new(self.timeout)
+            #[cfg(feature = "ws")]
+            max_websocket_connections: Arc::new(Semaphore::new(self.max_websocket_connections)),
+            max_body_size: self.max_body_size,
+            max_header_size: self.max_header_size,
+            max_header_count: self.max_header_count,
+            read_timeout: self.read_timeout.or(self.timeout).unwrap_or(std::time::Duration::from_millis(15_000)),
+            handler_timeout: self.handler_timeout.or(self.timeout),
+            allow_trace: self.allow_trace,
+            method_override: self.method_override,
+            tcp_nodelay: self.tcp_nodelay,
+            tcp_keepalive: self.tcp_keepalive,
+            not_found: self.not_found,
+            panic_handler: self.panic_handler,
+            rewrites: self.rewrites,
+            base_path: self.base_path,
+            server_header: self.server_header
         }))
     }
 }
@@ -224,9 +679,25 @@ pub struct Server<T> {
     pure_branch: Arc<PureBranch<T>>,
     additional: Arc<Additional<T>>,
     log_string: Arc<Option<String>>,
+    log_json: bool,
     cors: Arc<Option<Cors>>,
     max_connections: Arc<Semaphore>,
-    timeout: Arc<std::time::Duration>
+    #[cfg(feature = "ws")]
+    max_websocket_connections: Arc<Semaphore>,
+    max_body_size: Option<usize>,
+    max_header_size: usize,
+    max_header_count: usize,
+    read_timeout: std::time::Duration,
+    handler_timeout: Option<std::time::Duration>,
+    allow_trace: bool,
+    method_override: bool,
+    tcp_nodelay: bool,
+    tcp_keepalive: Option<std::time::Duration>,
+    not_found: Option<Arc<CoreFn<T>>>,
+    panic_handler: Option<Box<PanicFn>>,
+    rewrites: Vec<Box<RewriteFn>>,
+    base_path: Option<String>,
+    server_header: Option<String>
 }
 
 impl<T: 'static + Sync + Send> Server<T> {
@@ -235,9 +706,120 @@ impl<T: 'static + Sync + Send> Server<T> {
         ServerBuilder::new(branch)
     }
 
+    /// Gives back a snapshot of the traffic counters of the server
+    ///
+    /// ```rust,no_run
+    /// use cataclysm::{Server, Branch, http::{Response, Method}};
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let branch: Branch<()> = Branch::new("/").with(Method::Get.to(|| async {Response::ok()}));
+    ///     let server = Server::builder(branch).build().unwrap();
+    ///     let monitored = server.clone();
+    ///     tokio::spawn(async move {
+    ///         loop {
+    ///             tokio::time::sleep(std::time::Duration::from_secs(60)).await;
+    ///             let stats = monitored.stats();
+    ///             log::info!("{} requests, {} active connections", stats.requests, stats.active_connections);
+    ///         }
+    ///     });
+    ///     server.run("127.0.0.1:8000").await.unwrap();
+    /// }
+    /// ```
+    ///
+    /// Counters are plain atomics, updated as connections are accepted and requests are read and answered. Handlers can get the same snapshot with the [ServerStats](crate::ServerStats) extractor.
+    pub fn stats(&self) -> ServerStats {
+        self.additional.stats.snapshot()
+    }
+
+    /// Runs the server on the given address, within the current tokio runtime
     pub async fn run<S: AsRef<str>>(self: &Arc<Self>, socket: S) -> Result<(), Error> {
         let listener = TcpListener::bind(socket.as_ref()).await.map_err(|e| Error::Io(e))?;
+        self.serve(vec![Listener::Tcp(listener)]).await
+    }
+
+    /// Runs the server on a runtime managed elsewhere, through its handle
+    ///
+    /// The server gets spawned as a task in the given runtime, so this function can be called from outside of any async context. Connections are handled by the same runtime, which makes it possible to tune its worker threads, or to share it with other components of the application.
+    ///
+    /// ```rust,no_run
+    /// use cataclysm::{Server, Branch, http::{Response, Method}};
+    ///
+    /// fn main() {
+    ///     let runtime = tokio::runtime::Builder::new_multi_thread()
+    ///         .worker_threads(4)
+    ///         .enable_all()
+    ///         .build().unwrap();
+    ///     let branch: Branch<()> = Branch::new("/").with(Method::Get.to(|| async {Response::ok().body("Ok!")}));
+    ///     let server = Server::builder(branch).build().unwrap();
+    ///     let task = server.run_on(runtime.handle(), "127.0.0.1:8000");
+    ///     runtime.block_on(task).unwrap().unwrap();
+    /// }
+    /// ```
+    ///
+    /// Dropping the returned handle does not stop the server, use its `abort` method for that.
+    pub fn run_on<S: AsRef<str>>(self: &Arc<Self>, handle: &tokio::runtime::Handle, socket: S) -> tokio::task::JoinHandle<Result<(), Error>> {
+        let server = Arc::clone(self);
+        let socket = socket.as_ref().to_string();
+        handle.spawn(async move {
+            server.run(socket).await
+        })
+    }
+
+    /// Runs the server on several addresses at the same time
+    ///
+    /// All the listeners share the same server configuration, including the [max_connections](ServerBuilder::max_connections) limit.
+    ///
+    /// ```rust,no_run
+    /// use cataclysm::{Server, Branch, http::{Response, Method}};
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let branch: Branch<()> = Branch::new("/").with(Method::Get.to(|| async {Response::ok().body("Ok!")}));
+    ///     let server = Server::builder(branch).build().unwrap();
+    ///     // Both ipv4 and ipv6 loopback interfaces
+    ///     server.run_multi(&["127.0.0.1:8000", "[::1]:8000"]).await.unwrap();
+    /// }
+    /// ```
+    ///
+    /// If any of the addresses can't be bound, the server won't start, and the returned error indicates the failing address.
+    pub async fn run_multi<S: AsRef<str>>(self: &Arc<Self>, sockets: &[S]) -> Result<(), Error> {
+        if sockets.is_empty() {
+            return Err(Error::custom("at least one address is required to run the server"));
+        }
+        let mut listeners = Vec::with_capacity(sockets.len());
+        for socket in sockets {
+            let listener = TcpListener::bind(socket.as_ref()).await
+                .map_err(|e| Error::Io(std::io::Error::new(e.kind(), format!("could not bind to {}, {}", socket.as_ref(), e))))?;
+            listeners.push(Listener::Tcp(listener));
+        }
+        self.serve(listeners).await
+    }
+
+    /// Runs the server on a Unix domain socket
+    ///
+    /// Useful when the server sits behind a reverse proxy in the same host, avoiding the tcp overhead.
+    ///
+    /// ```rust,no_run
+    /// use cataclysm::{Server, Branch, http::{Response, Method}};
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let branch: Branch<()> = Branch::new("/").with(Method::Get.to(|| async {Response::ok().body("Ok!")}));
+    ///     let server = Server::builder(branch).build().unwrap();
+    ///     server.run_unix("/tmp/cataclysm.sock").await.unwrap();
+    /// }
+    /// ```
+    ///
+    /// The socket file must not exist beforehand. As Unix peers have no ip address, requests coming from this socket carry a [PeerAddr::Unix](crate::http::PeerAddr::Unix) address.
+    #[cfg(unix)]
+    pub async fn run_unix<P: AsRef<std::path::Path>>(self: &Arc<Self>, path: P) -> Result<(), Error> {
+        let listener = UnixListener::bind(path.as_ref()).map_err(Error::Io)?;
+        self.serve(vec![Listener::Unix(listener)]).await
+    }
 
+    /// Main accepting loop of the server, accepting connections from all the listeners
+    async fn serve(self: &Arc<Self>, listeners: Vec<Listener>) -> Result<(), Error> {
         log::info!("Cataclysm ongoing \u{26c8}");
         #[cfg(feature = "full_log")]
         log::warn!("using the `full_log` feature might impact performance and leak sensible information. Disable in production.");
@@ -260,16 +842,23 @@ impl<T: 'static + Sync + Send> Server<T> {
                         }
                     };
                     
-                    match listener.accept().await {
-                        Ok((socket, addr)) => {
+                    // The first listener to receive a connection wins, the rest of the accept calls are just dropped
+                    let (accepted, _, _) = futures::future::select_all(listeners.iter().map(|listener| Box::pin(listener.accept()))).await;
+                    match accepted {
+                        Ok((connection, addr)) => {
                             #[cfg(feature = "full_log")]
                             log::trace!("[server] socket connection accepted");
                             let server = Arc::clone(self);
 
-                            let stream = Stream::new(socket, Some(permit));
+                            server.apply_tcp_options(&connection);
+
+                            // The stream owns the permit, so it is given back when the connection is dropped, even if the task panics
+                            let stream = Stream::from_connection(connection, Some(permit));
+                            let connection_guard = server.additional.stats.connection();
                             
                             tokio::spawn(async move {
-                                match server.dispatch(stream, addr, *server.timeout).await {
+                                let _connection_guard = connection_guard;
+                                match server.dispatch(stream, addr, server.read_timeout).await {
                                     Ok(_) => {
                                         #[cfg(feature = "full_log")]
                                         log::trace!("[server] connection successfully dispatched");
@@ -298,63 +887,139 @@ impl<T: 'static + Sync + Send> Server<T> {
         Ok(())
     }
 
+    /// Removes the base path from the request, if any, and applies the rewrites
+    ///
+    /// The forwarding trust is set beforehand, so rewrites can rely on the scheme of the request.
+    fn rewrite_request(&self, request: &mut Request) {
+        request.trusted_proxies = self.additional.trusted_proxies;
+        if let Some(base_path) = self.base_path.as_deref() {
+            request.strip_base_path(base_path);
+        }
+        for rewrite in &self.rewrites {
+            rewrite(request);
+        }
+    }
+
+    /// Body size limit for the request, the one of the matching branch or else the server's one
+    fn max_body_size_for(&self, request: &Request) -> Option<usize> {
+        self.pure_branch.max_body_size(request).or(self.max_body_size)
+    }
+
+    /// Replaces the method of a `POST` request by the one it asks for, if any
+    fn override_method(request: &mut Request) {
+        if request.method != Method::Post {
+            return
+        }
+        let requested = match request.header("X-HTTP-Method-Override") {
+            Some(method) => Some(method.trim().to_uppercase()),
+            None if request.content_type().map(|ct| ct.is("application/x-www-form-urlencoded")).unwrap_or(false) => {
+                url::form_urlencoded::parse(&request.content).find(|(key, _)| key == "_method").map(|(_, method)| method.trim().to_uppercase())
+            },
+            None => None
+        };
+        if let Some(method @ ("PUT" | "DELETE" | "PATCH")) = requested.as_deref() {
+            request.method = method.into();
+        }
+    }
+
     /// Deals with the read part of the socket stream
-    async fn dispatch_read(socket: &Stream, addr: std::net::SocketAddr) -> Result<Option<Vec<u8>>, Error> {
-        let mut request_bytes = Vec::with_capacity(READ_CHUNK_SIZE);
+    ///
+    /// The read buffer lives as long as the connection, so it gets reused among requests. The request gets parsed only once, as soon as its header is complete, and the body is attached at the end.
+    ///
+    /// Requests for stream handlers are given back as soon as the header is complete, so the handler can read the body at its own pace.
+    async fn dispatch_read(&self, socket: &Stream, addr: &PeerAddr, buffer: &mut BytesMut) -> Result<Option<Request>, Error> {
         let mut expected_length = None;
-        let mut header_size = 0;
-        let mut request = None;
-        // First we read
+        let mut request: Option<Request> = None;
         loop {
-            socket.readable().await.map_err(|e| Error::Io(e))?;
-            
-            // being stored in the async task.
-            let mut buf = [0; READ_CHUNK_SIZE];
+            if request.is_none() {
+                // The header might come in several reads
+                if let Some(header_end) = buffer.windows(4).position(|w| w == b"\r\n\r\n") {
+                    if self.header_too_large(&buffer[..header_end]) {
+                        self.dispatch_write(socket, Response::request_header_fields_too_large().header("Connection", "close")).await?;
+                        return Ok(None)
+                    }
+                    request = match Request::parse_header(&buffer[..header_end], addr.clone()) {
+                        Ok(mut r) => {
+                            self.rewrite_request(&mut r);
 
-            // Try to read data, this may still fail with `WouldBlock`
-            // if the readiness event is a false positive.
-            match socket.try_read(&mut buf) {
-                Ok(0) => {
-                    break
-                },
-                Ok(n) => {
-                    request_bytes.extend_from_slice(&buf[0..n]);
-
-                    if request.is_none() {
-                        request = match Request::parse(request_bytes.clone(), addr) {
-                            Ok(r) => {
-                                // We check if we need to give a continue 100
-                                if r.headers.get("Expect").map(|h| h.get(0).map(|ih| ih == "100-continue")).flatten().unwrap_or(false) {
-                                    // We send it
-                                    Server::<T>::dispatch_write(&socket, Response::r#continue()).await?;
+                            // We check now if there is a content size hint
+                            expected_length = r.header("Content-Length").and_then(|v| v.parse::<usize>().ok());
+                            #[cfg(feature = "full_log")]
+                            log::trace!("expecting to read {:?} bytes in request", expected_length);
+                            if let (Some(expected_length), Some(max_body_size)) = (expected_length, self.max_body_size_for(&r)) {
+                                if expected_length > max_body_size {
+                                    // A client waiting for a continue gets the final answer instead, and never sends the body
+                                    self.dispatch_write(socket, Response::payload_too_large().header("Connection", "close")).await?;
+                                    return Ok(None)
                                 }
+                            }
 
-                                // We check now if there is a content size hint
-                                expected_length = r.headers.get("Content-Length").or_else(|| r.headers.get("content-length")).map(|cl| cl.get(0).map(|v| v.parse::<usize>().ok())).flatten().flatten();
+                            // The interim response is sent only once per request, and only if the client is still holding back a body
+                            let expects_continue = r.version() == Version::Http11 && r.header("Expect").map(|expect| expect.eq_ignore_ascii_case("100-continue")).unwrap_or(false);
+                            let body_pending = expected_length.map(|expected_length| expected_length > buffer.len() - r.header_size).unwrap_or(false);
+                            if expects_continue && body_pending {
                                 #[cfg(feature = "full_log")]
-                                log::trace!("expecting to read {:?} bytes in request", expected_length);
-                                header_size = r.header_size;
-                                Some(r)
-                            },
-                            Err(_e) => {
-                                #[cfg(feature = "full_log")]
-                                log::debug!("{}", _e);
-                                Server::<T>::dispatch_write(&socket, Response::bad_request()).await?;
-                                return Ok(None)
+                                log::trace!("[server] sending 100 continue");
+                                self.dispatch_write(socket, Response::r#continue()).await?;
                             }
-                        };
-                    }
-
-                    // And now we check if, given the hint, we need to act upon.
-                    if let Some(expected_length) = &expected_length {
-                        if *expected_length > request_bytes.len() - header_size {
-                            continue;
-                        } else {
-                            break;
+                            Some(r)
+                        },
+                        Err(_e) => {
+                            #[cfg(feature = "full_log")]
+                            log::debug!("{}", _e);
+                            self.dispatch_write(socket, Response::bad_request()).await?;
+                            return Ok(None)
                         }
-                    } else {
+                    };
+                } else if buffer.len() >= self.max_header_size {
+                    // Not even the end of the header fits in the limit
+                    self.dispatch_write(socket, Response::request_header_fields_too_large().header("Connection", "close")).await?;
+                    return Ok(None)
+                }
+            }
+
+            // And now we check if, given the hint, we need to act upon.
+            if let Some(request) = &mut request {
+                let body_length = match expected_length {
+                    #[cfg(feature = "stream")]
+                    Some(expected_length) if expected_length > buffer.len() - request.header_size && self.pure_branch.is_stream_handler(request) => {
+                        // The body stays in the buffer, and gets handed to the stream
+                        buffer.advance(request.header_size);
                         break;
-                    }
+                    },
+                    Some(expected_length) if expected_length > buffer.len() - request.header_size => None,
+                    Some(expected_length) => Some(expected_length),
+                    // Without a hint, anything after the header belongs to a stream handler
+                    #[cfg(feature = "stream")]
+                    None if self.pure_branch.is_stream_handler(request) => {
+                        buffer.advance(request.header_size);
+                        break;
+                    },
+                    // Otherwise there is no body (RFC 9112, section 6.3), and anything left in the buffer belongs to the next request
+                    None => Some(0)
+                };
+                if let Some(body_length) = body_length {
+                    buffer.advance(request.header_size);
+                    request.content = buffer.split_to(body_length).to_vec();
+                    break;
+                }
+            }
+
+            socket.readable().await.map_err(|e| Error::Io(e))?;
+
+            if buffer.capacity() - buffer.len() < READ_CHUNK_SIZE {
+                buffer.reserve(READ_CHUNK_SIZE);
+            }
+
+            // Try to read data, this may still fail with `WouldBlock`
+            // if the readiness event is a false positive.
+            match socket.try_read_buf(buffer) {
+                Ok(0) => {
+                    return Ok(None)
+                },
+                Ok(n) => {
+                    self.additional.stats.bytes_in(n);
+                    continue
                 },
                 Err(ref e) if e.kind() == std::io::ErrorKind::WouldBlock => {
                     continue
@@ -362,11 +1027,19 @@ impl<T: 'static + Sync + Send> Server<T> {
                 Err(e) => return Err(Error::Io(e))
             }
         }
-        Ok(Some(request_bytes))
+
+        Ok(request)
     }
 
-    async fn dispatch_write(socket: &Stream, mut response: Response) -> Result<(), Error> {
+    /// Indicates if a request header, without its final separator, goes over the configured limits
+    fn header_too_large(&self, header: &[u8]) -> bool {
+        // Every header line is preceded by a line break
+        header.len() + 4 > self.max_header_size || header.windows(2).filter(|w| w == b"\r\n").count() > self.max_header_count
+    }
+
+    async fn dispatch_write(&self, socket: &Stream, mut response: Response) -> Result<(), Error> {
         let serialized_response = response.serialize();
+        self.additional.stats.response(response.status.0, serialized_response.len());
         let mut chunks_iter = serialized_response.chunks(RESPONSE_CHUNK_SIZE);
         #[cfg(feature = "full_log")]
         log::trace!("writting {} chunks of maximum {} bytes each", chunks_iter.len(), RESPONSE_CHUNK_SIZE);
@@ -404,9 +1077,211 @@ impl<T: 'static + Sync + Send> Server<T> {
         }
     }
 
-    async fn dispatch(self: &Arc<Self>, stream: Stream, addr: std::net::SocketAddr, mut timeout: std::time::Duration) -> Result<(), Error> {
+    /// Runs a request through the server in-process, without touching the network
+    ///
+    /// The raw request is parsed as if it came from a connection, and goes through the same rewrites, routing, extractors and layers, which makes tests fast and deterministic. [BasicRequest](crate::http::BasicRequest) can be used to build it.
+    ///
+    /// ```rust
+    /// use cataclysm::{Server, Branch, http::{Response, Method, Path, BasicRequest}};
+    ///
+    /// # #[tokio::main(flavor = "current_thread")]
+    /// # async fn main() {
+    /// let branch: Branch<()> = Branch::new("/users/{:id}").with(Method::Get.to(|path: Path<(u32,)>| async move {
+    ///     let (id,) = path.into_inner();
+    ///     Response::ok().body(format!("user {}", id))
+    /// }));
+    /// let server = Server::builder(branch).build().unwrap();
+    ///
+    /// let request = BasicRequest::new(Method::Get, "http://localhost/users/7").unwrap().header("Accept", "text/plain");
+    /// let response = server.test_request(request.serialize()).await.unwrap();
+    /// assert_eq!(response.status_code(), 200);
+    /// assert_eq!(response.content, b"user 7");
+    /// # }
+    /// ```
+    ///
+    /// Malformed requests get a `400 Bad Request`, as they would from the server. Stream handlers need an actual connection, so they get a `501 Not Implemented`. Connection management, like keep-alive, is not applied.
+    pub async fn test_request<A: AsRef<[u8]>>(&self, raw_request: A) -> Result<Response, Error> {
+        let raw_request = raw_request.as_ref();
+        let header_end = raw_request.windows(4).position(|w| w == b"\r\n\r\n")
+            .ok_or_else(|| Error::Parse("the request header is incomplete".into()))?;
+        if self.header_too_large(&raw_request[..header_end]) {
+            return Ok(Response::request_header_fields_too_large())
+        }
+        let mut request = match Request::parse_header(&raw_request[..header_end], PeerAddr::Tcp(std::net::SocketAddr::from(([127, 0, 0, 1], 0)))) {
+            Ok(request) => request,
+            Err(_) => return Ok(Response::bad_request())
+        };
+        self.rewrite_request(&mut request);
+
+        let body = &raw_request[header_end + 4..];
+        let content_length = request.header("Content-Length").and_then(|cl| cl.parse::<usize>().ok()).unwrap_or(body.len());
+        request.content = body[..content_length.min(body.len())].to_vec();
+        let max_body_size = self.max_body_size_for(&request);
+        if max_body_size.map(|max_body_size| content_length > max_body_size).unwrap_or(false) {
+            return Ok(Response::payload_too_large())
+        }
+
+        #[cfg(feature = "compression")]
+        if let Err(e) = crate::compression::decompress_body(&mut request, max_body_size) {
+            return Ok(match e {
+                crate::compression::DecompressionError::TooLarge => Response::payload_too_large(),
+                crate::compression::DecompressionError::Invalid => Response::bad_request()
+            });
+        }
+
+        let origin = request.header("Origin").map(|o| o.to_string());
+        let mut response = match self.process(request).await?.outcome {
+            Outcome::Response(response) => response,
+            #[cfg(feature = "stream")]
+            Outcome::Stream(..) => Response::not_implemented(),
+            #[cfg(not(feature = "stream"))]
+            Outcome::Phantom(never, _) => match never {}
+        };
+        if let Some(cors) = &*self.cors {
+            cors.apply(origin.as_deref(), &mut response);
+        }
+        self.add_server_header(&mut response);
+        Ok(response)
+    }
+
+    /// Sets the configured socket options on an accepted tcp connection
+    fn apply_tcp_options(&self, connection: &Connection) {
+        let socket = match connection {
+            Connection::Tcp(socket) => socket,
+            #[cfg(unix)]
+            Connection::Unix(_) => return
+        };
+        if self.tcp_nodelay {
+            if let Err(e) = socket.set_nodelay(true) {
+                log::debug!("[server] could not set TCP_NODELAY, {}", e);
+            }
+        }
+        if let Some(idle_time) = self.tcp_keepalive {
+            let keepalive = socket2::TcpKeepalive::new().with_time(idle_time);
+            if let Err(e) = socket2::SockRef::from(socket).set_tcp_keepalive(&keepalive) {
+                log::debug!("[server] could not set the tcp keepalive, {}", e);
+            }
+        }
+    }
+
+    /// Adds the configured `Server` header, unless the response already has one
+    fn add_server_header(&self, response: &mut Response) {
+        if let Some(server_header) = &self.server_header {
+            if !response.headers().keys().any(|k| k.eq_ignore_ascii_case("server")) {
+                response.insert_header("Server", server_header.clone());
+            }
+        }
+    }
+
+    /// Routes the request, and executes the matching pipeline
+    ///
+    /// Stream handlers need the connection, so they are given back along with the request instead of being executed.
+    async fn process(&self, mut request: Request) -> Result<Processed<T>, Error> {
+        if self.method_override {
+            Server::<T>::override_method(&mut request);
+        }
+        let method = request.method.clone();
+        let path = request.url().path().to_string();
+
+        // Refused trace requests, and the ones outside of the base path, skip the routing altogether
+        let trace_refused = method == Method::Trace && !self.allow_trace;
+        let outside_base_path = self.base_path.is_some() && request.base_path.is_none();
+        let asterisk_form = request.asterisk_form;
+        let pipeline_info = if trace_refused || outside_base_path || asterisk_form {
+            None
+        } else {
+            // The method will take the request, and modify particularly the "variable count" variable
+            self.pure_branch.pipeline(&mut request)
+                .or_else(|| self.not_found.as_ref().map(|callback| self.pure_branch.fallback_pipeline(callback)))
+        };
+        // Set by the routing, if any route matched
+        let route = request.route.clone();
+
+        #[cfg(feature = "full_log")]
+        let mut tracker = None;
+
+        let outcome = match pipeline_info {
+            Some(pipeline_info) => {
+                #[cfg(feature = "full_log")]
+                {
+                    tracker = Some(pipeline_info.pipeline_track);
+                }
+
+                match pipeline_info.pipeline_kind {
+                    PipelineKind::NormalPipeline{pipeline} => {
+                        #[cfg(feature = "full_log")]
+                        log::trace!("[server] found normal pipeline for path {} with method {}", request.url, request.method);
+                        // Extractors run as soon as the pipeline is executed, so everything happens inside the future
+                        let additional = self.additional.clone();
+                        let handler = AssertUnwindSafe(async move { pipeline.execute(request, additional).await }).catch_unwind();
+                        let handled = match self.handler_timeout {
+                            Some(handler_timeout) => tokio::time::timeout(handler_timeout, handler).await,
+                            None => Ok(handler.await)
+                        };
+                        Outcome::Response(match handled {
+                            Ok(Ok(response)) => response,
+                            Ok(Err(panic)) => {
+                                let message = panic.downcast_ref::<&str>().map(|m| m.to_string())
+                                    .or_else(|| panic.downcast_ref::<String>().cloned())
+                                    .unwrap_or_else(|| "unknown panic".to_string());
+                                log::error!("[server] handler panicked for path {}, {}", path, message);
+                                match &self.panic_handler {
+                                    Some(panic_handler) => panic_handler(message),
+                                    None => Response::internal_server_error()
+                                }
+                            },
+                            Err(_) => {
+                                log::warn!("[server] handler timeout for path {}", path);
+                                Response::service_unavailable().header("Connection", "close")
+                            }
+                        })
+                    },
+                    #[cfg(feature = "stream")]
+                    PipelineKind::StreamPipeline{pipeline} => {
+                        #[cfg(feature = "full_log")]
+                        log::trace!("[server] found stream pipeline for path {}", request.url);
+                        Outcome::Stream(pipeline, Box::new(request))
+                    }
+                }
+            },
+            // Only OPTIONS can target the whole server, which just acknowledges it
+            None if asterisk_form => Outcome::Response(Response::no_content()),
+            None if outside_base_path => Outcome::Response(Response::not_found()),
+            None if trace_refused => {
+                #[cfg(feature = "full_log")]
+                log::trace!("[server] trace request refused for path {}", path);
+                Outcome::Response(match self.pure_branch.supported_methods(&path).filter(|methods| !methods.is_empty()) {
+                    Some(supported_methods) => {
+                        let mut allowed = supported_methods.into_iter()
+                            .filter(|m| m != &Method::Trace)
+                            .map(|m| m.to_str().to_string())
+                            .collect::<Vec<_>>();
+                        allowed.sort();
+                        Response::method_not_allowed().header("Allow", allowed.join(", "))
+                    },
+                    None => Response::not_found()
+                })
+            },
+            None => {
+                #[cfg(feature = "full_log")]
+                log::trace!("[server] pipeline for path {} with method {} not found", request.url, request.method);
+                Outcome::Response(Response::not_found())
+            }
+        };
+
+        Ok(Processed {
+            outcome,
+            route,
+            #[cfg(feature = "full_log")]
+            tracker
+        })
+    }
+
+    async fn dispatch(self: &Arc<Self>, #[allow(unused_mut)] mut stream: Stream, addr: PeerAddr, mut read_timeout: std::time::Duration) -> Result<(), Error> {
         let mut remaining_per_connection = None;
         let default_max_times = 100;
+        // Shared among all the requests of this connection
+        let mut read_buffer = BytesMut::with_capacity(READ_CHUNK_SIZE);
         #[cfg(feature = "full_log")]
         let mut attended_paths = Vec::new();
         loop {
@@ -414,30 +1289,22 @@ impl<T: 'static + Sync + Send> Server<T> {
                 break;
             }
 
-            let request_bytes = tokio::select!{
-                res = Server::<T>::dispatch_read(&stream, addr) => match res {
-                    Ok(request_bytes) => match request_bytes {
-                        Some(b) => b,
+            let mut request = tokio::select!{
+                res = self.dispatch_read(&stream, &addr, &mut read_buffer) => match res {
+                    Ok(request) => match request {
+                        Some(r) => r,
                         None => return Ok(())
                     },
                     Err(e) => return Err(e)
                 },
-                _ = tokio::time::sleep(timeout) => {
+                _ = tokio::time::sleep(read_timeout) => {
                     #[cfg(feature = "full_log")]
-                    log::trace!("[server] timeout for http response, after attending {:?}", attended_paths);
+                    log::trace!("[server] read timeout for http request, after attending {:?}", attended_paths);
                     return Err(Error::Timeout)
                 }
             };
-    
-            let mut request = match Request::parse(request_bytes.clone(), addr) {
-                Ok(r) => r,
-                Err(_e) => {
-                    #[cfg(feature = "full_log")]
-                    log::trace!("[server] error when parsing request, {}", _e);
-                    stream.response(Response::bad_request()).await?;
-                    return Ok(())
-                }
-            };
+
+            self.additional.stats.request();
 
             #[cfg(feature = "full_log")]
             {
@@ -446,80 +1313,133 @@ impl<T: 'static + Sync + Send> Server<T> {
             }
     
             if let Some(cors) = &*self.cors {
-                if request.method == Method::Options {
+                if request.method == Method::Options && !request.asterisk_form {
                     if let Some(supported_methods) = self.pure_branch.supported_methods(request.url().path()) {
                         #[cfg(feature = "full_log")]
                         log::trace!("[server] replying to preflight cors call");
-                        stream.response(cors.preflight(&request, &supported_methods)).await?;
+                        self.dispatch_write(&stream, cors.preflight(&request, &supported_methods)).await?;
                     } // If the method is not options, it will anyways return a not-found
                 }
             }
     
-            request.addr = addr;
-    
-            #[cfg(feature = "full_log")]
-            let mut tracker = None;
+            request.addr = addr.clone();
+
+            #[cfg(feature = "compression")]
+            let max_body_size = self.max_body_size_for(&request);
+            #[cfg(feature = "compression")]
+            if let Err(e) = crate::compression::decompress_body(&mut request, max_body_size) {
+                let response = match e {
+                    crate::compression::DecompressionError::TooLarge => Response::payload_too_large(),
+                    crate::compression::DecompressionError::Invalid => Response::bad_request()
+                };
+                self.dispatch_write(&stream, response.header("Connection", "close")).await?;
+                return Ok(())
+            }
     
-            // The method will take the request, and modify particularly the "variable count" variable
-            let mut response = match self.pure_branch.pipeline(&mut request) {
-                Some(pipeline_info) => {
-                    #[cfg(feature = "full_log")]
-                    {
-                        tracker = Some(pipeline_info.pipeline_track);
-                    }
+            // Only the information needed after the pipeline is kept, so the request can be moved into it
+            let method = request.method.clone();
+            let path = request.url().path().to_string();
+            let keep_alive_requested = request.requests_keep_alive();
+            let close_requested = request.requests_close();
+            let http10 = request.version() == Version::Http10;
+            let origin = request.header("Origin").map(|o| o.to_string());
+            let context = request.context().clone();
+
+            // Used for the response time in the logs
+            let start = std::time::Instant::now();
     
-                    match pipeline_info.pipeline_kind {
-                        PipelineKind::NormalPipeline{pipeline} => {
-                            #[cfg(feature = "full_log")]
-                            log::trace!("[server] found normal pipeline for path {} with method {}", request.url, request.method);
-                            match pipeline {
-                                Pipeline::Layer(func, pipeline_layer) => func(request.clone(), pipeline_layer, self.additional.clone()),
-                                Pipeline::Core(core_fn) => core_fn(request.clone(), self.additional.clone())
-                            }.await
-                        },
-                        #[cfg(feature = "stream")]
-                        PipelineKind::StreamPipeline{pipeline} => {
-                            #[cfg(feature = "full_log")]
-                            log::trace!("[server] found stream pipeline for path {}", request.url);
-                            pipeline(request.clone(), self.additional.clone(), stream).await;
-                            return Ok(())
+            let processed = self.process(request).await?;
+            #[cfg(feature = "full_log")]
+            let tracker = processed.tracker;
+            let route = processed.route;
+
+            let mut response = match processed.outcome {
+                Outcome::Response(response) => response,
+                #[cfg(feature = "stream")]
+                Outcome::Stream(pipeline, request) => {
+                    #[cfg(feature = "ws")]
+                    if request.headers.iter().any(|(k, v)| k.eq_ignore_ascii_case("upgrade") && v.iter().any(|v| v.eq_ignore_ascii_case("websocket"))) {
+                        // Websockets draw from their own pool, and the http permit is given back
+                        match self.max_websocket_connections.clone().try_acquire_owned() {
+                            Ok(permit) => {
+                                stream.replace_permit(permit);
+                            },
+                            Err(_) => {
+                                #[cfg(feature = "full_log")]
+                                log::trace!("[server] no websocket permits available");
+                                self.dispatch_write(&stream, Response::service_unavailable().header("Connection", "close")).await?;
+                                return Ok(())
+                            }
                         }
                     }
+                    // Anything already read from the connection belongs to the handler now
+                    stream.set_pending(read_buffer.split().to_vec());
+                    pipeline(*request, self.additional.clone(), stream).await;
+                    return Ok(())
                 },
-                None => {
-                    #[cfg(feature = "full_log")]
-                    log::trace!("[server] pipeline for path {} with method {} not found", request.url, request.method);
-                    Response::not_found()
-                }
+                #[cfg(not(feature = "stream"))]
+                Outcome::Phantom(never, _) => match never {}
             };
     
-            let should_keep_alive = request.requests_keep_alive(); 
-    
             if let Some(remaining_per_connection) = &mut remaining_per_connection {
                 *remaining_per_connection -= 1;
             } else {
-                if should_keep_alive {
+                if keep_alive_requested {
                     #[cfg(feature = "full_log")]
                     log::trace!("[server] keep alive request received, setting new timeout to 5 seconds, and maximum 100 calls");
                     response = response.header("Keep-Alive", "timeout=5, max=200");
-                    timeout = std::time::Duration::from_secs(5);
+                    read_timeout = std::time::Duration::from_secs(5);
                     remaining_per_connection = Some(default_max_times);
                 } else {
                     remaining_per_connection = Some(0);
                 }
             }
+
+            // A client (or the callback itself) asking to close the connection is always honored
+            if close_requested || response.closes_connection() {
+                remaining_per_connection = Some(0);
+            }
+            response.headers.retain(|k, _| !k.eq_ignore_ascii_case("connection"));
+            response = if remaining_per_connection == Some(0) {
+                response.headers.retain(|k, _| !k.eq_ignore_ascii_case("keep-alive"));
+                response.header("Connection", "close")
+            } else {
+                response.header("Connection", "keep-alive")
+            };
     
+            // Chunked encoding does not exist in HTTP/1.0, so trailers can't be sent. A response to a HEAD request has no body to follow either
+            if http10 || method == Method::Head {
+                response.trailers.clear();
+            }
+
             // Cors validation, not as an actual pipeline layer
             if let Some(cors) = &*self.cors {
-                cors.apply(&request, &mut response);
+                cors.apply(origin.as_deref(), &mut response);
             }
     
-            if let Some(log_string) = &*self.log_string {
+            // Layers might have reported a more accurate handler time
+            let elapsed = context.handler_time().unwrap_or_else(|| start.elapsed());
+
+            if self.log_json {
+                let log_line = serde_json::json!({
+                    "method": method.to_str(),
+                    "path": path,
+                    "route": route,
+                    "status": response.status.0,
+                    "addr": addr.to_string(),
+                    "time_ms": elapsed.as_secs_f64() * 1_000.0,
+                    "bytes": response.content.len()
+                });
+                log::info!("{}", log_line);
+            } else if let Some(log_string) = &*self.log_string {
                 #[allow(unused_mut)]
-                let mut final_log_string = log_string.replace("%M", request.method.to_str())
-                    .replace("%P", &request.url().path())
+                let mut final_log_string = log_string.replace("%M", method.to_str())
+                    .replace("%P", &path)
+                    .replace("%R", route.as_deref().unwrap_or("-"))
                     .replace("%A", &format!("{}", addr))
-                    .replace("%S", &format!("{}", response.status.0));
+                    .replace("%S", &format!("{}", response.status.0))
+                    .replace("%T", &format!("{:.3}", elapsed.as_secs_f64() * 1_000.0))
+                    .replace("%b", &format!("{}", response.content.len()));
                 #[cfg(feature = "full_log")]
                 {
                     if log_string.contains("%f") {
@@ -539,10 +1459,50 @@ impl<T: 'static + Sync + Send> Server<T> {
                 }
             }
     
-            stream.response(response).await?;
+            self.add_server_header(&mut response);
+            self.dispatch_write(&stream, response).await?;
         }
         #[cfg(feature = "full_log")]
         log::trace!("[server] leaving dispatch method");
         Ok(())
     }
+}
+
+/// Result of processing a request
+enum Outcome<T> {
+    /// The pipeline produced a response
+    Response(Response),
+    /// A stream handler has to take over the connection
+    #[cfg(feature = "stream")]
+    Stream(Arc<crate::HandlerFn<T>>, Box<Request>),
+    /// Keeps the type parameter in use when the `stream` feature is disabled
+    #[cfg(not(feature = "stream"))]
+    #[allow(dead_code)]
+    Phantom(std::convert::Infallible, std::marker::PhantomData<T>)
+}
+
+/// Outcome of a request, with the routing information needed for the logs
+struct Processed<T> {
+    outcome: Outcome<T>,
+    route: Option<String>,
+    #[cfg(feature = "full_log")]
+    tracker: Option<crate::metafunctions::callback::PipelineTrack>
+}
+
+/// Listener kinds supported by the server
+enum Listener {
+    Tcp(TcpListener),
+    #[cfg(unix)]
+    Unix(UnixListener)
+}
+
+impl Listener {
+    /// Accepts a new connection, with the address of the peer
+    async fn accept(&self) -> std::io::Result<(Connection, PeerAddr)> {
+        match self {
+            Listener::Tcp(listener) => listener.accept().await.map(|(socket, addr)| (Connection::Tcp(socket), PeerAddr::Tcp(addr))),
+            #[cfg(unix)]
+            Listener::Unix(listener) => listener.accept().await.map(|(socket, addr)| (Connection::Unix(socket), PeerAddr::Unix(addr)))
+        }
+    }
 }
\ No newline at end of file
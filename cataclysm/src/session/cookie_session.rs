@@ -85,7 +85,7 @@ impl CookieSession {
     ///     .secret("really secret!");
     /// ```
     ///
-    /// If no secret is provided, a random key will be used (generated by ring).
+    /// If no secret is provided, a random key will be used (generated by ring). As such a key changes on every start, a fixed secret is needed for sessions to outlive restarts or be shared among several instances.
     pub fn secret<A: AsRef<[u8]>>(mut self, secret: A) -> Self {
         self.key = hmac::Key::new(hmac::HMAC_SHA256, secret.as_ref());
         self
@@ -186,11 +186,7 @@ impl CookieSession {
     /// Helper function to extract a session from a cookie
     fn build_from_req(&self, req: &Request) -> Result<Option<Session>, Error> {
         // we can have multiple cookies, so we try for each one
-        let empty = Vec::new();
-        let cookie_headers = vec![
-            req.headers.get("Cookie").unwrap_or_else(|| &empty),
-            req.headers.get("cookie").unwrap_or_else(|| &empty)
-        ].into_iter().flatten();
+        let cookie_headers = req.header_values("Cookie");
         for cookie_header in cookie_headers {
             for single_cookie in cookie_header.split("; ") {
                 let cookie = Cookie::parse_encoded(single_cookie).map_err(|e| Error::custom(format!("{}", e)))?;
@@ -241,7 +237,7 @@ impl SessionCreator for CookieSession {
         }
     }
 
-    fn apply(&self, values: &HashMap<String, String>, mut res: Response) -> Response {
+    fn apply(&self, values: &HashMap<String, String>, res: Response) -> Response {
         let content = serde_json::to_string(values).unwrap();
         let signature = general_purpose::STANDARD.encode(hmac::sign(&self.key, content.as_bytes()).as_ref());
 
@@ -302,9 +298,6 @@ impl SessionCreator for CookieSession {
             cookie_builder
         };
 
-        let cookie = cookie_builder.build();
-
-        res = res.header("Set-Cookie", format!("{}", cookie.encoded()));
-        res
+        res.cookie(cookie_builder)
     }
 }
\ No newline at end of file
@@ -1,6 +1,11 @@
 use crate::{Extractor, Error, Additional, http::{Request}};
 use std::sync::Arc;
 use std::ops::{Deref};
+use std::any::{Any, TypeId};
+use std::collections::HashMap;
+
+/// Type map holding the values declared with [data](crate::ServerBuilder::data)
+pub(crate) type DataMap = HashMap<TypeId, Arc<dyn Any + Send + Sync>>;
 
 /// Wrapper around data to be shared in the server
 ///
@@ -48,4 +53,44 @@ impl<T: 'static + Sync + Send> Extractor<T> for Shared<T> {
             Err(Error::ExtractionSE(format!("No shared was set up by the server...")))
         }
     }
+}
+
+/// Wrapper around data to be shared in the server, retrieved by its type
+///
+/// Any number of values, of different types, can be declared with the ServerBuilder's [data](crate::ServerBuilder::data), which keeps them independent from the server's generic `T`.
+pub struct Data<D> {
+    inner: Arc<D>
+}
+
+// Convenience deref
+impl<D> Deref for Data<D> {
+    type Target = D;
+
+    fn deref(&self) -> &Self::Target {
+        &self.inner
+    }
+}
+
+impl<D> Clone for Data<D> {
+    fn clone(&self) -> Self {
+        Data {
+            inner: self.inner.clone()
+        }
+    }
+}
+
+impl<D> Data<D> {
+    /// Extracts the contained data from the `Data` in an `Arc`
+    pub fn into_inner(self) -> Arc<D> {
+        self.inner
+    }
+}
+
+impl<T: Sync, D: 'static + Send + Sync> Extractor<T> for Data<D> {
+    fn extract(_req: &Request, additional: Arc<Additional<T>>) -> Result<Self, Error> {
+        match additional.data.get(&TypeId::of::<D>()).map(|data| data.clone().downcast::<D>()) {
+            Some(Ok(inner)) => Ok(Data{inner}),
+            _ => Err(Error::ExtractionSE(format!("no data of type {} was set up by the server", std::any::type_name::<D>())))
+        }
+    }
 }
\ No newline at end of file
@@ -0,0 +1,96 @@
+use crate::{Additional, Extractor, Error, http::Request};
+use std::sync::{Arc, atomic::{AtomicU64, AtomicUsize, Ordering}};
+
+/// Snapshot of the traffic counters of a server, see [Server::stats](crate::Server::stats)
+///
+/// It is also an extractor, which makes it simple to expose the counters from a handler.
+///
+/// ```rust,no_run
+/// use cataclysm::{Branch, ServerStats, http::{Response, Method}};
+///
+/// let branch: Branch<()> = Branch::new("/metrics").with(Method::Get.to(|stats: ServerStats| async move {
+///     Response::ok().body(format!(
+///         "active_connections {}\nrequests_total {}\nresponses_5xx_total {}\n",
+///         stats.active_connections, stats.requests, stats.responses[4]
+///     ))
+/// }));
+/// ```
+///
+/// Counters start at zero when the server is built, and only cover the http traffic the server handles itself. Requests given to [test_request](crate::Server::test_request) are not counted, and neither are the bytes exchanged by stream handlers.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct ServerStats {
+    /// Connections being attended at the moment
+    pub active_connections: usize,
+    /// Connections accepted since the server started
+    pub connections: u64,
+    /// Requests read since the server started
+    pub requests: u64,
+    /// Responses sent by status class, from `1xx` (at index 0) to `5xx` (at index 4)
+    pub responses: [u64; 5],
+    /// Bytes read from the connections, while reading requests
+    pub bytes_in: u64,
+    /// Bytes written to the connections, while sending responses
+    pub bytes_out: u64
+}
+
+impl<T: Sync> Extractor<T> for ServerStats {
+    fn extract(_req: &Request, additional: Arc<Additional<T>>) -> Result<Self, Error> {
+        Ok(additional.stats.snapshot())
+    }
+}
+
+/// Atomic counters behind the [ServerStats](ServerStats) snapshots
+#[derive(Default)]
+pub(crate) struct Counters {
+    active_connections: AtomicUsize,
+    connections: AtomicU64,
+    requests: AtomicU64,
+    responses: [AtomicU64; 5],
+    bytes_in: AtomicU64,
+    bytes_out: AtomicU64
+}
+
+impl Counters {
+    /// Counts a new connection, which stays active until the returned guard is dropped
+    pub(crate) fn connection(self: &Arc<Self>) -> ConnectionGuard {
+        self.connections.fetch_add(1, Ordering::Relaxed);
+        self.active_connections.fetch_add(1, Ordering::Relaxed);
+        ConnectionGuard(Arc::clone(self))
+    }
+
+    pub(crate) fn request(&self) {
+        self.requests.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn bytes_in(&self, n: usize) {
+        self.bytes_in.fetch_add(n as u64, Ordering::Relaxed);
+    }
+
+    /// Counts a response with the given status code, and its size once serialized
+    pub(crate) fn response(&self, status_code: u32, n: usize) {
+        if let Some(counter) = (status_code / 100).checked_sub(1).and_then(|class| self.responses.get(class as usize)) {
+            counter.fetch_add(1, Ordering::Relaxed);
+        }
+        self.bytes_out.fetch_add(n as u64, Ordering::Relaxed);
+    }
+
+    pub(crate) fn snapshot(&self) -> ServerStats {
+        ServerStats {
+            active_connections: self.active_connections.load(Ordering::Relaxed),
+            connections: self.connections.load(Ordering::Relaxed),
+            requests: self.requests.load(Ordering::Relaxed),
+            responses: std::array::from_fn(|class| self.responses[class].load(Ordering::Relaxed)),
+            bytes_in: self.bytes_in.load(Ordering::Relaxed),
+            bytes_out: self.bytes_out.load(Ordering::Relaxed)
+        }
+    }
+}
+
+/// Keeps a connection counted as active while alive, even if its task panics
+pub(crate) struct ConnectionGuard(Arc<Counters>);
+
+impl Drop for ConnectionGuard {
+    fn drop(&mut self) {
+        self.0.active_connections.fetch_sub(1, Ordering::Relaxed);
+    }
+}
@@ -2,35 +2,156 @@ use tokio::{
     sync::OwnedSemaphorePermit,
     net::TcpStream
 };
+#[cfg(unix)]
+use tokio::net::UnixStream;
 use bytes::Buf;
 use crate::{Error, http::{Response, BasicRequest}};
 
 const CHUNK_SIZE: usize = 4_096;
 
-/// Wrapper around a TCP Stream
+/// Underlying connection of a stream
+pub(crate) enum Connection {
+    Tcp(TcpStream),
+    #[cfg(unix)]
+    Unix(UnixStream)
+}
+
+/// Wrapper around a TCP (or Unix domain socket) Stream
 pub struct Stream {
-    inner: TcpStream,
-    permit: Option<OwnedSemaphorePermit>
+    inner: Connection,
+    permit: Option<OwnedSemaphorePermit>,
+    /// Bytes already read by the server from the connection, served before any new read
+    pending: std::sync::Mutex<Vec<u8>>
 }
 
 impl Stream {
     /// Generates a new stream
     pub fn new(stream: TcpStream, permit: Option<OwnedSemaphorePermit>) -> Stream {
-        Stream{inner: stream, permit}
+        Stream{inner: Connection::Tcp(stream), permit, pending: Default::default()}
+    }
+
+    /// Generates a new stream from any supported connection
+    pub(crate) fn from_connection(connection: Connection, permit: Option<OwnedSemaphorePermit>) -> Stream {
+        Stream{inner: connection, permit, pending: Default::default()}
+    }
+
+    /// Hands back bytes that the server read from the connection, but belong to the stream handler
+    #[cfg(feature = "stream")]
+    pub(crate) fn set_pending(&mut self, bytes: Vec<u8>) {
+        *self.pending.get_mut().unwrap_or_else(|e| e.into_inner()) = bytes;
+    }
+
+    /// Moves as many pending bytes as possible into the buffer
+    fn read_pending<F: FnOnce(&[u8]) -> usize>(&self, write: F) -> Option<usize> {
+        let mut pending = self.pending.lock().unwrap_or_else(|e| e.into_inner());
+        if pending.is_empty() {
+            None
+        } else {
+            let n = write(&pending);
+            pending.drain(..n);
+            Some(n)
+        }
+    }
+
+    /// Swaps the semaphore permit attached to the stream
+    #[cfg(feature = "ws")]
+    pub(crate) fn replace_permit(&mut self, permit: OwnedSemaphorePermit) -> Option<OwnedSemaphorePermit> {
+        self.permit.replace(permit)
+    }
+
+    /// Waits for the stream to become readable
+    pub async fn readable(&self) -> std::io::Result<()> {
+        if !self.pending.lock().unwrap_or_else(|e| e.into_inner()).is_empty() {
+            return Ok(());
+        }
+        match &self.inner {
+            Connection::Tcp(s) => s.readable().await,
+            #[cfg(unix)]
+            Connection::Unix(s) => s.readable().await
+        }
     }
 
+    /// Waits for the stream to become writable
+    pub async fn writable(&self) -> std::io::Result<()> {
+        match &self.inner {
+            Connection::Tcp(s) => s.writable().await,
+            #[cfg(unix)]
+            Connection::Unix(s) => s.writable().await
+        }
+    }
+
+    /// Tries to read data from the stream into the provided buffer, without waiting
+    pub fn try_read(&self, buf: &mut [u8]) -> std::io::Result<usize> {
+        if let Some(n) = self.read_pending(|pending| {
+            let n = pending.len().min(buf.len());
+            buf[..n].copy_from_slice(&pending[..n]);
+            n
+        }) {
+            return Ok(n);
+        }
+        match &self.inner {
+            Connection::Tcp(s) => s.try_read(buf),
+            #[cfg(unix)]
+            Connection::Unix(s) => s.try_read(buf)
+        }
+    }
+
+    /// Tries to read data from the stream into a growable buffer, without waiting
+    pub fn try_read_buf<B: bytes::BufMut>(&self, buf: &mut B) -> std::io::Result<usize> {
+        if let Some(n) = self.read_pending(|pending| {
+            let n = pending.len().min(buf.remaining_mut());
+            buf.put_slice(&pending[..n]);
+            n
+        }) {
+            return Ok(n);
+        }
+        match &self.inner {
+            Connection::Tcp(s) => s.try_read_buf(buf),
+            #[cfg(unix)]
+            Connection::Unix(s) => s.try_read_buf(buf)
+        }
+    }
+
+    /// Tries to write a buffer to the stream, without waiting
+    pub fn try_write(&self, buf: &[u8]) -> std::io::Result<usize> {
+        match &self.inner {
+            Connection::Tcp(s) => s.try_write(buf),
+            #[cfg(unix)]
+            Connection::Unix(s) => s.try_write(buf)
+        }
+    }
+
+    /// Waits for data and reads it into the provided buffer, failing with [Error::Timeout] if nothing arrives within the given time
+    ///
+    /// Returns the amount of bytes read, where `0` means that the peer closed the connection.
+    pub async fn read_with_timeout(&self, buf: &mut [u8], duration: std::time::Duration) -> Result<usize, Error> {
+        tokio::time::timeout(duration, async {
+            loop {
+                self.readable().await.map_err(Error::Io)?;
+                match self.try_read(buf) {
+                    Ok(n) => break Ok(n),
+                    Err(ref e) if e.kind() == std::io::ErrorKind::WouldBlock => continue,
+                    Err(e) => break Err(Error::Io(e))
+                }
+            }
+        }).await.map_err(|_| Error::Timeout)?
+    }
+
+    /// Reads a response from the stream, waiting until the peer sends something
+    ///
+    /// There is no limit on the waiting time, see [try_read_response_timeout](Stream::try_read_response_timeout) to avoid hanging on a stalled peer.
     pub async fn try_read_response(&self) -> Result<Response, Error> {
         let mut response_bytes = Vec::with_capacity(CHUNK_SIZE);
         // First we read
         loop {
-            self.inner.readable().await.map_err(|e| Error::Io(e))?;
+            self.readable().await.map_err(|e| Error::Io(e))?;
             
             // being stored in the async task.
             let mut buf = [0; CHUNK_SIZE];
 
             // Try to read data, this may still fail with `WouldBlock`
             // if the readiness event is a false positive.
-            match self.inner.try_read(&mut buf) {
+            match self.try_read(&mut buf) {
                 Ok(0) => {
                     break
                 },
@@ -51,6 +172,23 @@ impl Stream {
         Response::parse(response_bytes)
     }
 
+    /// Same as [try_read_response](Stream::try_read_response), but fails with [Error::Timeout] if the response is not read within the given time
+    ///
+    /// ```rust,no_run
+    /// use cataclysm::{Stream, http::{BasicRequest, Method}};
+    /// use std::time::Duration;
+    ///
+    /// async fn check_status(upstream: Stream) -> Result<(), cataclysm::Error> {
+    ///     upstream.request(BasicRequest::new(Method::Get, "http://127.0.0.1:9000/status")?).await?;
+    ///     let response = upstream.try_read_response_timeout(Duration::from_secs(5)).await?;
+    ///     println!("upstream answered with {}", response.status_code());
+    ///     Ok(())
+    /// }
+    /// ```
+    pub async fn try_read_response_timeout(&self, duration: std::time::Duration) -> Result<Response, Error> {
+        tokio::time::timeout(duration, self.try_read_response()).await.map_err(|_| Error::Timeout)?
+    }
+
     /// Writes bytes through the tcp connection
     pub async fn write_bytes<A: AsRef<[u8]>>(&self, bytes: A) -> Result<(), Error> {
         let bytes_ref: &[u8] = bytes.as_ref();
@@ -64,11 +202,11 @@ impl Stream {
         };
         loop {
             // Wait for the socket to be writable
-            self.inner.writable().await.map_err(|e| Error::Io(e))?;
+            self.writable().await.map_err(|e| Error::Io(e))?;
     
             // Try to write data, this may still fail with `WouldBlock`
             // if the readiness event is a false positive.        
-            match self.inner.try_write(&current_chunk) {
+            match self.try_write(&current_chunk) {
                 Ok(n) => {
                     if n != current_chunk.remaining() {
                         // There are some bytes still to be written in this chunk
@@ -104,30 +242,91 @@ impl Stream {
     /// Used to retrieve the internal tcp_stream.
     ///
     /// The semaphore permit that might come with it is the helper structure from cataclysm to keep track of the amount of connections that the server has. Use with care.
+    ///
+    /// Bytes that the server already read from the connection, and were not yet read from this stream, are lost.
+    ///
+    /// # Panics
+    ///
+    /// If the connection is not a tcp one, which only happens with servers started through [run_unix](crate::Server::run_unix). See [try_into_tcp_stream](Stream::try_into_tcp_stream) for a fallible version.
     pub fn into_tcp_stream(self) -> (TcpStream, Option<OwnedSemaphorePermit>) {
-        (self.inner, self.permit)
+        match self.try_into_tcp_stream() {
+            Ok(parts) => parts,
+            Err(_) => panic!("the stream is not a tcp stream")
+        }
+    }
+
+    /// Same as [into_tcp_stream](Stream::into_tcp_stream), but fails if the connection is not a tcp one
+    pub fn try_into_tcp_stream(self) -> Result<(TcpStream, Option<OwnedSemaphorePermit>), Error> {
+        match self.inner {
+            Connection::Tcp(s) => Ok((s, self.permit)),
+            #[cfg(unix)]
+            Connection::Unix(_) => Err(Error::custom("the stream is not a tcp stream"))
+        }
+    }
+
+    /// Used to retrieve the internal Unix domain socket stream, failing if the connection is not one
+    ///
+    /// The same remarks of [into_tcp_stream](Stream::into_tcp_stream) apply.
+    #[cfg(unix)]
+    pub fn try_into_unix_stream(self) -> Result<(UnixStream, Option<OwnedSemaphorePermit>), Error> {
+        match self.inner {
+            Connection::Unix(s) => Ok((s, self.permit)),
+            Connection::Tcp(_) => Err(Error::custom("the stream is not a unix stream"))
+        }
+    }
+
+    /// Reference access to the internal tcp stream, if the connection is a tcp one
+    pub fn as_tcp_stream(&self) -> Option<&TcpStream> {
+        match &self.inner {
+            Connection::Tcp(s) => Some(s),
+            #[cfg(unix)]
+            Connection::Unix(_) => None
+        }
+    }
+
+    /// Mutable access to the internal tcp stream, if the connection is a tcp one
+    pub fn as_tcp_stream_mut(&mut self) -> Option<&mut TcpStream> {
+        match &mut self.inner {
+            Connection::Tcp(s) => Some(s),
+            #[cfg(unix)]
+            Connection::Unix(_) => None
+        }
+    }
+
+    /// Reference access to the internal Unix domain socket stream, if the connection is one
+    #[cfg(unix)]
+    pub fn as_unix_stream(&self) -> Option<&UnixStream> {
+        match &self.inner {
+            Connection::Unix(s) => Some(s),
+            Connection::Tcp(_) => None
+        }
     }
 }
 
+/// Gives access to the tcp stream
+///
+/// # Panics
+///
+/// If the connection is not a tcp one, see [as_tcp_stream](Stream::as_tcp_stream) for a fallible version.
 impl std::ops::Deref for Stream {
     type Target = TcpStream;
 
     // Required method
     fn deref(&self) -> &Self::Target {
-        &self.inner
+        self.as_ref()
     }
 }
 
-// Reference access to the inner structure
+// Reference access to the inner structure, panics for non tcp connections
 impl AsRef<TcpStream> for Stream {
     fn as_ref(&self) -> &TcpStream {
-        &self.inner
+        self.as_tcp_stream().expect("the stream is not a tcp stream")
     }
 }
 
-// Mutable reference access to the inner structure
+// Mutable reference access to the inner structure, panics for non tcp connections
 impl AsMut<TcpStream> for Stream {
     fn as_mut(&mut self) -> &mut TcpStream {
-        &mut self.inner
+        self.as_tcp_stream_mut().expect("the stream is not a tcp stream")
     }
 }
\ No newline at end of file
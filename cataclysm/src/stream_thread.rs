@@ -0,0 +1,85 @@
+use std::future::Future;
+use bytes::BytesMut;
+use crate::Stream;
+
+const CHUNK_SIZE: usize = 4_096;
+
+/// Trait to deal with a raw connection through callbacks, keeping state per connection
+///
+/// It is the stream counterpart of the websockets `WebSocketThread`, see [stream_thread](crate::Branch::stream_thread).
+///
+/// ```rust,no_run
+/// use cataclysm::{Branch, Stream, StreamThread};
+/// use bytes::BytesMut;
+///
+/// // Line based echo protocol, that counts the lines it answers
+/// struct Echo {
+///     lines: usize
+/// }
+///
+/// impl StreamThread for Echo {
+///     async fn on_data(&mut self, stream: &Stream, buffer: &mut BytesMut) -> bool {
+///         while let Some(end) = buffer.iter().position(|b| *b == b'\n') {
+///             let line = buffer.split_to(end + 1);
+///             self.lines += 1;
+///             if stream.write_bytes(&line).await.is_err() {
+///                 return false
+///             }
+///         }
+///         true
+///     }
+/// }
+///
+/// let branch: Branch<()> = Branch::new("/echo").stream_thread(|_: ()| Echo {lines: 0});
+/// ```
+pub trait StreamThread: Send + 'static {
+    /// On opened connection
+    ///
+    /// This function gets called before anything is read from the stream, which allows to greet the other end.
+    fn on_open(&mut self, _stream: &Stream) -> impl Future<Output = ()> + Send {
+        async {}
+    }
+
+    /// On received data
+    ///
+    /// This function gets called every time new bytes arrive. The buffer holds everything that has not been consumed yet, so incomplete messages can be left in it until the rest arrives. Returning `false` finishes the connection.
+    fn on_data(&mut self, stream: &Stream, buffer: &mut BytesMut) -> impl Future<Output = bool> + Send;
+
+    /// On closed connection
+    ///
+    /// This function gets called when the connection is finished, either by the other end or by [on_data](StreamThread::on_data) (clean), or due to an error.
+    fn on_close(&mut self, _clean: bool) -> impl Future<Output = ()> + Send {
+        async {}
+    }
+}
+
+/// Reads from the stream until the connection is finished, calling the thread's callbacks along the way
+pub(crate) async fn drive<S: StreamThread>(stream: Stream, mut thread: S) {
+    thread.on_open(&stream).await;
+    let mut buffer = BytesMut::with_capacity(CHUNK_SIZE);
+    let clean = loop {
+        if let Err(_e) = stream.readable().await {
+            #[cfg(feature = "full_log")]
+            log::debug!("stream thread error: {}", _e);
+            break false;
+        }
+
+        if buffer.capacity() - buffer.len() < CHUNK_SIZE {
+            buffer.reserve(CHUNK_SIZE);
+        }
+
+        match stream.try_read_buf(&mut buffer) {
+            Ok(0) => break true,
+            Ok(_) => if !thread.on_data(&stream, &mut buffer).await {
+                break true;
+            },
+            Err(ref e) if e.kind() == std::io::ErrorKind::WouldBlock => continue,
+            Err(_e) => {
+                #[cfg(feature = "full_log")]
+                log::debug!("stream thread error: {}", _e);
+                break false;
+            }
+        }
+    };
+    thread.on_close(clean).await;
+}
\ No newline at end of file
@@ -1,30 +1,118 @@
-pub use cataclysm_ws::{Error as WSError, WebSocketStream, WebSocketReader, WebSocketWriter, WebSocketThread, Message, Frame};
+pub use cataclysm_ws::{Error as WSError, WebSocketStream, WebSocketReader, WebSocketWriter, WebSocketThread, WebSocketHub, HubThread, Message, Frame};
 use crate::{
     Stream,
     Error,
     http::{Request, Response}
 };
 use base64::{Engine, engine::general_purpose};
+use std::borrow::Borrow;
+
+/// Verification of the upgrade request, that can reject it with a response
+type GuardFn = dyn Fn(&Request) -> Result<(), Response> + Send + Sync;
 
 pub struct WebSocketHandshake {
-    protocol: Option<String>
+    protocols: Vec<String>,
+    origins: Vec<String>,
+    guard: Option<Box<GuardFn>>
 }
 
 impl WebSocketHandshake {
     pub fn new() -> WebSocketHandshake {
         WebSocketHandshake {
-            protocol: None
+            protocols: Vec::new(),
+            origins: Vec::new(),
+            guard: None
         }
     }
 
+    /// Verifies the upgrade request before accepting the connection
+    ///
+    /// The callback receives the original http request, with its path variables, query and headers. Returning an error sends that response instead of switching protocols, and the handshake fails.
+    ///
+    /// ```rust,no_run
+    /// use cataclysm::{Stream, http::{Request, Response}, ws::WebSocketHandshake};
+    ///
+    /// async fn chat(stream: Stream, request: Request) {
+    ///     let handshake = WebSocketHandshake::new().guard(|req: &Request| {
+    ///         match req.query_param("token") {
+    ///             Some(token) if token == "secret" => Ok(()),
+    ///             _ => Err(Response::unauthorized())
+    ///         }
+    ///     });
+    ///     // The request is only borrowed, so it remains available after the upgrade
+    ///     if let Ok(web_socket) = handshake.perform(stream, &request).await {
+    ///         // Read loop...
+    ///     }
+    /// }
+    /// ```
+    pub fn guard<F: Fn(&Request) -> Result<(), Response> + Send + Sync + 'static>(mut self, guard: F) -> WebSocketHandshake {
+        self.guard = Some(Box::new(guard));
+        self
+    }
+
+    /// Adds an origin allowed to open the connection, like `https://example.com`
+    ///
+    /// ```rust,no_run
+    /// use cataclysm::{Stream, http::Request, ws::WebSocketHandshake};
+    ///
+    /// async fn chat(stream: Stream, request: Request) {
+    ///     let handshake = WebSocketHandshake::new()
+    ///         .allowed_origin("https://example.com")
+    ///         .allowed_origin("https://app.example.com");
+    ///     if let Ok(web_socket) = handshake.perform(stream, request).await {
+    ///         // Read loop...
+    ///     }
+    /// }
+    /// ```
+    ///
+    /// Can be called more than once. Once an origin is given, upgrade requests from any other origin get a `403 Forbidden`, which prevents other sites from opening connections with the credentials of the user (cross-site websocket hijacking). Requests without an `Origin` header don't come from browsers, and are not affected.
+    pub fn allowed_origin<A: Into<String>>(mut self, origin: A) -> WebSocketHandshake {
+        self.origins.push(origin.into());
+        self
+    }
+
+    /// Adds a supported subprotocol
+    ///
+    /// Can be called more than once, in order of preference. The agreed subprotocol is available through [WebSocketStream::protocol], and is handed to the [WebSocketThread::on_protocol] callback. If the client offers none of them, the request gets a `403 Forbidden`.
     pub fn protocol<A: Into<String>>(mut self, protocol: A) -> WebSocketHandshake {
-        self.protocol = Some(protocol.into());
+        self.protocols.push(protocol.into());
         self
     }
 
-    pub async fn perform(self, stream: Stream, request: Request) -> Result<WebSocketStream, Error> {
-        if request.headers.get("Upgrade").map(|u| u.get(0).map(|v| v == "websocket")).flatten().unwrap_or(false) && request.headers.get("Connection").map(|c| c.get(0).map(|v| v == "Upgrade" || v == "keep-alive, Upgrade")).flatten().unwrap_or(false) {
-            if let Some(nonce) = request.headers.get("Sec-WebSocket-Key").map(|wsk| wsk.get(0)).flatten() {
+    /// Performs the handshake over the stream, with the upgrade request
+    ///
+    /// The request can be given by value or by reference, the latter allows to keep using it once the connection is established.
+    ///
+    /// Only version 13 of the protocol is supported, requests with any other `Sec-WebSocket-Version` get a `426 Upgrade Required` response that announces it. Websockets need a tcp connection, so the ones accepted by [run_unix](crate::Server::run_unix) get a `501 Not Implemented` response.
+    pub async fn perform<R: Borrow<Request>>(self, stream: Stream, request: R) -> Result<WebSocketStream, Error> {
+        let request = request.borrow();
+        // Websockets run over tcp only, so the upgrade is refused before it gets promised to the client
+        if stream.as_tcp_stream().is_none() {
+            stream.response(Response::not_implemented()).await?;
+            return Err(Error::custom("websockets are only supported over tcp connections"));
+        }
+        if let Some(guard) = &self.guard {
+            if let Err(response) = guard(request) {
+                stream.response(response).await?;
+                return Err(Error::custom("websocket upgrade request rejected"));
+            }
+        }
+        if let Some(origin) = request.header("Origin") {
+            if !self.origins.is_empty() && !self.origins.iter().any(|allowed| allowed.eq_ignore_ascii_case(origin)) {
+                stream.response(Response::forbidden()).await?;
+                return Err(Error::custom(format!("websocket upgrade from a forbidden origin, {}", origin)));
+            }
+        }
+        // Both are case-insensitive tokens, and proxies usually send the connection options as a list
+        let upgrade = request.header_list("Upgrade").any(|token| token.eq_ignore_ascii_case("websocket"));
+        let connection = request.header_list("Connection").any(|token| token.eq_ignore_ascii_case("upgrade"));
+        if upgrade && connection {
+            // Version 13 is the only one defined by RFC6455, the client gets told about it otherwise
+            if request.header("Sec-WebSocket-Version").map(|v| v.trim() != "13").unwrap_or(true) {
+                stream.response(Response::upgrade_required().header("Sec-WebSocket-Version", "13")).await?;
+                return Err(Error::custom("unsupported websocket version"));
+            }
+            if let Some(nonce) = request.header("Sec-WebSocket-Key") {
                 // According to RFC4122
                 let nonce = format!("{}258EAFA5-E914-47DA-95CA-C5AB0DC85B11", nonce);
                 let websocket_accept = general_purpose::STANDARD.encode(ring::digest::digest(&ring::digest::SHA1_FOR_LEGACY_USE_ONLY, nonce.as_bytes()));
@@ -33,24 +121,20 @@ impl WebSocketHandshake {
                     .header("Upgrade", "websocket")
                     .header("Connection", "Upgrade");
 
-                if let Some(protocol) = self.protocol {
-                    if let Some(available_protocols) = request.headers.get("Sec-WebSocket-Protocol") {
-                        let mut found = false;
-                        for header in available_protocols {
-                            if header.split(",").map(|v| v.trim()).find(|v| *v == protocol).is_some() {
-                                found = true;
-                                break;
-                            }
-                        }
+                let mut agreed_protocol = None;
+                if !self.protocols.is_empty() {
+                    let offered: Vec<&str> = request.header_list("Sec-WebSocket-Protocol").collect();
+                    if !offered.is_empty() {
+                        agreed_protocol = self.protocols.into_iter().find(|protocol| offered.contains(&protocol.as_str()));
 
-                        if found {
+                        if let Some(protocol) = &agreed_protocol {
                             response = response.header("Sec-WebSocket-Protocol", protocol);
                         } else {
-                            stream.response(Response::bad_request()).await?;
+                            stream.response(Response::forbidden()).await?;
                             return Err(Error::custom("unsupported protocol for websockets exchange"));
                         }
                     } else {
-                        stream.response(Response::bad_request()).await?;
+                        stream.response(Response::forbidden()).await?;
                         return Err(Error::custom("missing Sec-WebSocket-Protocol header"));
                     }
                 }
@@ -58,17 +142,21 @@ impl WebSocketHandshake {
                 response = response.header("Sec-WebSocket-Accept", websocket_accept);
 
                 stream.response(response).await?;
-                let (stream, permit) = stream.into_tcp_stream();
+                let (stream, permit) = stream.try_into_tcp_stream()?;
                 let mut web_socket_stream = WebSocketStream::from_tcp_stream_unchecked(stream);
                 if let Some(permit) = permit {
                     web_socket_stream.set_permit(permit)
                 }
+                if let Some(protocol) = agreed_protocol {
+                    web_socket_stream.set_protocol(protocol);
+                }
                 Ok(web_socket_stream)
             } else {
                 stream.response(Response::bad_request()).await?;
                 Err(Error::custom("nonce does not exist in websocket handshake"))
             }
         } else {
+            stream.response(Response::bad_request()).await?;
             Err(Error::custom("missing headers or headers with incorrect values"))
         }
     }
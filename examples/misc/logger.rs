@@ -2,10 +2,15 @@
 use chrono::Local;
 use colored::*;
 use log::{Level, LevelFilter, Log, Metadata, Record, SetLoggerError};
+use std::io::IsTerminal;
 
 pub struct SimpleLogger {
     default_level: LevelFilter,
     module_levels: Vec<(String, LevelFilter)>,
+    format: String,
+    timestamp_format: String,
+    colors: bool,
+    json: bool,
 }
 
 impl SimpleLogger {
@@ -13,9 +18,42 @@ impl SimpleLogger {
         SimpleLogger {
             default_level: LevelFilter::Trace,
             module_levels: Vec::new(),
+            format: "{ts} [{level}]: {msg}".to_string(),
+            timestamp_format: "%Y-%m-%d %H:%M:%S,%3f".to_string(),
+            // Colors would only garble files and journald
+            colors: std::io::stdout().is_terminal(),
+            json: false,
         }
     }
 
+    /// Sets the layout of each line, with the `{ts}`, `{level}`, `{target}` and `{msg}` placeholders
+    #[allow(dead_code)]
+    pub fn with_format(mut self, pattern: &str) -> SimpleLogger {
+        self.format = pattern.to_string();
+        self
+    }
+
+    /// Sets the `chrono` format of the timestamp
+    #[allow(dead_code)]
+    pub fn with_timestamp_format(mut self, timestamp_format: &str) -> SimpleLogger {
+        self.timestamp_format = timestamp_format.to_string();
+        self
+    }
+
+    /// Writes each record as a single line json object, instead of the human readable format
+    #[allow(dead_code)]
+    pub fn json(mut self) -> SimpleLogger {
+        self.json = true;
+        self
+    }
+
+    /// Forces colors on or off, instead of enabling them only when stdout is a terminal
+    #[allow(dead_code)]
+    pub fn with_colors(mut self, colors: bool) -> SimpleLogger {
+        self.colors = colors;
+        self
+    }
+
     #[allow(dead_code)]
     pub fn with_level(mut self, level: LevelFilter) -> SimpleLogger {
         self.default_level = level;
@@ -71,7 +109,20 @@ impl Log for SimpleLogger {
 
     fn log(&self, record: &Record) {
         if self.enabled(record.metadata()) {
-            let level_string = {
+            if self.json {
+                println!(
+                    "{}",
+                    serde_json::json!({
+                        "ts": Local::now().to_rfc3339(),
+                        "level": record.level().to_string(),
+                        "target": record.target(),
+                        "msg": record.args().to_string()
+                    })
+                );
+                return;
+            }
+
+            let level_string = if self.colors {
                 match record.level() {
                     Level::Error => record.level().to_string().red(),
                     Level::Warn => record.level().to_string().yellow(),
@@ -79,27 +130,22 @@ impl Log for SimpleLogger {
                     Level::Debug => record.level().to_string().purple(),
                     Level::Trace => record.level().to_string().normal(),
                 }
+            } else {
+                record.level().to_string().normal()
             };
-            /*
             let target = if !record.target().is_empty() {
                 record.target()
             } else {
                 record.module_path().unwrap_or_default()
             };
-            
-            println!(
-                "{} {:<5} [{}] {}",
-                Local::now().format("%Y-%m-%d %H:%M:%S,%3f"),
-                level_string,
-                target,
-                record.args()
-            );
-            */
+
             println!(
-                "{} [{:<5}]: {}",
-                Local::now().format("%Y-%m-%d %H:%M:%S,%3f"),
-                level_string,
-                record.args()
+                "{}",
+                self.format
+                    .replace("{ts}", &Local::now().format(&self.timestamp_format).to_string())
+                    .replace("{level}", &format!("{:<5}", level_string))
+                    .replace("{target}", target)
+                    .replace("{msg}", &record.args().to_string())
             );
         }
     }